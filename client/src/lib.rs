@@ -1,3 +1,6 @@
+pub mod local;
+pub mod replay;
+pub mod view;
 pub mod ws;
 
 use wasm_bindgen::prelude::*;
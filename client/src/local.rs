@@ -0,0 +1,152 @@
+//! Offline practice game against bots.
+//!
+//! Whole game runs in the browser without any server round trip.
+//! It emits the same callbacks as `ws::room_user::User`, so the same rendering code can be used.
+//!
+//! # For js callback
+//!
+//! - `room_info`: when the game is (re)started or ended.
+//! - `game_state`: when the state of the game is changed by the user or bots.
+//! - `game_view`: `GameView` of the user, along with every `game_state`.
+
+use crate::prelude::*;
+use crate::view::GameView;
+use mighty::bot::waiting_users;
+use mighty::prelude::{Agent, BasicAgent, Command, Game, Preset, Rule};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use types::{RoomId, RoomInfo, RoomUid, RuleHash, UserNo};
+
+/// Seat of the user in the local game.
+const USER_SEAT: usize = 0;
+
+#[wasm_bindgen]
+pub struct LocalGame {
+    user_no: UserNo,
+    rule: Rule,
+    seed: u32,
+    game: Game,
+    rng: StdRng,
+    bots: Vec<BasicAgent>,
+    callback: HashMap<String, Function>,
+    unsent_msg: HashMap<String, Vec<JsValue>>,
+}
+
+#[wasm_bindgen]
+impl LocalGame {
+    /// Makes new game of `preset` with the user and bots.
+    /// Same `seed` always deals the same cards and bots play in the same way.
+    #[wasm_bindgen(constructor)]
+    pub fn new(preset: &JsValue, seed: u32, user_no: u32) -> Result<LocalGame> {
+        let preset: Preset = preset.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let rule = Rule::from(preset);
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        let game = Game::new_with_rng(rule.clone(), &mut rng);
+        let bots = vec![BasicAgent; rule.user_cnt as usize];
+
+        let mut local = LocalGame {
+            user_no: UserNo(user_no),
+            rule,
+            seed,
+            game,
+            rng,
+            bots,
+            callback: HashMap::new(),
+            unsent_msg: HashMap::new(),
+        };
+        local.started();
+        Ok(local)
+    }
+
+    pub fn on(&mut self, tag: String, callback: Function) {
+        if let Some(msg_list) = self.unsent_msg.remove(&tag) {
+            for msg in msg_list.iter() {
+                let _ = callback.call1(&JsValue::null(), msg);
+            }
+        }
+        self.callback.insert(tag, callback);
+    }
+
+    pub fn command(&mut self, cmd: &JsValue) -> Result<()> {
+        let cmd: Command = cmd.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.game
+            .next_with_rng(USER_SEAT, cmd, &mut self.rng)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.emit_state();
+        self.play_bots();
+        Ok(())
+    }
+
+    /// Starts new game with the same rule.
+    pub fn restart(&mut self, seed: u32) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed as u64);
+        self.game = Game::new_with_rng(self.rule.clone(), &mut self.rng);
+        self.started();
+    }
+}
+
+impl LocalGame {
+    fn started(&mut self) {
+        self.emit("room_info", JsValue::from_serde(&self.room_info()).unwrap());
+        self.emit_state();
+        self.play_bots();
+    }
+
+    /// Lets bots play until the user should act or the game is finished.
+    fn play_bots(&mut self) {
+        loop {
+            let waiting = waiting_users(&self.game.state, &self.game.rule) & !(1 << USER_SEAT);
+            if waiting == 0 {
+                break;
+            }
+            let seat = (0..self.bots.len()).find(|i| waiting & (1 << i) > 0).unwrap();
+            let cmd = self.bots[seat].decide(seat, &self.game.state, &self.game.rule);
+            if self.game.next_with_rng(seat, cmd, &mut self.rng).is_err()
+                && self.game.next_with_rng(seat, Command::Random, &mut self.rng).is_err()
+            {
+                break;
+            }
+            self.emit_state();
+        }
+
+        if self.game.is_finished() {
+            self.emit("room_info", JsValue::from_serde(&self.room_info()).unwrap());
+        }
+    }
+
+    fn emit_state(&mut self) {
+        let view = GameView::new(&self.game.state, &self.game.rule, USER_SEAT);
+        self.emit("game_state", JsValue::from_serde(&self.game.state).unwrap());
+        self.emit("game_view", JsValue::from(view));
+    }
+
+    fn room_info(&self) -> RoomInfo {
+        let user_cnt = self.rule.user_cnt as usize;
+        let mut user = vec![UserNo(0); user_cnt];
+        user[USER_SEAT] = self.user_no;
+        RoomInfo {
+            head: self.user_no,
+            user,
+            is_game: !self.game.is_finished(),
+            ghost: ((1u16 << user_cnt) - 1) as u8 & !(1 << USER_SEAT),
+            ..RoomInfo::new(
+                RoomUid::generate(format!("local-{}", self.seed)),
+                RoomId(0),
+                "practice".to_owned(),
+                RuleHash::generate(&self.rule),
+                false,
+                user_cnt,
+            )
+        }
+    }
+
+    fn emit(&mut self, tag: &str, val: JsValue) {
+        if let Some(func) = self.callback.get(tag) {
+            let _ = func.call1(&JsValue::null(), &val);
+        } else {
+            self.unsent_msg.entry(tag.to_owned()).or_default().push(val);
+        }
+    }
+}
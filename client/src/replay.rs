@@ -0,0 +1,252 @@
+//! Replay of a finished game.
+//!
+//! `Replay` is made from the json of `/api/replay/{game_id}` and moves through the saved states.
+//! Autoplay is driven by js: call `tick` on every interval while `playing` is true.
+//!
+//! # For js callback
+//!
+//! - `game_state`: when the current state is changed.
+//! - `game_view`: `GameView` of the selected seat, along with every `game_state`.
+
+use crate::prelude::*;
+use crate::view::GameView;
+use mighty::prelude::{Rule, State};
+use serde::Deserialize;
+use std::collections::HashMap;
+use types::UserNo;
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReplayData {
+    game_id: String,
+    users: Vec<UserNo>,
+    rule: Rule,
+    states: Vec<State>,
+}
+
+#[wasm_bindgen]
+pub struct Replay {
+    game_id: String,
+    users: Vec<UserNo>,
+    rule: Rule,
+    states: Vec<State>,
+    pos: usize,
+    seat: usize,
+    playing: bool,
+    callback: HashMap<String, Function>,
+}
+
+#[wasm_bindgen]
+impl Replay {
+    /// Makes the replay starting from the move `start`.
+    /// Fails if the replay has no state.
+    #[wasm_bindgen(constructor)]
+    pub fn from_js(replay: &JsValue, start: usize) -> Result<Replay> {
+        let data: ReplayData = replay.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Replay::new(data, start).ok_or_else(|| JsValue::from_str("replay has no state"))
+    }
+
+    pub fn on(&mut self, tag: String, callback: Function) {
+        self.callback.insert(tag, callback);
+        self.emit_state();
+    }
+
+    /// Count of the states in the replay.
+    #[wasm_bindgen(getter)]
+    pub fn move_count(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Index of the current state.
+    #[wasm_bindgen(getter)]
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn users(&self) -> Vec<u32> {
+        self.users.iter().map(|x| x.0).collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn state(&self) -> JsValue {
+        JsValue::from_serde(&self.states[self.pos]).unwrap()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rule(&self) -> JsValue {
+        JsValue::from_serde(&self.rule).unwrap()
+    }
+
+    /// Count of tricks finished at the current state.
+    #[wasm_bindgen(getter)]
+    pub fn trick(&self) -> u8 {
+        self.states[self.pos].trick_count()
+    }
+
+    /// Shows the replay from `seat`; only changes the order of the hand and `is_my_turn`.
+    pub fn set_seat(&mut self, seat: usize) {
+        self.seat = seat;
+        self.emit_state();
+    }
+
+    pub fn forward(&mut self) -> bool {
+        self.jump(self.pos + 1)
+    }
+
+    pub fn backward(&mut self) -> bool {
+        self.pos > 0 && self.jump(self.pos - 1)
+    }
+
+    /// Moves to the state `pos`. Returns `false` if it is out of range.
+    pub fn jump(&mut self, pos: usize) -> bool {
+        if pos >= self.states.len() {
+            return false;
+        }
+        self.pos = pos;
+        self.emit_state();
+        true
+    }
+
+    /// Moves to the first card of the `trick`th trick starting from 0.
+    pub fn jump_to_trick(&mut self, trick: u8) -> bool {
+        match self.trick_start(trick) {
+            Some(pos) => self.jump(pos),
+            None => false,
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = self.pos + 1 < self.states.len();
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Moves to the next state while playing, and stops at the end.
+    pub fn tick(&mut self) {
+        if self.playing && !self.forward() {
+            self.playing = false;
+        }
+        if self.pos + 1 == self.states.len() {
+            self.playing = false;
+        }
+    }
+
+    /// Link of the replay starting from the current state.
+    pub fn share_link(&self) -> Result<String> {
+        let origin = window()?.location().origin()?;
+        Ok(format!("{}{}", origin, self.path()))
+    }
+}
+
+impl Replay {
+    /// Returns `None` if there is no state, since the replay always shows a state.
+    fn new(data: ReplayData, start: usize) -> Option<Replay> {
+        if data.states.is_empty() {
+            return None;
+        }
+        let pos = start.min(data.states.len() - 1);
+        Some(Replay {
+            game_id: data.game_id,
+            users: data.users,
+            rule: data.rule,
+            states: data.states,
+            pos,
+            seat: 0,
+            playing: false,
+            callback: HashMap::new(),
+        })
+    }
+
+    /// First state in game with `trick` tricks finished
+    fn trick_start(&self, trick: u8) -> Option<usize> {
+        self.states.iter().position(|state| {
+            matches!(state, State::InGame { .. } | State::GameEnded { .. }) && state.trick_count() >= trick
+        })
+    }
+
+    fn path(&self) -> String {
+        format!("/replay/{}?move={}", self.game_id, self.pos)
+    }
+
+    fn emit_state(&self) {
+        let state = &self.states[self.pos];
+        if let Some(func) = self.callback.get("game_state") {
+            let _ = func.call1(&JsValue::null(), &JsValue::from_serde(state).unwrap());
+        }
+        if let Some(func) = self.callback.get("game_view") {
+            let view = GameView::new(state, &self.rule, self.seat);
+            let _ = func.call1(&JsValue::null(), &JsValue::from(view));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::bot::waiting_users;
+    use mighty::prelude::{Agent, BasicAgent, Game, Preset};
+
+    fn replay() -> Replay {
+        let rule = Rule::from(Preset::Default5);
+        let mut game = Game::new(rule.clone());
+        let mut states = vec![game.get_state()];
+        while !game.is_finished() {
+            let waiting = waiting_users(&game.state, &game.rule);
+            let seat = (0..5).find(|i| waiting & (1 << i) > 0).unwrap();
+            let cmd = BasicAgent.decide(seat, &game.state, &game.rule);
+            game.next(seat, cmd).unwrap();
+            states.push(game.get_state());
+        }
+        let data = ReplayData {
+            game_id: "test".to_owned(),
+            users: vec![UserNo(0); 5],
+            rule,
+            states,
+        };
+        Replay::new(data, 0).unwrap()
+    }
+
+    #[test]
+    fn empty_test() {
+        let data = ReplayData {
+            game_id: "test".to_owned(),
+            users: Vec::new(),
+            rule: Rule::from(Preset::Default5),
+            states: Vec::new(),
+        };
+        assert!(Replay::new(data, 0).is_none());
+    }
+
+    #[test]
+    fn jump_test() {
+        let mut replay = replay();
+        assert!(!replay.backward());
+        assert!(replay.forward());
+        assert_eq!(replay.pos, 1);
+        assert!(!replay.jump(replay.move_count()));
+        assert_eq!(replay.pos, 1);
+
+        assert!(replay.jump_to_trick(3));
+        assert_eq!(replay.trick(), 3);
+        assert!(replay.states[replay.pos - 1].trick_count() < 3);
+        assert!(replay.path().ends_with(&format!("?move={}", replay.pos)));
+        assert!(!replay.jump_to_trick(11));
+    }
+
+    #[test]
+    fn autoplay_test() {
+        let mut replay = replay();
+        replay.play();
+        while replay.playing {
+            replay.tick();
+        }
+        assert_eq!(replay.pos, replay.move_count() - 1);
+    }
+}
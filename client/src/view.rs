@@ -0,0 +1,277 @@
+//! View of the game from one seat.
+//!
+//! `GameView` is built from each game state, so the js side doesn't need to know
+//! about the rules to sort the hand, find whose turn it is, or count the points.
+
+use crate::prelude::*;
+use mighty::bot::{valid_commands, waiting_users};
+use mighty::prelude::{Card, Color, Command, Pattern, Rule, State};
+use serde::Serialize;
+use std::cmp::Reverse;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &'static str = r#"
+export type PatternName = "s" | "d" | "h" | "c";
+export type ColorName = "b" | "r";
+export type Card = [PatternName, number] | ColorName;
+export type Rush = { bits: number };
+export type FriendFunc = "None" | "First" | "Last" | { ByCard: Card } | { ByUser: number };
+export type Command =
+    | { Pledge: [PatternName | null, number] | null }
+    | { SelectFriend: [Card[], FriendFunc] }
+    | { ChangePledge: PatternName | null }
+    | { Go: [Card, Rush, boolean] }
+    | "Random";
+export type Phase = "election" | "select_friend" | "in_game" | "ended";
+export interface TrickCard {
+    seat: number;
+    card: Card;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "Card[]")]
+    pub type CardList;
+
+    #[wasm_bindgen(typescript_type = "TrickCard[]")]
+    pub type TrickCardList;
+
+    #[wasm_bindgen(typescript_type = "Command[]")]
+    pub type CommandList;
+
+    #[wasm_bindgen(typescript_type = "PatternName | null")]
+    pub type PatternOrNull;
+
+    #[wasm_bindgen(typescript_type = "Phase")]
+    pub type Phase;
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct TrickCard {
+    seat: usize,
+    card: Card,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct GameView {
+    seat: usize,
+    phase: &'static str,
+    hand: Vec<Card>,
+    turn: Option<usize>,
+    is_my_turn: bool,
+    trick: Vec<TrickCard>,
+    president: Option<usize>,
+    friend: Option<usize>,
+    giruda: Option<Pattern>,
+    pledge: Option<u8>,
+    points: Vec<u8>,
+    actions: Vec<Command>,
+}
+
+#[wasm_bindgen]
+impl GameView {
+    #[wasm_bindgen(constructor)]
+    pub fn from_js(state: &JsValue, rule: &JsValue, seat: usize) -> Result<GameView> {
+        let state: State = state.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let rule: Rule = rule.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(GameView::new(&state, &rule, seat))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn seat(&self) -> usize {
+        self.seat
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn phase(&self) -> Phase {
+        JsValue::from_str(self.phase).unchecked_into()
+    }
+
+    /// Cards of this seat; jokers first, then giruda, then the others in `pattern_order`.
+    #[wasm_bindgen(getter)]
+    pub fn hand(&self) -> CardList {
+        JsValue::from_serde(&self.hand).unwrap().unchecked_into()
+    }
+
+    /// Seat who should act next, or `undefined` if many seats can act at once.
+    #[wasm_bindgen(getter)]
+    pub fn turn(&self) -> Option<usize> {
+        self.turn
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn is_my_turn(&self) -> bool {
+        self.is_my_turn
+    }
+
+    /// Cards placed in the current trick in the order they were placed.
+    #[wasm_bindgen(getter)]
+    pub fn trick(&self) -> TrickCardList {
+        JsValue::from_serde(&self.trick).unwrap().unchecked_into()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn president(&self) -> Option<usize> {
+        self.president
+    }
+
+    /// Friend as known to this seat.
+    #[wasm_bindgen(getter)]
+    pub fn friend(&self) -> Option<usize> {
+        self.friend
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn giruda(&self) -> PatternOrNull {
+        JsValue::from_serde(&self.giruda).unwrap().unchecked_into()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pledge(&self) -> Option<u8> {
+        self.pledge
+    }
+
+    /// Count of point cards each seat has taken.
+    #[wasm_bindgen(getter)]
+    pub fn points(&self) -> Vec<u8> {
+        self.points.clone()
+    }
+
+    /// Commands this seat can send now.
+    /// While selecting friend, only the change of pledge is listed.
+    #[wasm_bindgen(getter)]
+    pub fn actions(&self) -> CommandList {
+        JsValue::from_serde(&self.actions).unwrap().unchecked_into()
+    }
+}
+
+impl GameView {
+    pub fn new(state: &State, rule: &Rule, seat: usize) -> GameView {
+        let user_cnt = rule.user_cnt as usize;
+        let valid = state.valid_users(rule);
+        let turn = if valid.count_ones() == 1 {
+            Some(valid.trailing_zeros() as usize)
+        } else {
+            None
+        };
+        let is_my_turn = waiting_users(state, rule) & (1 << seat) > 0;
+        let actions = if is_my_turn {
+            valid_commands(state, seat, rule)
+        } else {
+            Vec::new()
+        };
+
+        let mut view = GameView {
+            seat,
+            phase: "election",
+            hand: Vec::new(),
+            turn,
+            is_my_turn,
+            trick: Vec::new(),
+            president: None,
+            friend: None,
+            giruda: None,
+            pledge: None,
+            points: vec![0; user_cnt],
+            actions,
+        };
+
+        match state {
+            State::Election { deck, .. } => {
+                view.hand = deck[seat].clone();
+            }
+            State::SelectFriend {
+                president,
+                giruda,
+                pledge,
+                deck,
+            } => {
+                view.phase = "select_friend";
+                view.hand = deck[seat].clone();
+                view.president = Some(*president);
+                view.giruda = *giruda;
+                view.pledge = Some(*pledge);
+            }
+            State::InGame {
+                president,
+                friend,
+                is_friend_known,
+                giruda,
+                pledge,
+                deck,
+                score_deck,
+                placed_cards,
+                start_user,
+                current_user,
+                ..
+            } => {
+                view.phase = "in_game";
+                view.hand = deck[seat].clone();
+                view.president = Some(*president);
+                view.friend = friend.filter(|f| *is_friend_known || *f == seat);
+                view.giruda = *giruda;
+                view.pledge = Some(*pledge);
+                view.points = score_deck.iter().map(|d| d.len() as u8).collect();
+                view.trick = (0..user_cnt)
+                    .map(|i| (start_user + i) % user_cnt)
+                    .take_while(|i| i != current_user)
+                    .map(|i| TrickCard {
+                        seat: i,
+                        card: placed_cards[i].0,
+                    })
+                    .collect();
+            }
+            State::GameEnded {
+                president,
+                friend,
+                pledge,
+                giruda,
+                ..
+            } => {
+                view.phase = "ended";
+                view.president = Some(*president);
+                view.friend = *friend;
+                view.giruda = *giruda;
+                view.pledge = Some(*pledge);
+            }
+        }
+
+        sort_hand(&mut view.hand, view.giruda, rule);
+        view
+    }
+}
+
+fn sort_hand(hand: &mut [Card], giruda: Option<Pattern>, rule: &Rule) {
+    let pattern_idx = |p: Pattern| rule.pattern_order.iter().position(|x| *x == p).unwrap_or(4);
+    hand.sort_by_key(|c| match c {
+        Card::Joker(Color::Black) => (0, 0, Reverse(0)),
+        Card::Joker(Color::Red) => (0, 1, Reverse(0)),
+        Card::Normal(p, n) if Some(*p) == giruda => (1, 0, Reverse(*n)),
+        Card::Normal(p, n) => (2, pattern_idx(*p), Reverse(*n)),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::prelude::Preset;
+
+    #[test]
+    fn sort_hand_test() {
+        let rule = Rule::from(Preset::Default5);
+        let mut hand = vec![
+            Card::Normal(Pattern::Heart, 3),
+            Card::Normal(Pattern::Spade, 14),
+            Card::Joker(Color::Red),
+            Card::Normal(Pattern::Clover, 10),
+            Card::Normal(Pattern::Heart, 12),
+        ];
+        sort_hand(&mut hand, Some(Pattern::Heart), &rule);
+        assert_eq!(hand[0], Card::Joker(Color::Red));
+        assert_eq!(hand[1], Card::Normal(Pattern::Heart, 12));
+        assert_eq!(hand[2], Card::Normal(Pattern::Heart, 3));
+    }
+}
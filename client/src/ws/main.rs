@@ -1,7 +1,8 @@
 use crate::prelude::*;
 use crate::ws::session::{Context, Session, SessionTrait};
+use mighty::prelude::{Preset, Rule};
 use serde::Serialize;
-use types::{MainToClient, MainToServer, UserNo, UserStatus};
+use types::{MainToClient, MainToServer, RoomId, RoomKey, UserNo, UserStatus};
 
 pub struct MainSession;
 
@@ -25,6 +26,10 @@ impl SessionTrait for MainSession {
                 ("user_status", JsValue::from_serde(&Status { no, status }).unwrap())
             }
             MainToClient::UserInfo(info) => ("user_info", JsValue::from_serde(&info).unwrap()),
+            MainToClient::Joined(info) => ("joined", JsValue::from_serde(&info).unwrap()),
+            MainToClient::Queued(status) => ("queued", JsValue::from_serde(&status).unwrap()),
+            MainToClient::QueueCancelled(reason) => ("queue_cancelled", JsValue::from_str(&reason)),
+            MainToClient::Matched(room_id) => ("matched", JsValue::from_serde(&room_id).unwrap()),
         }
     }
 }
@@ -58,4 +63,40 @@ impl Main {
     pub fn unsubscribe(&self, user_no: UserNo) {
         self.session.send(MainToServer::Unsubscribe(user_no));
     }
+
+    pub fn get_info(&self) {
+        self.session.send(MainToServer::GetInfo);
+    }
+
+    pub fn join(&self, room_id: RoomId) {
+        self.session.send(MainToServer::Join(room_id));
+    }
+
+    pub fn join_with(&self, room_id: RoomId, key: &JsValue) -> Result<()> {
+        let key: RoomKey = key.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.session.send(MainToServer::JoinWith(room_id, key));
+        Ok(())
+    }
+
+    pub fn make_room(&self, name: String, rule: &JsValue, is_rank: bool) -> Result<()> {
+        let rule: Rule = rule.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.session.send(MainToServer::MakeRoom(name, Box::new(rule), is_rank));
+        Ok(())
+    }
+
+    pub fn leave(&self) {
+        self.session.send(MainToServer::Leave);
+    }
+
+    /// Waits for a ranked game of `preset`; `range` is `[low, high]` of the opponents' rating or `null`.
+    pub fn queue(&self, preset: &JsValue, range: &JsValue) -> Result<()> {
+        let preset: Preset = preset.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let range: Option<(u32, u32)> = range.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.session.send(MainToServer::Queue(preset, range));
+        Ok(())
+    }
+
+    pub fn cancel_queue(&self) {
+        self.session.send(MainToServer::CancelQueue);
+    }
 }
@@ -15,8 +15,14 @@ impl SessionTrait for ObserveSession {
         let msg: ObserveToClient = serde_json::from_str(&*msg).unwrap();
         match msg {
             ObserveToClient::Room(info) => ("room_info", JsValue::from_serde(&info).unwrap()),
+            ObserveToClient::Rule(rule) => ("rule", JsValue::from_serde(&rule).unwrap()),
             ObserveToClient::Game(state) => ("game_state", JsValue::from_serde(&state).unwrap()),
+            ObserveToClient::Clock(clock) => ("clock", JsValue::from_serde(&clock).unwrap()),
             ObserveToClient::Chat(chat, no) => ("chat", JsValue::from_serde(&(chat, no)).unwrap()),
+            ObserveToClient::ChatLog(chat) => ("chat_log", JsValue::from_serde(&chat).unwrap()),
+            ObserveToClient::Reveal(states) => ("reveal", JsValue::from_serde(&states).unwrap()),
+            ObserveToClient::Summary(summary) => ("summary", JsValue::from_serde(&summary).unwrap()),
+            ObserveToClient::Maintenance => ("maintenance", JsValue::null()),
         }
     }
 }
@@ -38,4 +44,8 @@ impl Observe {
     pub fn on(&self, tag: String, callback: Function) {
         self.session.on(tag, callback);
     }
+
+    pub fn resync(&self) {
+        self.session.send(ObserveToServer::Resync);
+    }
 }
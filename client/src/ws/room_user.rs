@@ -1,8 +1,22 @@
 use crate::prelude::*;
+use crate::view::GameView;
 use crate::ws::session::{Context, Session, SessionTrait};
-use types::{RoomUserToClient, RoomUserToServer};
+use mighty::prelude::Rule;
+use types::{RoomInfo, RoomUserToClient, RoomUserToServer, UserNo, VoteKind, VoteRule};
 
-pub struct UserSession;
+pub struct UserSession {
+    user_no: Option<UserNo>,
+    info: Option<RoomInfo>,
+    rule: Option<Rule>,
+}
+
+impl UserSession {
+    /// Seat of this user if the user is in the room.
+    fn seat(&self) -> Option<usize> {
+        let user_no = self.user_no?;
+        self.info.as_ref()?.user.iter().position(|x| *x == user_no)
+    }
+}
 
 impl SessionTrait for UserSession {
     type Sender = RoomUserToServer;
@@ -11,12 +25,48 @@ impl SessionTrait for UserSession {
         "room"
     }
 
-    fn receive(&mut self, msg: String, _: &Context<Self>) -> (&str, JsValue) {
+    fn reconnected(&mut self, ctx: &Context<Self>) {
+        ctx.handle(RoomUserToServer::Resync);
+    }
+
+    /// Game commands and votes made on the state before disconnection are dropped.
+    fn replay(&self, msg: &str) -> bool {
+        !matches!(
+            serde_json::from_str(msg),
+            Ok(RoomUserToServer::Command(_))
+                | Ok(RoomUserToServer::Propose(_))
+                | Ok(RoomUserToServer::Vote(_))
+                | Ok(RoomUserToServer::Resync)
+        )
+    }
+
+    fn receive(&mut self, msg: String, ctx: &Context<Self>) -> (&str, JsValue) {
         let msg: RoomUserToClient = serde_json::from_str(&*msg).unwrap();
         match msg {
-            RoomUserToClient::Room(info) => ("room_info", JsValue::from_serde(&info).unwrap()),
-            RoomUserToClient::Game(state) => ("game_state", JsValue::from_serde(&state).unwrap()),
+            RoomUserToClient::Room(info) => {
+                let val = JsValue::from_serde(&info).unwrap();
+                self.info = Some(info);
+                ("room_info", val)
+            }
+            RoomUserToClient::Rule(rule) => {
+                let val = JsValue::from_serde(&rule).unwrap();
+                self.rule = Some(rule);
+                ("rule", val)
+            }
+            RoomUserToClient::Game(state) => {
+                if let (Some(seat), Some(rule)) = (self.seat(), &self.rule) {
+                    ctx.emit("game_view", JsValue::from(GameView::new(&state, rule, seat)));
+                }
+                ("game_state", JsValue::from_serde(&state).unwrap())
+            }
+            RoomUserToClient::Clock(clock) => ("clock", JsValue::from_serde(&clock).unwrap()),
             RoomUserToClient::Chat(chat, no) => ("chat", JsValue::from_serde(&(chat, no)).unwrap()),
+            RoomUserToClient::ChatLog(chat) => ("chat_log", JsValue::from_serde(&chat).unwrap()),
+            RoomUserToClient::Invite(token) => ("invite", JsValue::from_str(&token)),
+            RoomUserToClient::Kicked => ("kicked", JsValue::null()),
+            RoomUserToClient::Reveal(states) => ("reveal", JsValue::from_serde(&states).unwrap()),
+            RoomUserToClient::Summary(summary) => ("summary", JsValue::from_serde(&summary).unwrap()),
+            RoomUserToClient::Maintenance => ("maintenance", JsValue::null()),
         }
     }
 }
@@ -28,10 +78,17 @@ pub struct User {
 
 #[wasm_bindgen]
 impl User {
+    /// `user_no` is optional, so `new User()` still works as before;
+    /// it is needed to get `game_view` callback.
     #[wasm_bindgen(constructor)]
-    pub fn new() -> Result<User> {
+    pub fn new(user_no: Option<u32>) -> Result<User> {
         Ok(User {
-            session: UserSession.start()?,
+            session: UserSession {
+                user_no: user_no.map(UserNo),
+                info: None,
+                rule: None,
+            }
+            .start()?,
         })
     }
 
@@ -55,4 +112,72 @@ impl User {
     pub fn command(&self, cmd: &JsValue) {
         self.session.send(RoomUserToServer::Command(cmd.into_serde().unwrap()))
     }
+
+    pub fn resync(&self) {
+        self.session.send(RoomUserToServer::Resync);
+    }
+
+    pub fn sit(&self, seat: usize) {
+        self.session.send(RoomUserToServer::Sit(seat));
+    }
+
+    pub fn stand(&self) {
+        self.session.send(RoomUserToServer::Stand);
+    }
+
+    pub fn swap(&self, seat: usize) {
+        self.session.send(RoomUserToServer::Swap(seat));
+    }
+
+    pub fn lock(&self, seat: usize, lock: bool) {
+        self.session.send(RoomUserToServer::Lock(seat, lock));
+    }
+
+    pub fn ready(&self, ready: bool) {
+        self.session.send(RoomUserToServer::Ready(ready));
+    }
+
+    pub fn set_auto_start(&self, auto_start: bool) {
+        self.session.send(RoomUserToServer::SetAutoStart(auto_start));
+    }
+
+    pub fn set_bot_fill(&self, bot_fill: bool) {
+        self.session.send(RoomUserToServer::SetBotFill(bot_fill));
+    }
+
+    pub fn kick(&self, user_no: u32) {
+        self.session.send(RoomUserToServer::Kick(UserNo(user_no)));
+    }
+
+    pub fn transfer_head(&self, user_no: u32) {
+        self.session.send(RoomUserToServer::TransferHead(UserNo(user_no)));
+    }
+
+    pub fn set_password(&self, password: Option<String>) {
+        self.session.send(RoomUserToServer::SetPassword(password));
+    }
+
+    pub fn set_invite_only(&self, invite_only: bool) {
+        self.session.send(RoomUserToServer::SetInviteOnly(invite_only));
+    }
+
+    pub fn invite(&self) {
+        self.session.send(RoomUserToServer::Invite);
+    }
+
+    pub fn propose(&self, kind: &JsValue) -> Result<()> {
+        let kind: VoteKind = kind.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.session.send(RoomUserToServer::Propose(kind));
+        Ok(())
+    }
+
+    pub fn vote(&self, yes: bool) {
+        self.session.send(RoomUserToServer::Vote(yes));
+    }
+
+    pub fn set_vote_rule(&self, rule: &JsValue) -> Result<()> {
+        let rule: VoteRule = rule.into_serde().map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.session.send(RoomUserToServer::SetVoteRule(rule));
+        Ok(())
+    }
 }
@@ -1,5 +1,12 @@
 //! Actor implement in small size of code with convenient API.
 //!
+//! Session keeps the connection to the server. When the connection is lost,
+//! it reconnects with exponential backoff and jitter (see `SessionPolicy`),
+//! and the messages sent while offline are replayed in order after reconnection.
+//! When the server closes the connection normally, the session stops instead of reconnecting.
+//! On reconnection, `reconnected` is called before the replay so that the session can resync first,
+//! and `replay` can drop the messages which are not valid anymore.
+//!
 //! # Example
 //!
 //! ```
@@ -36,36 +43,49 @@
 //!
 //! # For js callback
 //!
-//! - `start`: when websocket is connected for the first time.
+//! - `start`: when the session is started.
 //! - `reconnect`: when websocket is reconnected due to disconnection for some reason.
 //! - `disconnect`: when websocket is disconnected for any reason except stopping connection.
-//! - `stop`: when websocket connection is totally stopped.
+//! - `stop`: when websocket connection is totally stopped, by `stop`, by the server or after too many retries.
 
 use crate::prelude::*;
+use config::Config;
 use js_sys::JsString;
-use serde::Serialize;
-use std::collections::HashMap;
-use std::sync::mpsc::{channel, Sender};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
-use web_sys::{MessageEvent, WebSocket};
+use web_sys::{CloseEvent, MessageEvent, WebSocket};
 
 /// Trait to be implemented to use `Session`
 ///
 /// This trait helps you to use websocket in wasm. This trait is based on actor model,
 /// so it can handle on message at a time.
-pub trait SessionTrait: Sized + Send + 'static {
-    type Sender: Serialize + Send + 'static;
+pub trait SessionTrait: Sized + 'static {
+    type Sender: Serialize + 'static;
 
     /// It will connect websocket to `/ws/{tag}`
     fn tag() -> &'static str;
 
+    /// Policy for reconnection and buffering.
+    fn policy() -> SessionPolicy {
+        SessionPolicy::default()
+    }
+
     /// This function will be called when the actor is started.
     fn started(&mut self, _: &Context<Self>) {}
 
     /// This function will be called when the connection is reopened.
+    /// Messages sent in this function go before the messages buffered while offline.
     fn reconnected(&mut self, _: &Context<Self>) {}
 
+    /// If the message buffered while offline should still be sent after reconnection.
+    fn replay(&self, _: &str) -> bool {
+        true
+    }
+
     /// This function should not be overridden.
     /// This function starts the Session.
     fn start(self) -> Result<Session<Self>> {
@@ -96,30 +116,279 @@ pub trait SessionTrait: Sized + Send + 'static {
     fn receive(&mut self, msg: String, _: &Context<Self>) -> (&str, JsValue);
 }
 
-struct Wrap<T> {
-    inner: T,
+/// Policy of reconnection and buffering
+///
+/// - `initial_delay`: delay before the first retry in milliseconds
+/// - `max_delay`: upper bound of the delay in milliseconds
+/// - `multiplier`: delay is multiplied by this for every retry in a row
+/// - `jitter`: ratio of random variation of the delay (0.0 to 1.0)
+/// - `max_retry`: session stops after this many retries in a row; `None` for retrying forever
+/// - `max_queue`: max count of messages buffered while offline; the oldest one is dropped first
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Config)]
+#[serde(default)]
+pub struct SessionPolicy {
+    pub initial_delay: u32,
+    pub max_delay: u32,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub max_retry: Option<u32>,
+    pub max_queue: usize,
 }
 
-unsafe impl<T> Send for Wrap<T> {}
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionPolicy {
+    pub fn new() -> SessionPolicy {
+        SessionPolicy {
+            initial_delay: 500,
+            max_delay: 8000,
+            multiplier: 2.0,
+            jitter: 0.3,
+            max_retry: Some(10),
+            max_queue: 256,
+        }
+    }
+
+    /// Delay in milliseconds before `retry`-th retry (starting from 0).
+    /// `random` should be in `[0, 1)`.
+    pub fn delay(&self, retry: u32, random: f64) -> u32 {
+        let base = (self.initial_delay as f64 * self.multiplier.powi(retry as i32)).min(self.max_delay as f64);
+        (base * (1.0 + self.jitter * (2.0 * random - 1.0))).max(0.0) as u32
+    }
+}
+
+/// Close code of the connection closed normally by the server
+pub const NORMAL_CLOSURE: u16 = 1000;
+
+/// Event from the connection
+///
+/// - `Close`: with the close code
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketEvent {
+    Open,
+    Message(String),
+    Error,
+    Close(u16),
+}
+
+/// Connection to the server
+pub trait Socket {
+    /// Returns `false` if the message couldn't be sent.
+    fn send(&self, msg: &str) -> bool;
+
+    fn close(&self);
+}
+
+/// Everything the session needs from outside.
+///
+/// `WebEnv` is used in the browser. It can be replaced to test the session without browser.
+pub trait Env {
+    /// Opens new connection to `url`.
+    /// Every event of the connection should be passed to `on_event`.
+    fn connect(&mut self, url: &str, on_event: Rc<dyn Fn(SocketEvent)>) -> Result<Box<dyn Socket>>;
+
+    /// Calls `callback` after `ms` milliseconds.
+    fn set_timeout(&mut self, ms: u32, callback: Box<dyn FnOnce()>);
+
+    /// Calls js callback of `tag`.
+    fn call(&mut self, tag: &str, val: JsValue);
+
+    fn set_callback(&mut self, tag: String, func: Function);
+
+    /// Random number in `[0, 1)` for jitter.
+    fn random(&mut self) -> f64 {
+        rand::random()
+    }
+}
 
 enum Message<T>
 where
     T: SessionTrait,
 {
-    ReStarted,
+    // number of connection, event
+    Socket(u32, SocketEvent),
+    Retry(u32),
     Send(String),
     Handle(T::Sender),
-    Receive(String),
-    SetCallback(String, Wrap<Function>),
+    Emit(String, JsValue),
+    SetCallback(String, Function),
     Reconnect,
     Stop,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Connecting,
+    Open,
+    Waiting,
+    Stopped,
+}
+
+struct Core<T>
+where
+    T: SessionTrait,
+{
+    inner: T,
+    env: Box<dyn Env>,
+    url: String,
+    policy: SessionPolicy,
+    socket: Option<Box<dyn Socket>>,
+    status: Status,
+    // increased for every connection so that events from old ones are ignored
+    conn_no: u32,
+    retry: u32,
+    is_opened: bool,
+    queue: VecDeque<String>,
+}
+
+impl<T> Core<T>
+where
+    T: SessionTrait,
+{
+    fn process(&mut self, msg: Message<T>, ctx: &Context<T>) {
+        match msg {
+            Message::Socket(no, event) if no == self.conn_no => match event {
+                SocketEvent::Open => self.opened(ctx),
+                SocketEvent::Message(msg) => {
+                    let (tag, val) = self.inner.receive(msg, ctx);
+                    self.env.call(tag, val);
+                }
+                SocketEvent::Close(NORMAL_CLOSURE) => self.stop(ctx),
+                SocketEvent::Error | SocketEvent::Close(_) => self.disconnect(ctx),
+            },
+            Message::Retry(no) if no == self.conn_no && self.status == Status::Waiting => self.connect(ctx),
+            Message::Socket(..) | Message::Retry(..) => {}
+            Message::Send(msg) => self.send(msg, ctx),
+            Message::Handle(msg) => self.inner.handle(msg, ctx),
+            Message::Emit(tag, val) => self.env.call(&tag, val),
+            Message::SetCallback(tag, func) => self.env.set_callback(tag, func),
+            Message::Reconnect => self.disconnect(ctx),
+            Message::Stop => self.stop(ctx),
+        }
+    }
+
+    fn connect(&mut self, ctx: &Context<T>) {
+        self.conn_no += 1;
+        self.status = Status::Connecting;
+
+        let no = self.conn_no;
+        let ctx_c = ctx.clone();
+        let on_event = Rc::new(move |e| ctx_c.push(Message::Socket(no, e)));
+        match self.env.connect(&self.url, on_event) {
+            Ok(socket) => self.socket = Some(socket),
+            Err(_) => self.disconnect(ctx),
+        }
+    }
+
+    fn opened(&mut self, ctx: &Context<T>) {
+        self.status = Status::Open;
+        self.retry = 0;
+        let queue = std::mem::take(&mut self.queue);
+
+        let reconnected = self.is_opened;
+        self.is_opened = true;
+        if reconnected {
+            self.inner.reconnected(ctx);
+            self.env.call("reconnect", JsValue::null());
+            // messages from `reconnected` are sent before the replay
+            loop {
+                let msg = ctx.shared.mailbox.borrow_mut().pop_front();
+                match msg {
+                    Some(msg) => self.process(msg, ctx),
+                    None => break,
+                }
+            }
+        }
+
+        for msg in queue {
+            if !reconnected || self.inner.replay(&msg) {
+                self.send(msg, ctx);
+            }
+        }
+    }
+
+    fn send(&mut self, msg: String, ctx: &Context<T>) {
+        match self.status {
+            Status::Stopped => {}
+            Status::Open => {
+                if !matches!(&self.socket, Some(s) if s.send(&msg)) {
+                    self.enqueue(msg);
+                    self.disconnect(ctx);
+                }
+            }
+            Status::Connecting | Status::Waiting => self.enqueue(msg),
+        }
+    }
+
+    fn enqueue(&mut self, msg: String) {
+        if self.policy.max_queue == 0 {
+            return;
+        }
+        if self.queue.len() >= self.policy.max_queue {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(msg);
+    }
+
+    fn disconnect(&mut self, ctx: &Context<T>) {
+        if matches!(self.status, Status::Waiting | Status::Stopped) {
+            return;
+        }
+        let was_open = self.status == Status::Open;
+        self.conn_no += 1;
+        if let Some(socket) = self.socket.take() {
+            socket.close();
+        }
+        if was_open {
+            self.inner.disconnected(ctx);
+            self.env.call("disconnect", JsValue::null());
+        }
+
+        if matches!(self.policy.max_retry, Some(max) if self.retry >= max) {
+            self.stop(ctx);
+            return;
+        }
+
+        self.status = Status::Waiting;
+        let delay = self.policy.delay(self.retry, self.env.random());
+        self.retry += 1;
+        let no = self.conn_no;
+        let ctx = ctx.clone();
+        self.env
+            .set_timeout(delay, Box::new(move || ctx.push(Message::Retry(no))));
+    }
+
+    fn stop(&mut self, ctx: &Context<T>) {
+        if self.status == Status::Stopped {
+            return;
+        }
+        self.status = Status::Stopped;
+        self.conn_no += 1;
+        if let Some(socket) = self.socket.take() {
+            socket.close();
+        }
+        self.inner.stopped(ctx);
+        self.env.call("stop", JsValue::null());
+    }
+}
+
+struct Shared<T>
+where
+    T: SessionTrait,
+{
+    mailbox: RefCell<VecDeque<Message<T>>>,
+    core: RefCell<Core<T>>,
+}
+
 pub struct Context<T>
 where
     T: SessionTrait,
 {
-    sender: Sender<Message<T>>,
+    shared: Rc<Shared<T>>,
 }
 
 impl<T> Clone for Context<T>
@@ -128,7 +397,7 @@ where
 {
     fn clone(&self) -> Self {
         Context {
-            sender: self.sender.clone(),
+            shared: self.shared.clone(),
         }
     }
 }
@@ -137,34 +406,50 @@ impl<T> Context<T>
 where
     T: SessionTrait,
 {
-    pub fn restarted(&self) {
-        let _ = self.sender.send(Message::ReStarted);
+    /// Messages are handled one at a time.
+    /// Messages pushed while handling are handled right after it.
+    fn push(&self, msg: Message<T>) {
+        self.shared.mailbox.borrow_mut().push_back(msg);
+        self.flush();
     }
 
+    fn flush(&self) {
+        if let Ok(mut core) = self.shared.core.try_borrow_mut() {
+            loop {
+                let msg = self.shared.mailbox.borrow_mut().pop_front();
+                match msg {
+                    Some(msg) => core.process(msg, self),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Sends the message to server.
+    /// If the connection is not opened, it is sent after reconnection.
     pub fn send(&self, msg: String) {
-        let _ = self.sender.send(Message::Send(msg));
+        self.push(Message::Send(msg));
     }
 
     pub fn handle(&self, msg: T::Sender) {
-        let _ = self.sender.send(Message::Handle(msg));
+        self.push(Message::Handle(msg));
     }
 
-    pub fn receive(&self, msg: String) {
-        let _ = self.sender.send(Message::Receive(msg));
+    /// Calls the js callback of `tag` besides the one returned from `receive`.
+    pub fn emit<S: AsRef<str>>(&self, tag: S, val: JsValue) {
+        self.push(Message::Emit(tag.as_ref().to_owned(), val));
     }
 
     pub fn set_callback<S: AsRef<str>>(&self, tag: S, func: Function) {
-        let _ = self
-            .sender
-            .send(Message::SetCallback(tag.as_ref().to_owned(), Wrap { inner: func }));
+        self.push(Message::SetCallback(tag.as_ref().to_owned(), func));
     }
 
     pub fn reconnect(&self) {
-        let _ = self.sender.send(Message::Reconnect);
+        self.push(Message::Reconnect);
     }
 
     pub fn stop(&self) {
-        let _ = self.sender.send(Message::Stop);
+        self.push(Message::Stop);
     }
 }
 
@@ -190,7 +475,7 @@ impl<T> Session<T>
 where
     T: SessionTrait,
 {
-    pub fn start(mut inner: T) -> Result<Session<T>> {
+    pub fn start(inner: T) -> Result<Session<T>> {
         let protocol = if window()?.location().protocol()? == "https:" {
             "wss"
         } else {
@@ -199,115 +484,38 @@ where
         let host = window()?.location().host()?;
         let url = format!("{}://{}/ws/{}", protocol, host, T::tag());
 
-        let (sender, receiver) = channel();
-        let context = Context { sender };
-
-        let ctx = context.clone();
-
-        let inner = Closure::wrap(Box::new(move || {
-            let mut callback: HashMap<String, Function> = HashMap::new();
-            let mut unsent_msg: HashMap<String, Vec<JsValue>> = HashMap::new();
-
-            let call = |tag: &str,
-                        val: JsValue,
-                        callback: &mut HashMap<String, Function>,
-                        unsent_msg: &mut HashMap<String, Vec<JsValue>>| {
-                let tag = tag.to_owned();
+        Ok(Session::start_with(inner, url, T::policy(), Box::new(WebEnv::new())))
+    }
 
-                if let Some(func) = callback.get(&tag) {
-                    let _ = func.call1(&JsValue::null(), &val);
-                } else if let Some(msg_list) = unsent_msg.get_mut(&tag) {
-                    msg_list.push(val);
-                } else {
-                    unsent_msg.insert(tag, vec![val]);
-                }
-            };
-
-            inner.started(&ctx);
-            call("start", JsValue::null(), &mut callback, &mut unsent_msg);
-
-            'outer: loop {
-                let ws = WebSocket::new(&*url).unwrap();
-
-                let ctx_c = ctx.clone();
-                let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| {
-                    if let Ok(msg) = e.data().dyn_into::<JsString>() {
-                        ctx_c.receive(String::from(msg));
-                    }
-                }) as Box<dyn FnMut(MessageEvent)>);
-                ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
-                onmessage.forget();
-
-                let ctx_c = ctx.clone();
-                let onerror = Closure::wrap(Box::new(move |_| {
-                    ctx_c.reconnect();
-                }) as Box<dyn FnMut(MessageEvent)>);
-                ws.set_onerror(Some(onerror.as_ref().unchecked_ref()));
-                onerror.forget();
-
-                let ctx_c = ctx.clone();
-                let onclose = Closure::wrap(Box::new(move |_| {
-                    ctx_c.stop();
-                }) as Box<dyn FnMut(MessageEvent)>);
-                ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
-                onclose.forget();
-
-                let ctx_c = ctx.clone();
-                let onopen = Closure::wrap(Box::new(move |_| {
-                    ctx_c.restarted();
-                }) as Box<dyn FnMut(MessageEvent)>);
-                ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
-                onopen.forget();
-
-                'inner: loop {
-                    if let Ok(msg) = receiver.recv() {
-                        match msg {
-                            Message::ReStarted => {
-                                inner.reconnected(&ctx);
-                                call("reconnect", JsValue::null(), &mut callback, &mut unsent_msg);
-                            }
-                            Message::Send(msg) => {
-                                let _ = ws.send_with_str(&*msg);
-                            }
-                            Message::Handle(msg) => {
-                                inner.handle(msg, &ctx);
-                            }
-                            Message::Receive(msg) => {
-                                let (tag, val) = inner.receive(msg, &ctx);
-                                call(tag, val, &mut callback, &mut unsent_msg);
-                            }
-                            Message::SetCallback(tag, func) => {
-                                let func = func.inner;
-                                if let Some(msg_list) = unsent_msg.get(&tag) {
-                                    for msg in msg_list.iter() {
-                                        let _ = func.call1(&JsValue::null(), msg);
-                                    }
-                                }
-                                unsent_msg.remove(&tag);
-                                callback.insert(tag, func);
-                            }
-                            Message::Reconnect => {
-                                inner.disconnected(&ctx);
-                                let _ = ws.close();
-                                call("disconnect", JsValue::null(), &mut callback, &mut unsent_msg);
-                                break 'inner;
-                            }
-                            Message::Stop => {
-                                inner.stopped(&ctx);
-                                let _ = ws.close();
-                                call("stop", JsValue::null(), &mut callback, &mut unsent_msg);
-                                break 'outer;
-                            }
-                        }
-                    }
-                }
-            }
-        }) as Box<dyn FnMut()>);
+    /// Starts the session with custom `Env`.
+    pub fn start_with(inner: T, url: String, policy: SessionPolicy, env: Box<dyn Env>) -> Session<T> {
+        let context = Context {
+            shared: Rc::new(Shared {
+                mailbox: RefCell::new(VecDeque::new()),
+                core: RefCell::new(Core {
+                    inner,
+                    env,
+                    url,
+                    policy,
+                    socket: None,
+                    status: Status::Connecting,
+                    conn_no: 0,
+                    retry: 0,
+                    is_opened: false,
+                    queue: VecDeque::new(),
+                }),
+            }),
+        };
 
-        window()?.set_timeout_with_callback(inner.as_ref().unchecked_ref())?;
-        inner.forget();
+        {
+            let mut core = context.shared.core.borrow_mut();
+            core.inner.started(&context);
+            core.env.call("start", JsValue::null());
+            core.connect(&context);
+        }
+        context.flush();
 
-        Ok(Session { context })
+        Session { context }
     }
 
     pub fn send(&self, msg: T::Sender) {
@@ -317,4 +525,322 @@ where
     pub fn on<S: AsRef<str>>(&self, tag: S, func: Function) {
         self.context.set_callback(tag, func);
     }
+
+    pub fn stop(&self) {
+        self.context.stop();
+    }
+}
+
+/// `Env` in the browser
+#[derive(Default)]
+pub struct WebEnv {
+    callback: HashMap<String, Function>,
+    unsent_msg: HashMap<String, Vec<JsValue>>,
+}
+
+impl WebEnv {
+    pub fn new() -> WebEnv {
+        WebEnv::default()
+    }
+}
+
+impl Env for WebEnv {
+    fn connect(&mut self, url: &str, on_event: Rc<dyn Fn(SocketEvent)>) -> Result<Box<dyn Socket>> {
+        Ok(Box::new(WebSocketSocket::new(url, on_event)?))
+    }
+
+    fn set_timeout(&mut self, ms: u32, callback: Box<dyn FnOnce()>) {
+        let callback = Closure::once_into_js(callback);
+        if let Ok(window) = window() {
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(callback.unchecked_ref(), ms as i32);
+        }
+    }
+
+    fn call(&mut self, tag: &str, val: JsValue) {
+        if let Some(func) = self.callback.get(tag) {
+            let _ = func.call1(&JsValue::null(), &val);
+        } else {
+            self.unsent_msg.entry(tag.to_owned()).or_default().push(val);
+        }
+    }
+
+    fn set_callback(&mut self, tag: String, func: Function) {
+        if let Some(msg_list) = self.unsent_msg.remove(&tag) {
+            for msg in msg_list.iter() {
+                let _ = func.call1(&JsValue::null(), msg);
+            }
+        }
+        self.callback.insert(tag, func);
+    }
+}
+
+struct WebSocketSocket {
+    ws: WebSocket,
+    _handlers: Vec<Closure<dyn FnMut(JsValue)>>,
+}
+
+impl WebSocketSocket {
+    fn new(url: &str, on_event: Rc<dyn Fn(SocketEvent)>) -> Result<WebSocketSocket> {
+        let ws = WebSocket::new(url)?;
+
+        let handler = |f: fn(JsValue) -> Option<SocketEvent>| {
+            let on_event = on_event.clone();
+            Closure::wrap(Box::new(move |e: JsValue| {
+                if let Some(event) = f(e) {
+                    on_event(event);
+                }
+            }) as Box<dyn FnMut(JsValue)>)
+        };
+
+        let onopen = handler(|_| Some(SocketEvent::Open));
+        let onmessage = handler(|e| {
+            let data = e.dyn_into::<MessageEvent>().ok()?.data();
+            let msg = data.dyn_into::<JsString>().ok()?;
+            Some(SocketEvent::Message(String::from(msg)))
+        });
+        let onerror = handler(|_| Some(SocketEvent::Error));
+        let onclose = handler(|e| {
+            // 1006: closed abnormally
+            let code = e.dyn_into::<CloseEvent>().map(|e| e.code()).unwrap_or(1006);
+            Some(SocketEvent::Close(code))
+        });
+
+        ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        ws.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+        ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+        Ok(WebSocketSocket {
+            ws,
+            _handlers: vec![onopen, onmessage, onerror, onclose],
+        })
+    }
+}
+
+impl Socket for WebSocketSocket {
+    fn send(&self, msg: &str) -> bool {
+        self.ws.send_with_str(msg).is_ok()
+    }
+
+    fn close(&self) {
+        let _ = self.ws.close();
+    }
+}
+
+impl Drop for WebSocketSocket {
+    fn drop(&mut self) {
+        // handlers are dropped with this, so they should not be called anymore
+        self.ws.set_onopen(None);
+        self.ws.set_onmessage(None);
+        self.ws.set_onerror(None);
+        self.ws.set_onclose(None);
+        let _ = self.ws.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    type Sent = Rc<RefCell<Vec<String>>>;
+    type OnEvent = Rc<dyn Fn(SocketEvent)>;
+
+    #[derive(Default)]
+    struct Mock {
+        sockets: Vec<(OnEvent, Sent)>,
+        timers: Vec<(u32, Box<dyn FnOnce()>)>,
+        calls: Vec<String>,
+    }
+
+    struct MockEnv(Rc<RefCell<Mock>>);
+
+    struct MockSocket(Sent, Rc<Cell<bool>>);
+
+    impl Socket for MockSocket {
+        fn send(&self, msg: &str) -> bool {
+            self.0.borrow_mut().push(msg.to_owned());
+            true
+        }
+
+        fn close(&self) {
+            self.1.set(true);
+        }
+    }
+
+    impl Env for MockEnv {
+        fn connect(&mut self, _: &str, on_event: Rc<dyn Fn(SocketEvent)>) -> Result<Box<dyn Socket>> {
+            let sent = Rc::new(RefCell::new(Vec::new()));
+            self.0.borrow_mut().sockets.push((on_event, sent.clone()));
+            Ok(Box::new(MockSocket(sent, Rc::new(Cell::new(false)))))
+        }
+
+        fn set_timeout(&mut self, ms: u32, callback: Box<dyn FnOnce()>) {
+            self.0.borrow_mut().timers.push((ms, callback));
+        }
+
+        fn call(&mut self, tag: &str, _: JsValue) {
+            self.0.borrow_mut().calls.push(tag.to_owned());
+        }
+
+        fn set_callback(&mut self, _: String, _: Function) {}
+
+        fn random(&mut self) -> f64 {
+            0.5
+        }
+    }
+
+    struct Room;
+
+    impl SessionTrait for Room {
+        type Sender = String;
+
+        fn tag() -> &'static str {
+            "test"
+        }
+
+        fn reconnected(&mut self, ctx: &Context<Self>) {
+            ctx.handle("resync".to_owned());
+        }
+
+        fn replay(&self, msg: &str) -> bool {
+            !msg.starts_with("go")
+        }
+
+        fn handle(&mut self, msg: String, ctx: &Context<Self>) {
+            ctx.send(msg);
+        }
+
+        fn receive(&mut self, _: String, _: &Context<Self>) -> (&str, JsValue) {
+            ("message", JsValue::null())
+        }
+    }
+
+    fn start(policy: SessionPolicy) -> (Session<Room>, Rc<RefCell<Mock>>) {
+        let mock = Rc::new(RefCell::new(Mock::default()));
+        let session = Session::start_with(Room, "ws://test".to_owned(), policy, Box::new(MockEnv(mock.clone())));
+        (session, mock)
+    }
+
+    fn event(mock: &Rc<RefCell<Mock>>, idx: usize, e: SocketEvent) {
+        let on_event = mock.borrow().sockets[idx].0.clone();
+        on_event(e);
+    }
+
+    fn sent(mock: &Rc<RefCell<Mock>>, idx: usize) -> Vec<String> {
+        mock.borrow().sockets[idx].1.borrow().clone()
+    }
+
+    fn fire_timer(mock: &Rc<RefCell<Mock>>) -> u32 {
+        let (ms, callback) = mock.borrow_mut().timers.remove(0);
+        callback();
+        ms
+    }
+
+    #[test]
+    fn policy_delay_test() {
+        let policy = SessionPolicy::new().set_jitter(0.0);
+        assert_eq!(policy.delay(0, 0.3), 500);
+        assert_eq!(policy.delay(1, 0.3), 1000);
+        assert_eq!(policy.delay(3, 0.3), 4000);
+        assert_eq!(policy.delay(10, 0.3), 8000);
+
+        let policy = SessionPolicy::new().set_jitter(0.5);
+        assert_eq!(policy.delay(0, 0.0), 250);
+        assert_eq!(policy.delay(0, 0.5), 500);
+        assert!(policy.delay(10, 0.99) <= 12000);
+    }
+
+    #[test]
+    fn buffer_until_open_test() {
+        let (session, mock) = start(SessionPolicy::new());
+        session.send("a".to_owned());
+        session.send("b".to_owned());
+        assert!(sent(&mock, 0).is_empty());
+
+        event(&mock, 0, SocketEvent::Open);
+        assert_eq!(sent(&mock, 0), vec!["a", "b"]);
+
+        session.send("c".to_owned());
+        assert_eq!(sent(&mock, 0), vec!["a", "b", "c"]);
+        assert_eq!(mock.borrow().calls, vec!["start"]);
+    }
+
+    #[test]
+    fn reconnect_and_replay_test() {
+        let (session, mock) = start(SessionPolicy::new().set_jitter(0.0));
+        event(&mock, 0, SocketEvent::Open);
+        event(&mock, 0, SocketEvent::Close(1006));
+        assert_eq!(mock.borrow().calls, vec!["start", "disconnect"]);
+
+        session.send("a".to_owned());
+        session.send("go".to_owned());
+        session.send("b".to_owned());
+        assert_eq!(fire_timer(&mock), 500);
+        event(&mock, 1, SocketEvent::Error);
+        assert_eq!(fire_timer(&mock), 1000);
+
+        // events from old connection are ignored
+        event(&mock, 1, SocketEvent::Open);
+        event(&mock, 0, SocketEvent::Message("old".to_owned()));
+        assert!(sent(&mock, 1).is_empty());
+
+        // resynced first, and the stale message is dropped
+        event(&mock, 2, SocketEvent::Open);
+        assert_eq!(sent(&mock, 2), vec!["resync", "a", "b"]);
+        assert_eq!(mock.borrow().calls, vec!["start", "disconnect", "reconnect"]);
+
+        // backoff is reset after reconnection
+        event(&mock, 2, SocketEvent::Close(1006));
+        assert_eq!(fire_timer(&mock), 500);
+    }
+
+    #[test]
+    fn queue_limit_test() {
+        let (session, mock) = start(SessionPolicy::new().set_max_queue(2));
+        for msg in ["a", "b", "c"].iter() {
+            session.send(msg.to_string());
+        }
+        event(&mock, 0, SocketEvent::Open);
+        assert_eq!(sent(&mock, 0), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn server_close_test() {
+        let (session, mock) = start(SessionPolicy::new());
+        event(&mock, 0, SocketEvent::Open);
+        event(&mock, 0, SocketEvent::Close(NORMAL_CLOSURE));
+        assert_eq!(mock.borrow().calls, vec!["start", "stop"]);
+        assert!(mock.borrow().timers.is_empty());
+
+        session.send("a".to_owned());
+        assert_eq!(mock.borrow().sockets.len(), 1);
+    }
+
+    #[test]
+    fn default_max_retry_test() {
+        let (_session, mock) = start(SessionPolicy::new());
+        // the server never accepts the connection
+        for idx in 0..10 {
+            event(&mock, idx, SocketEvent::Close(1006));
+            fire_timer(&mock);
+        }
+        event(&mock, 10, SocketEvent::Close(1006));
+        assert_eq!(mock.borrow().calls, vec!["start", "stop"]);
+        assert!(mock.borrow().timers.is_empty());
+    }
+
+    #[test]
+    fn max_retry_test() {
+        let (session, mock) = start(SessionPolicy::new().set_max_retry(Some(1)));
+        event(&mock, 0, SocketEvent::Open);
+        event(&mock, 0, SocketEvent::Close(1006));
+        fire_timer(&mock);
+        event(&mock, 1, SocketEvent::Error);
+        assert_eq!(mock.borrow().calls, vec!["start", "disconnect", "stop"]);
+        assert!(mock.borrow().timers.is_empty());
+
+        session.send("a".to_owned());
+        assert_eq!(mock.borrow().sockets.len(), 2);
+    }
 }
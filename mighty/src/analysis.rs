@@ -0,0 +1,375 @@
+//! Post-game analysis of the decisions.
+//!
+//! Each decision of a finished game is evaluated by Monte Carlo simulation:
+//! the cards the player can't see are dealt again at random, and the game is played out by `BasicAgent`
+//! for the command the player took and its alternatives.
+//! A decision is flagged when the best alternative is expected to make more points than the command taken.
+
+use crate::bot::{is_valid_command, mighty_of, valid_commands, waiting_users, Agent, BasicAgent};
+use crate::card::Card;
+use crate::command::Command;
+use crate::rule::Rule;
+use crate::state::{FriendFunc, State};
+use rand::rngs::mock::StepRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Limit of the commands in one simulated game, to stop when the agent can't finish the game
+const MAX_ROLLOUT_STEPS: usize = 1000;
+
+/// Kind of the decision
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Bid,
+    Drop,
+    Friend,
+    Play,
+}
+
+/// How bad the decision is, by the expected points lost
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Severity {
+    /// Returns `None` if the cost is small enough to ignore.
+    pub fn new(cost: f64) -> Option<Severity> {
+        match cost {
+            c if c >= 4.0 => Some(Severity::Blunder),
+            c if c >= 2.0 => Some(Severity::Mistake),
+            c if c >= 1.0 => Some(Severity::Inaccuracy),
+            _ => None,
+        }
+    }
+}
+
+/// Decision which cost expected points
+///
+/// - `pos`: index of the state where the decision was made
+/// - `cost`: expected points lost compared to `best`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Flag {
+    pub pos: usize,
+    pub user: usize,
+    pub kind: Decision,
+    pub played: Command,
+    pub best: Command,
+    pub cost: f64,
+    pub severity: Severity,
+}
+
+/// Analyzes every decision between `states`, simulating `samples` games for each command.
+pub fn analyze<R: Rng + ?Sized>(states: &[State], rule: &Rule, samples: usize, rng: &mut R) -> Vec<Flag> {
+    let mut flags = Vec::new();
+    for (pos, pair) in states.windows(2).enumerate() {
+        for (user, kind, played) in find_decisions(&pair[0], &pair[1], rule) {
+            let cmds = alternatives(&pair[0], user, kind, &played, rule);
+            if cmds.len() < 2 {
+                continue;
+            }
+            let values = evaluate(&pair[0], user, &cmds, rule, samples, rng);
+            let actual = match values[0] {
+                Some(value) => value,
+                None => continue,
+            };
+            let best = values
+                .iter()
+                .enumerate()
+                .filter_map(|(i, v)| v.map(|v| (i, v)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((i, value)) = best {
+                let cost = value - actual;
+                if let Some(severity) = Severity::new(cost) {
+                    flags.push(Flag {
+                        pos,
+                        user,
+                        kind,
+                        played,
+                        best: cmds[i].clone(),
+                        cost,
+                        severity,
+                    });
+                }
+            }
+        }
+    }
+    flags
+}
+
+/// Decisions made from `before` to `after`: user, kind and the command.
+/// Dropping cards and calling the friend are made by one command, but they are analyzed separately.
+fn find_decisions(before: &State, after: &State, rule: &Rule) -> Vec<(usize, Decision, Command)> {
+    let kind = match (before, after) {
+        (
+            State::SelectFriend { president, deck, .. },
+            State::InGame {
+                friend_func,
+                deck: after_deck,
+                ..
+            },
+        ) => {
+            let drop_card = deck[*president]
+                .iter()
+                .filter(|c| !after_deck[*president].contains(c))
+                .copied()
+                .collect::<Vec<_>>();
+            let cmd = Command::SelectFriend(drop_card, friend_func.clone());
+            return vec![
+                (*president, Decision::Drop, cmd.clone()),
+                (*president, Decision::Friend, cmd),
+            ];
+        }
+        (State::Election { .. }, _) => Decision::Bid,
+        (State::InGame { .. }, _) => Decision::Play,
+        _ => return Vec::new(),
+    };
+
+    let waiting = waiting_users(before, rule);
+    for user in (0..rule.user_cnt as usize).filter(|i| waiting & (1 << i) > 0) {
+        for cmd in valid_commands(before, user, rule) {
+            let next = before.next_with_rng(user, cmd.clone(), rule, &mut StepRng::new(0, 1));
+            if next.ok().as_ref() == Some(after) {
+                return vec![(user, kind, cmd)];
+            }
+        }
+    }
+    Vec::new()
+}
+
+/// Commands compared with `played`, starting from `played`.
+fn alternatives(state: &State, user: usize, kind: Decision, played: &Command, rule: &Rule) -> Vec<Command> {
+    let basic = BasicAgent.decide(user, state, rule);
+    let candidates = match (kind, played, &basic) {
+        (Decision::Bid, _, _) => vec![Command::Pledge(None), basic],
+        (Decision::Drop, Command::SelectFriend(_, friend_func), Command::SelectFriend(drop_card, _)) => {
+            vec![Command::SelectFriend(drop_card.clone(), friend_func.clone())]
+        }
+        (Decision::Friend, Command::SelectFriend(drop_card, _), _) => {
+            let (giruda, deck) = match state {
+                State::SelectFriend { giruda, deck, .. } => (*giruda, deck),
+                _ => return Vec::new(),
+            };
+            let mut friend_func = vec![FriendFunc::None, FriendFunc::First, FriendFunc::Last];
+            friend_func.push(FriendFunc::ByCard(mighty_of(giruda)));
+            friend_func.extend(
+                rule.deck
+                    .0
+                    .iter()
+                    .filter(|c| c.is_joker())
+                    .map(|c| FriendFunc::ByCard(*c)),
+            );
+            if let Command::SelectFriend(_, f) = basic {
+                friend_func.push(f);
+            }
+            friend_func
+                .into_iter()
+                .filter(|f| match f {
+                    // the called card should be in someone's hand
+                    FriendFunc::ByCard(c) => !drop_card.contains(c) && deck.iter().any(|d| d.contains(c)),
+                    _ => true,
+                })
+                .map(|f| Command::SelectFriend(drop_card.clone(), f))
+                .collect()
+        }
+        (Decision::Play, _, _) => valid_commands(state, user, rule),
+        _ => Vec::new(),
+    };
+
+    let mut cmds = vec![played.clone()];
+    for cmd in candidates {
+        if !cmds.contains(&cmd) && is_valid_command(state, user, &cmd, rule) {
+            cmds.push(cmd);
+        }
+    }
+    cmds
+}
+
+/// Average points of `user` for each command, or `None` if no simulated game finished.
+/// Every command is simulated in the same random deals to compare them fairly.
+fn evaluate<R: Rng + ?Sized>(
+    state: &State,
+    user: usize,
+    cmds: &[Command],
+    rule: &Rule,
+    samples: usize,
+    rng: &mut R,
+) -> Vec<Option<f64>> {
+    let mut sum = vec![(0.0, 0); cmds.len()];
+    for _ in 0..samples {
+        let world = determinize(state, user, rng);
+        let seed = rng.gen::<u64>();
+        for (cmd, (total, count)) in cmds.iter().zip(sum.iter_mut()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let end = world
+                .next_with_rng(user, cmd.clone(), rule, &mut rng)
+                .ok()
+                .and_then(|next| rollout(next, rule, &mut rng));
+            if let Some(end) = end {
+                *total += payoff(&end, user);
+                *count += 1;
+            }
+        }
+    }
+    sum.into_iter()
+        .map(|(total, count)| if count > 0 { Some(total / count as f64) } else { None })
+        .collect()
+}
+
+/// Deals the cards `user` can't see again, keeping the count of cards of each hand.
+pub fn determinize<R: Rng + ?Sized>(state: &State, user: usize, rng: &mut R) -> State {
+    let mut state = state.clone();
+    let (deck, mut left) = match &mut state {
+        State::Election { deck, left, .. } => (deck, Some(left)),
+        State::SelectFriend { deck, .. } | State::InGame { deck, .. } => (deck, None),
+        State::GameEnded { .. } => return state,
+    };
+
+    let mut hidden = deck
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != user)
+        .flat_map(|(_, d)| d.iter().copied())
+        .chain(left.iter().flat_map(|l| l.iter().copied()))
+        .collect::<Vec<Card>>();
+    hidden.shuffle(rng);
+    let mut hidden = hidden.into_iter();
+    for (i, d) in deck.iter_mut().enumerate() {
+        if i != user {
+            let cnt = d.len();
+            *d = hidden.by_ref().take(cnt).collect();
+        }
+    }
+    if let Some(left) = left.as_mut() {
+        let cnt = left.len();
+        **left = hidden.by_ref().take(cnt).collect();
+    }
+
+    // friend called by the card is whoever has the card now
+    if let State::InGame {
+        friend_func: FriendFunc::ByCard(c),
+        friend,
+        is_friend_known: false,
+        deck,
+        ..
+    } = &mut state
+    {
+        if let Some(holder) = deck.iter().position(|d| d.contains(c)) {
+            *friend = Some(holder);
+        }
+    }
+    state
+}
+
+/// Plays the game to the end with `BasicAgent`.
+fn rollout<R: Rng + ?Sized>(mut state: State, rule: &Rule, rng: &mut R) -> Option<State> {
+    let mut agent = BasicAgent;
+    for _ in 0..MAX_ROLLOUT_STEPS {
+        if let State::GameEnded { .. } = state {
+            return Some(state);
+        }
+        let waiting = waiting_users(&state, rule);
+        let user = (0..rule.user_cnt as usize).find(|i| waiting & (1 << i) > 0)?;
+        let cmd = agent.decide(user, &state, rule);
+        state = state.next_with_rng(user, cmd, rule, rng).ok()?;
+    }
+    None
+}
+
+/// Points of `user` over the pledge, negative for the opposition.
+fn payoff(end: &State, user: usize) -> f64 {
+    match end {
+        State::GameEnded {
+            president,
+            friend,
+            score,
+            pledge,
+            ..
+        } => {
+            let margin = *score as f64 - *pledge as f64;
+            if user == *president || Some(user) == *friend {
+                margin
+            } else {
+                -margin
+            }
+        }
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::rule::Preset;
+
+    fn play(rule: &Rule, seed: u64) -> Vec<State> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut game = Game::new_with_rng(rule.clone(), &mut rng);
+        let mut states = vec![game.get_state()];
+        while !game.is_finished() {
+            let user = (0..5)
+                .find(|i| waiting_users(&game.state, rule) & (1 << i) > 0)
+                .unwrap();
+            let cmd = BasicAgent.decide(user, &game.state, rule);
+            game.next_with_rng(user, cmd, &mut rng).unwrap();
+            states.push(game.get_state());
+        }
+        states
+    }
+
+    #[test]
+    fn determinize_test() {
+        let rule = Rule::from(Preset::Default5);
+        let states = play(&rule, 1);
+        let state = states.iter().find(|s| matches!(s, State::InGame { .. })).unwrap();
+        let world = determinize(state, 2, &mut StdRng::seed_from_u64(0));
+        match (state, &world) {
+            (State::InGame { deck, .. }, State::InGame { deck: new_deck, .. }) => {
+                assert_eq!(deck[2], new_deck[2]);
+                let count = |d: &Vec<Vec<Card>>| d.iter().map(|h| h.len()).collect::<Vec<_>>();
+                assert_eq!(count(deck), count(new_deck));
+                let mut old = deck.concat();
+                let mut new = new_deck.concat();
+                old.sort();
+                new.sort();
+                assert_eq!(old, new);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn find_decisions_test() {
+        let rule = Rule::from(Preset::Default5);
+        let states = play(&rule, 2);
+        let kinds = states
+            .windows(2)
+            .flat_map(|pair| find_decisions(&pair[0], &pair[1], &rule))
+            .map(|(_, kind, _)| kind)
+            .collect::<Vec<_>>();
+        assert_eq!(kinds.iter().filter(|k| **k == Decision::Play).count(), 50);
+        assert_eq!(kinds.iter().filter(|k| **k == Decision::Drop).count(), 1);
+        assert!(kinds.contains(&Decision::Bid));
+    }
+
+    #[test]
+    fn analyze_test() {
+        let rule = Rule::from(Preset::Default5);
+        let states = play(&rule, 3);
+        let flags = analyze(&states, &rule, 2, &mut StdRng::seed_from_u64(0));
+        for flag in flags {
+            assert!(flag.cost >= 1.0);
+            assert_ne!(flag.played, flag.best);
+            assert!(flag.pos + 1 < states.len());
+        }
+        assert_eq!(Severity::new(0.5), None);
+        assert_eq!(Severity::new(2.5), Some(Severity::Mistake));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::card::{Card, Color, Pattern, Rush};
+use crate::command::Command;
+use crate::rule::{election::Election, Rule};
+use crate::state::{FriendFunc, State};
+use rand::rngs::mock::StepRng;
+
+/// Player controlled by the computer.
+///
+/// `decide` is called only when `user_id` is one of `state.valid_users(rule)`,
+/// and the command it returns should be accepted by `State::next`.
+pub trait Agent {
+    fn decide(&mut self, user_id: usize, state: &State, rule: &Rule) -> Command;
+}
+
+const PATTERNS: [Option<Pattern>; 5] = [
+    Some(Pattern::Spade),
+    Some(Pattern::Diamond),
+    Some(Pattern::Heart),
+    Some(Pattern::Clover),
+    None,
+];
+
+/// Checks if `cmd` of `user_id` is accepted in this state.
+pub fn is_valid_command(state: &State, user_id: usize, cmd: &Command, rule: &Rule) -> bool {
+    state.valid_users(rule) & (1 << user_id) > 0
+        && state
+            .next_with_rng(user_id, cmd.clone(), rule, &mut StepRng::new(0, 1))
+            .is_ok()
+}
+
+/// Every command `user_id` can take in this state.
+///
+/// Dropping cards in `State::SelectFriend` has too many combinations,
+/// so only `Command::ChangePledge` is listed for it.
+pub fn valid_commands(state: &State, user_id: usize, rule: &Rule) -> Vec<Command> {
+    let candidates = match state {
+        State::Election { .. } => std::iter::once(Command::Pledge(None))
+            .chain(
+                PATTERNS
+                    .iter()
+                    .flat_map(|g| (rule.pledge.min..=rule.pledge.max).map(move |p| Command::Pledge(Some((*g, p))))),
+            )
+            .collect::<Vec<_>>(),
+        State::SelectFriend { .. } => PATTERNS.iter().map(|g| Command::ChangePledge(*g)).collect(),
+        State::InGame { deck, .. } => deck[user_id]
+            .iter()
+            .flat_map(|c| {
+                let rushes = match c {
+                    Card::Joker(Color::Black) => vec![Rush::SPADE, Rush::CLOVER],
+                    Card::Joker(Color::Red) => vec![Rush::DIAMOND, Rush::HEART],
+                    Card::Normal(..) => vec![Rush::from(*c)],
+                };
+                let can_call = rule.joker_call.cards.iter().any(|(a, b)| a == c || b == c);
+                rushes.into_iter().flat_map(move |r| {
+                    let mut cmds = vec![Command::Go(*c, r, false)];
+                    if can_call {
+                        cmds.push(Command::Go(*c, r, true));
+                    }
+                    cmds
+                })
+            })
+            .collect(),
+        State::GameEnded { .. } => Vec::new(),
+    };
+
+    candidates
+        .into_iter()
+        .filter(|c| is_valid_command(state, user_id, c, rule))
+        .collect()
+}
+
+/// Users who still have to act in this state.
+///
+/// Same as `State::valid_users`, except that users who already passed
+/// in an unordered election are not waited for.
+pub fn waiting_users(state: &State, rule: &Rule) -> u8 {
+    let valid = state.valid_users(rule);
+    match state {
+        State::Election { done, .. } if !rule.election.contains(Election::ORDERED) => done
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| **d)
+            .fold(valid, |v, (i, _)| v & !(1 << i)),
+        _ => valid,
+    }
+}
+
+pub(crate) fn mighty_of(giruda: Option<Pattern>) -> Card {
+    match giruda {
+        Some(Pattern::Spade) => Card::Normal(Pattern::Diamond, 14),
+        _ => Card::Normal(Pattern::Spade, 14),
+    }
+}
+
+/// Rough strength of a card when `giruda` is the giruda.
+fn card_value(card: Card, giruda: Option<Pattern>) -> u8 {
+    match card {
+        c if c == mighty_of(giruda) => 100,
+        Card::Joker(_) => 90,
+        Card::Normal(p, n) if Some(p) == giruda => 50 + n,
+        Card::Normal(_, n) => n,
+    }
+}
+
+/// Simple agent which follows a few rules of thumb.
+///
+/// - election: pledges on the pattern it has most, as long as the hand looks strong enough.
+/// - select friend: drops the weakest cards and calls the strongest card it doesn't have.
+/// - in game: leads with the strongest card, and follows with the weakest card that wins the trick.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BasicAgent;
+
+impl BasicAgent {
+    fn pledge(&self, user_id: usize, state: &State, rule: &Rule) -> Command {
+        let (pledge, hand) = match state {
+            State::Election { pledge, deck, .. } => (pledge, &deck[user_id]),
+            _ => unreachable!(),
+        };
+        let cmds = valid_commands(state, user_id, rule);
+        let pass = Command::Pledge(None);
+
+        let max_pledge = pledge.iter().flatten().map(|(_, p)| *p).max();
+        if matches!(pledge[user_id], Some((_, p)) if Some(p) == max_pledge) && cmds.contains(&pass) {
+            return pass;
+        }
+
+        let (giruda, count) = PATTERNS[..4]
+            .iter()
+            .map(|g| {
+                (
+                    *g,
+                    hand.iter()
+                        .filter(|c| matches!(c, Card::Normal(p, _) if Some(*p) == *g))
+                        .count(),
+                )
+            })
+            .max_by_key(|(_, cnt)| *cnt)
+            .unwrap();
+        let mighty = hand.contains(&mighty_of(giruda)) as usize;
+        let jokers = hand.iter().filter(|c| c.is_joker()).count();
+        let aces = hand
+            .iter()
+            .filter(|c| matches!(c, Card::Normal(p, 14) if Some(*p) != giruda))
+            .count();
+        let estimate = 6 + count + mighty + jokers + aces;
+
+        let lowest = |f: &dyn Fn(Option<Pattern>, u8) -> bool| {
+            cmds.iter()
+                .filter(|c| matches!(c, Command::Pledge(Some((g, p))) if f(*g, *p)))
+                .min_by_key(|c| match c {
+                    Command::Pledge(Some((_, p))) => *p,
+                    _ => unreachable!(),
+                })
+                .cloned()
+        };
+
+        lowest(&|g, p| g == giruda && p as usize <= estimate)
+            .or_else(|| Some(pass).filter(|c| cmds.contains(c)))
+            .or_else(|| lowest(&|_, _| true))
+            .unwrap_or(Command::Random)
+    }
+
+    fn select_friend(&self, user_id: usize, state: &State, rule: &Rule) -> Command {
+        let (giruda, deck) = match state {
+            State::SelectFriend { giruda, deck, .. } => (*giruda, deck),
+            _ => unreachable!(),
+        };
+        let hand = &deck[user_id];
+        let drop_cnt = hand.len() - deck[(user_id + 1) % (rule.user_cnt as usize)].len();
+
+        let mut sorted = hand.clone();
+        sorted.sort_by_key(|c| card_value(*c, giruda));
+        let drop_card = sorted[..drop_cnt].to_vec();
+
+        // every card of the deck which is not in the hand of president is in someone else's hand
+        let mut friend_func = Vec::new();
+        let mighty = mighty_of(giruda);
+        if !hand.contains(&mighty) {
+            friend_func.push(FriendFunc::ByCard(mighty));
+        }
+        for c in rule.deck.0.iter().filter(|c| c.is_joker() && !hand.contains(c)) {
+            friend_func.push(FriendFunc::ByCard(*c));
+        }
+        if let Some(g) = giruda {
+            for c in (10..=14).rev().map(|n| Card::Normal(g, n)) {
+                if rule.deck.0.contains(&c) && !hand.contains(&c) {
+                    friend_func.push(FriendFunc::ByCard(c));
+                }
+            }
+        }
+        friend_func.push(FriendFunc::First);
+        friend_func.push(FriendFunc::None);
+        friend_func.push(FriendFunc::ByUser((user_id + 1) % (rule.user_cnt as usize)));
+
+        friend_func
+            .into_iter()
+            .map(|f| Command::SelectFriend(drop_card.clone(), f))
+            .find(|c| is_valid_command(state, user_id, c, rule))
+            .unwrap_or(Command::Random)
+    }
+
+    fn go(&self, user_id: usize, state: &State, rule: &Rule) -> Command {
+        let (giruda, deck, placed_cards, start_user, current_user) = match state {
+            State::InGame {
+                giruda,
+                deck,
+                placed_cards,
+                start_user,
+                current_user,
+                ..
+            } => (*giruda, deck, placed_cards, *start_user, *current_user),
+            _ => unreachable!(),
+        };
+        let cmds = valid_commands(state, user_id, rule);
+        let value = |c: &Command| match c {
+            Command::Go(card, rush, call) => {
+                let rush_cnt = deck[user_id].iter().filter(|x| rush.contains(Rush::from(**x))).count();
+                (card_value(*card, giruda), !*call, rush_cnt)
+            }
+            _ => (0, false, 0),
+        };
+
+        if start_user == current_user {
+            return cmds.iter().max_by_key(|c| value(c)).cloned().unwrap_or(Command::Random);
+        }
+
+        let user_cnt = rule.user_cnt as usize;
+        let played = (0..user_cnt)
+            .map(|i| (start_user + i) % user_cnt)
+            .take_while(|i| *i != current_user)
+            .map(|i| placed_cards[i].0)
+            .collect::<Vec<_>>();
+        let is_winning = |c: &&Command| match c {
+            Command::Go(card, ..) => {
+                let mut cards = played.clone();
+                cards.push(*card);
+                state.calculate_winner(rule, &cards) == *card
+            }
+            _ => false,
+        };
+
+        cmds.iter()
+            .filter(is_winning)
+            .min_by_key(|c| value(c))
+            .or_else(|| cmds.iter().min_by_key(|c| value(c)))
+            .cloned()
+            .unwrap_or(Command::Random)
+    }
+}
+
+impl Agent for BasicAgent {
+    fn decide(&mut self, user_id: usize, state: &State, rule: &Rule) -> Command {
+        match state {
+            State::Election { .. } => self.pledge(user_id, state, rule),
+            State::SelectFriend { .. } => self.select_friend(user_id, state, rule),
+            State::InGame { .. } => self.go(user_id, state, rule),
+            State::GameEnded { .. } => Command::Random,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::Game;
+    use crate::rule::Preset;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn play(preset: Preset, seed: u64) -> State {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut game = Game::new_with_rng(Rule::from(preset), &mut rng);
+        let mut agent = BasicAgent;
+
+        for _ in 0..1000 {
+            if game.is_finished() {
+                break;
+            }
+            let user_id = (0..game.rule.user_cnt as usize)
+                .find(|i| waiting_users(&game.state, &game.rule) & (1 << i) > 0)
+                .unwrap();
+            let cmd = agent.decide(user_id, &game.state, &game.rule);
+            game.next_with_rng(user_id, cmd, &mut rng).unwrap();
+        }
+
+        assert!(game.is_finished());
+        game.get_state()
+    }
+
+    #[test]
+    fn basic_agent_finishes_game_test() {
+        let presets = [
+            Preset::Default5,
+            Preset::Ddshs5,
+            Preset::Dhsh5,
+            Preset::Kmla5,
+            Preset::Gsa5,
+            Preset::Gshs5,
+            Preset::Skku5,
+            Preset::Sshs5,
+            Preset::Yu5,
+        ];
+
+        for preset in presets.iter() {
+            for seed in 0..10 {
+                assert!(matches!(play(*preset, seed), State::GameEnded { .. }));
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_game_test() {
+        for seed in 0..5 {
+            assert_eq!(play(Preset::Default5, seed), play(Preset::Default5, seed));
+        }
+    }
+}
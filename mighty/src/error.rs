@@ -16,6 +16,7 @@ pub enum Error {
     WrongCard,
     PassFirst,
     JokerCall,
+    NothingToUndo,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -41,6 +42,7 @@ impl std::fmt::Display for Error {
             Error::WrongCard => write!(f, "you can't place this card"),
             Error::PassFirst => write!(f, "dealer should run at first turn"),
             Error::JokerCall => write!(f, "you need to place the joker"),
+            Error::NothingToUndo => write!(f, "there is no command to undo"),
         }
     }
 }
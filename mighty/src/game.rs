@@ -2,6 +2,8 @@ use crate::command::Command;
 use crate::error::{Error, Result};
 use crate::rule::Rule;
 use crate::state::State;
+use crate::summary::Summary;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, Eq, PartialEq)]
@@ -9,16 +11,22 @@ pub struct Game {
     pub rule: Rule,
     pub state: State,
     valid_users: u8,
+    history: Vec<(usize, State)>,
 }
 
 impl Game {
     pub fn new(rule: Rule) -> Game {
-        let state = State::new(&rule);
+        Game::new_with_rng(rule, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng + ?Sized>(rule: Rule, rng: &mut R) -> Game {
+        let state = State::new_with_rng(&rule, rng);
         let valid_users = state.valid_users(&rule);
         Game {
             rule,
             state,
             valid_users,
+            history: Vec::new(),
         }
     }
 
@@ -31,8 +39,13 @@ impl Game {
     }
 
     pub fn next(&mut self, user_id: usize, cmd: Command) -> Result<bool> {
+        self.next_with_rng(user_id, cmd, &mut rand::thread_rng())
+    }
+
+    pub fn next_with_rng<R: Rng + ?Sized>(&mut self, user_id: usize, cmd: Command, rng: &mut R) -> Result<bool> {
         if self.valid_users & (1u8 << user_id) > 0 {
-            self.state = self.state.next(user_id, cmd, &self.rule)?;
+            let state = self.state.next_with_rng(user_id, cmd, &self.rule, rng)?;
+            self.history.push((user_id, std::mem::replace(&mut self.state, state)));
             self.valid_users = self.state.valid_users(&self.rule);
             Ok(self.valid_users == 0)
         } else {
@@ -43,4 +56,101 @@ impl Game {
     pub fn get_state(&self) -> State {
         self.state.clone()
     }
+
+    /// All states of the game from the start to now
+    pub fn states(&self) -> Vec<State> {
+        let mut states = self.history.iter().map(|(_, state)| state.clone()).collect::<Vec<_>>();
+        states.push(self.state.clone());
+        states
+    }
+
+    /// Summary of the game if it is ended
+    pub fn summary(&self) -> Option<Summary> {
+        Summary::from_states(&self.states(), &self.rule)
+    }
+
+    /// If the user has any command to undo
+    pub fn can_undo(&self, user_id: usize) -> bool {
+        self.history.iter().any(|(id, _)| *id == user_id)
+    }
+
+    /// Rolls back to the state before the last command of the user, including the commands after it.
+    /// Returns how many commands are undone.
+    pub fn undo(&mut self, user_id: usize) -> Result<usize> {
+        let pos = self
+            .history
+            .iter()
+            .rposition(|(id, _)| *id == user_id)
+            .ok_or(Error::NothingToUndo)?;
+        let undone = self.history.len() - pos;
+        let (_, state) = self.history.drain(pos..).next().unwrap();
+        self.state = state;
+        self.valid_users = self.state.valid_users(&self.rule);
+        Ok(undone)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bot::{Agent, BasicAgent};
+    use crate::rule::Preset;
+
+    #[test]
+    fn undo_test() {
+        let mut game = Game::new(Rule::from(Preset::Default5));
+        let start = game.get_state();
+        assert_eq!(game.undo(0), Err(Error::NothingToUndo));
+        assert!(!game.can_undo(0));
+
+        game.next(0, Command::Pledge(None)).unwrap();
+        game.next(1, Command::Pledge(None)).unwrap();
+        let after_first = game.get_state();
+        game.next(2, Command::Pledge(None)).unwrap();
+
+        assert_eq!(game.undo(2), Ok(1));
+        assert_eq!(game.get_state(), after_first);
+        assert_eq!(game.undo(0), Ok(2));
+        assert_eq!(game.get_state(), start);
+        assert_eq!(game.valid_users(), start.valid_users(&game.rule));
+        assert_eq!(game.states(), vec![start]);
+    }
+
+    #[test]
+    fn summary_test() {
+        let mut game = Game::new(Rule::from(Preset::Default5));
+        assert_eq!(game.summary(), None);
+        while !game.is_finished() {
+            let user_id = (0..5).find(|i| game.valid_users() & (1 << i) > 0).unwrap();
+            let cmd = BasicAgent.decide(user_id, &game.state, &game.rule);
+            game.next(user_id, cmd).unwrap();
+        }
+
+        let summary = game.summary().unwrap();
+        assert_eq!(summary.hands.len(), 5);
+        assert!(summary.hands.iter().all(|hand| hand.len() == 10));
+        assert_eq!(summary.points.len(), 5);
+        assert!(summary.points.iter().flatten().all(|card| card.is_score()));
+        match game.state {
+            State::GameEnded { winner, president, .. } => {
+                assert_eq!(summary.winner, winner);
+                assert_eq!(summary.president, president);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn masked_test() {
+        let game = Game::new(Rule::from(Preset::Default5));
+        match game.state.masked(0b100) {
+            State::Election { deck, left, .. } => {
+                assert!(left.is_empty());
+                for (i, d) in deck.iter().enumerate() {
+                    assert_eq!(d.is_empty(), i != 2);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
 }
@@ -1,16 +1,24 @@
+#[cfg(any(feature = "client", feature = "server"))]
+pub mod analysis;
+#[cfg(any(feature = "client", feature = "server"))]
+pub mod bot;
 mod card;
 mod command;
 pub mod error;
-#[cfg(feature = "server")]
+#[cfg(any(feature = "client", feature = "server"))]
 mod game;
 mod rule;
 mod state;
+mod summary;
 
 pub mod prelude {
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub use crate::bot::{Agent, BasicAgent};
     pub use crate::card::{Card, Color, Pattern, Rush};
     pub use crate::command::Command;
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     pub use crate::game::Game;
     pub use crate::rule::prelude::*;
     pub use crate::state::{FriendFunc, State};
+    pub use crate::summary::{Multiplier, Summary};
 }
@@ -1,22 +1,15 @@
 use crate::card::{Card, Pattern, Rush};
 use crate::rule::{card_policy::CardPolicy, election, Rule};
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "server")]
+#[cfg(any(feature = "client", feature = "server"))]
 use {
     crate::card::Color,
     crate::command::Command,
     crate::error::{Error, Result},
     crate::rule::friend,
-    rand::seq::SliceRandom,
+    rand::{seq::SliceRandom, Rng},
     std::cmp::Ordering,
 };
-/*#[cfg(any(feature = "client", feature = "server"))]
-use {
-    crate::card::Color,
-    crate::command::Command,
-    crate::error::{Error, Result},
-    crate::rule::friend,
-};*/
 
 #[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq, Hash)]
 pub enum FriendFunc {
@@ -89,15 +82,18 @@ pub enum State {
         score: u8,
         pledge: u8,
         giruda: Option<Pattern>,
+        // point cards taken by each user
+        #[serde(default)]
+        score_deck: Vec<Vec<Card>>,
     },
 }
 
 impl State {
-    #[cfg(feature = "server")]
-    fn get_random_deck(rule: &Rule) -> Vec<Vec<Card>> {
+    #[cfg(any(feature = "client", feature = "server"))]
+    fn get_random_deck<R: Rng + ?Sized>(rule: &Rule, rng: &mut R) -> Vec<Vec<Card>> {
         loop {
             let mut deck = rule.deck.0.clone();
-            deck.shuffle(&mut rand::thread_rng());
+            deck.shuffle(rng);
             let deck = deck
                 .chunks(rule.card_cnt_per_user as usize)
                 .map(|v| v.to_vec())
@@ -119,7 +115,7 @@ impl State {
     }
 
     /*
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn is_joker_called(&self) -> bool {
         if let State::InGame { joker_call_card, .. } = self {
             *joker_call_card != None
@@ -128,7 +124,7 @@ impl State {
         }
     }
     */
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn get_current_pattern(&self) -> Rush {
         match self {
             State::InGame { current_pattern, .. } => *current_pattern,
@@ -136,7 +132,7 @@ impl State {
         }
     }
 
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn get_giruda(&self) -> Option<Pattern> {
         match self {
             State::SelectFriend { giruda, .. } => *giruda,
@@ -146,8 +142,7 @@ impl State {
         }
     }
 
-    #[cfg(feature = "server")]
-    //#[cfg(any(feature = "client", feature = "server"))]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn get_mighty(&self) -> Card {
         match self.get_giruda() {
             Some(Pattern::Spade) => Card::Normal(Pattern::Diamond, 14),
@@ -155,8 +150,7 @@ impl State {
         }
     }
 
-    #[cfg(feature = "server")]
-    //#[cfg(any(feature = "client", feature = "server"))]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn check_card_valid(&self, c: (CardPolicy, CardPolicy)) -> bool {
         match self {
             State::InGame {
@@ -175,7 +169,7 @@ impl State {
         }
     }
 
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn check_card_effect(&self, c: (CardPolicy, CardPolicy)) -> bool {
         match self {
             State::InGame { turn_count, .. } => {
@@ -186,7 +180,7 @@ impl State {
     }
 
     /// Winner when no mighty, giruda, joker is valid
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn minor_card_winner(&self, rule: &Rule, cards: &[Card]) -> Card {
         let cur_pat = self.get_current_pattern();
 
@@ -219,7 +213,7 @@ impl State {
     }
 
     /// Winner when no mighty, joker is valid
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn pattern_winner(pat: Pattern, cards: &[Card]) -> Option<Card> {
         cards
             .iter()
@@ -232,7 +226,7 @@ impl State {
     }
 
     /// Calculating winner of cards
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     pub fn calculate_winner(&self, rule: &Rule, cards: &[Card]) -> Card {
         let mighty = self.get_mighty();
         if cards.contains(&mighty) {
@@ -284,9 +278,16 @@ impl State {
 }
 
 impl State {
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     pub fn new(rule: &Rule) -> State {
-        let mut deck = State::get_random_deck(rule);
+        State::new_with_rng(rule, &mut rand::thread_rng())
+    }
+
+    /// Deals a new game with the given random number generator.
+    /// Same generator state gives the same deal, which makes games reproducible.
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub fn new_with_rng<R: Rng + ?Sized>(rule: &Rule, rng: &mut R) -> State {
+        let mut deck = State::get_random_deck(rule, rng);
         let left = deck.pop().unwrap();
         State::Election {
             pledge: vec![None; 5],
@@ -298,8 +299,21 @@ impl State {
         }
     }
 
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     pub fn next(&self, user_id: usize, cmd: Command, rule: &Rule) -> Result<Self> {
+        self.next_with_rng(user_id, cmd, rule, &mut rand::thread_rng())
+    }
+
+    /// Same as `next`, but every random choice (president on a tie, random commands)
+    /// is drawn from `rng`.
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub fn next_with_rng<R: Rng + ?Sized>(
+        &self,
+        user_id: usize,
+        cmd: Command,
+        rule: &Rule,
+        rng: &mut R,
+    ) -> Result<Self> {
         match self {
             State::Election {
                 pledge,
@@ -405,11 +419,15 @@ impl State {
                                         }
                                     }
                                 }
+                                // everyone passed: same as the ordered election, anyone can be the president
+                                if candidate.is_empty() {
+                                    candidate = (0..rule.user_cnt as usize).collect();
+                                }
                             }
                             if is_election_done {
                                 let mut deck = deck.clone();
                                 let mut left = left.clone();
-                                let president = candidate.choose(&mut rand::thread_rng()).copied().unwrap();
+                                let president = candidate.choose(&mut *rng).copied().unwrap();
                                 let mut pledge = pledge[president];
                                 if last_max == 0 {
                                     let mut pledge_vec = vec![
@@ -422,7 +440,7 @@ impl State {
                                         pledge_vec
                                             .push((None, (rule.pledge.min as i8 + rule.pledge.no_giruda_offset) as u8));
                                     }
-                                    pledge = Some(pledge_vec.choose(&mut rand::thread_rng()).copied().unwrap());
+                                    pledge = Some(pledge_vec.choose(&mut *rng).copied().unwrap());
                                 }
                                 deck[president].append(&mut left);
                                 Ok(State::SelectFriend {
@@ -444,7 +462,7 @@ impl State {
                         }
                     }
                 }
-                Command::Random => self.next(user_id, Command::Pledge(None), rule),
+                Command::Random => self.next_with_rng(user_id, Command::Pledge(None), rule, rng),
                 _ => Err(Error::InvalidCommand("Command::Pledge")),
             },
             State::SelectFriend {
@@ -533,12 +551,12 @@ impl State {
                         deck: deck.clone(),
                     })
                 }
-                Command::Random => self.next(
+                Command::Random => self.next_with_rng(
                     user_id,
                     Command::SelectFriend(
                         deck[user_id]
                             .choose_multiple(
-                                &mut rand::thread_rng(),
+                                &mut *rng,
                                 deck[user_id].len() - deck[(user_id + 1) % (rule.user_cnt as usize)].len(),
                             )
                             .cloned()
@@ -546,6 +564,7 @@ impl State {
                         FriendFunc::None,
                     ),
                     rule,
+                    rng,
                 ),
                 _ => Err(Error::InvalidCommand("Command::Pledge")),
             },
@@ -798,6 +817,7 @@ impl State {
                                 score,
                                 pledge,
                                 giruda: *giruda,
+                                score_deck,
                             });
                         }
                     }
@@ -821,8 +841,13 @@ impl State {
                     })
                 }
                 Command::Random => {
-                    let rand_card = deck[user_id].choose(&mut rand::thread_rng()).unwrap();
-                    self.next(user_id, Command::Go(*rand_card, Rush::from(*rand_card), false), rule)
+                    let rand_card = deck[user_id].choose(&mut *rng).unwrap();
+                    self.next_with_rng(
+                        user_id,
+                        Command::Go(*rand_card, Rush::from(*rand_card), false),
+                        rule,
+                        rng,
+                    )
                 }
                 _ => Err(Error::InvalidCommand("BasicCommand::Go")),
             },
@@ -1080,6 +1105,48 @@ impl State {
             _ => 0,
         }
     }
+
+    /// State which only shows the hands of users in `visible` bitmask.
+    /// Cards left in election and the friend not known yet are hidden too.
+    pub fn masked(&self, visible: u8) -> State {
+        let hide = |deck: &mut Vec<Vec<Card>>| {
+            for (i, d) in deck.iter_mut().enumerate() {
+                if visible & (1 << i) == 0 {
+                    d.clear();
+                }
+            }
+        };
+        let mut state = self.clone();
+        match &mut state {
+            State::Election { deck, left, .. } => {
+                hide(deck);
+                left.clear();
+            }
+            State::SelectFriend { deck, .. } => hide(deck),
+            State::InGame {
+                deck,
+                friend,
+                is_friend_known,
+                ..
+            } => {
+                hide(deck);
+                if !*is_friend_known && friend.is_none_or(|f| visible & (1 << f) == 0) {
+                    *friend = None;
+                }
+            }
+            State::GameEnded { .. } => {}
+        }
+        state
+    }
+
+    /// Count of tricks finished
+    pub fn trick_count(&self) -> u8 {
+        match self {
+            State::InGame { turn_count, .. } => *turn_count,
+            State::GameEnded { .. } => 10,
+            _ => 0,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1276,6 +1343,32 @@ mod test {
         }
     }
 
+    #[cfg(feature = "server")]
+    #[test]
+    fn unordered_all_pass_test() {
+        let rule = Rule::from(Preset::Gshs5);
+        assert!(!rule.election.contains(election::Election::ORDERED));
+        for order in [[0, 1, 2, 3, 4], [3, 1, 4, 0, 2]] {
+            let mut state = State::new(&rule);
+            for user_id in order {
+                state = state.next(user_id, Command::Pledge(None), &rule).unwrap();
+            }
+            match state {
+                State::SelectFriend {
+                    president,
+                    pledge,
+                    deck,
+                    ..
+                } => {
+                    assert!(president < 5);
+                    assert!(pledge >= (rule.pledge.min as i8 + rule.pledge.no_giruda_offset) as u8);
+                    assert!(deck[president].len() > deck[(president + 1) % 5].len());
+                }
+                _ => panic!("election should end when everyone passed"),
+            }
+        }
+    }
+
     #[cfg(feature = "server")]
     #[test]
     fn next_gshs5_test1() {
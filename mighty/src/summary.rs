@@ -0,0 +1,160 @@
+use crate::card::{Card, Pattern};
+use crate::rule::Rule;
+use crate::state::{FriendFunc, State};
+use serde::{Deserialize, Serialize};
+
+/// Multipliers applied to the score of the game
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub enum Multiplier {
+    /// played without giruda
+    NoGiruda,
+    /// played without friend
+    NoFriend,
+    /// ruling party took every point card
+    Run,
+    /// ruling party took half of the point cards or less
+    BackRun,
+}
+
+/// What happened in the game, made after the game is ended
+#[derive(Debug, Clone, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub struct Summary {
+    /// bitmask of winners
+    pub winner: u8,
+    pub president: usize,
+    pub friend: Option<usize>,
+    /// how the friend was called
+    pub friend_func: FriendFunc,
+    pub score: u8,
+    pub pledge: u8,
+    pub giruda: Option<Pattern>,
+    /// dealt hands of each user
+    pub hands: Vec<Vec<Card>>,
+    /// cards left after dealing
+    pub left: Vec<Card>,
+    /// point cards taken by each user
+    pub points: Vec<Vec<Card>>,
+    pub multipliers: Vec<Multiplier>,
+}
+
+impl Summary {
+    /// Makes the summary from every state of the game.
+    /// Returns `None` if the game is not ended.
+    pub fn from_states(states: &[State], rule: &Rule) -> Option<Summary> {
+        let (winner, president, friend, score, pledge, giruda, points) = match states.last()? {
+            State::GameEnded {
+                winner,
+                president,
+                friend,
+                score,
+                pledge,
+                giruda,
+                score_deck,
+            } => (
+                *winner,
+                *president,
+                *friend,
+                *score,
+                *pledge,
+                *giruda,
+                score_deck.clone(),
+            ),
+            _ => return None,
+        };
+        let (hands, left) = match states.first()? {
+            State::Election { deck, left, .. } => (deck.clone(), left.clone()),
+            _ => (Vec::new(), Vec::new()),
+        };
+        let friend_func = states
+            .iter()
+            .rev()
+            .find_map(|state| match state {
+                State::InGame { friend_func, .. } => Some(friend_func.clone()),
+                _ => None,
+            })
+            .unwrap_or(FriendFunc::None);
+
+        let ruling = points
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i == president || Some(*i) == friend)
+            .map(|(_, v)| v.len())
+            .sum::<usize>();
+        let total = rule.deck.0.iter().filter(|card| card.is_score()).count();
+        let mut multipliers = Vec::new();
+        if giruda.is_none() {
+            multipliers.push(Multiplier::NoGiruda);
+        }
+        if friend_func == FriendFunc::None {
+            multipliers.push(Multiplier::NoFriend);
+        }
+        if ruling == total {
+            multipliers.push(Multiplier::Run);
+        }
+        if winner & (1 << president) == 0 && ruling * 2 <= total {
+            multipliers.push(Multiplier::BackRun);
+        }
+
+        Some(Summary {
+            winner,
+            president,
+            friend,
+            friend_func,
+            score,
+            pledge,
+            giruda,
+            hands,
+            left,
+            points,
+            multipliers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rule::prelude::{DeckBuilder, DeckPreset, Preset};
+
+    /// Run and back-run multipliers of the ended game
+    fn runs(won: bool, ruling: usize, opposite: usize, rule: &Rule) -> Vec<Multiplier> {
+        let points = |n: usize| {
+            (0..n)
+                .map(|i| Card::Normal(Pattern::Spade, 10 + (i % 5) as u8))
+                .collect::<Vec<_>>()
+        };
+        let states = vec![State::GameEnded {
+            winner: if won { 0b00011 } else { 0b11100 },
+            president: 0,
+            friend: Some(1),
+            score: 0,
+            pledge: 13,
+            giruda: Some(Pattern::Spade),
+            score_deck: vec![points(ruling), Vec::new(), points(opposite), Vec::new(), Vec::new()],
+        }];
+        Summary::from_states(&states, rule)
+            .unwrap()
+            .multipliers
+            .into_iter()
+            .filter(|m| matches!(m, Multiplier::Run | Multiplier::BackRun))
+            .collect()
+    }
+
+    #[test]
+    fn multiplier_test() {
+        let rule = Rule::from(Preset::Gshs5);
+        assert_eq!(runs(true, 20, 0, &rule), vec![Multiplier::Run]);
+        assert_eq!(runs(false, 10, 10, &rule), vec![Multiplier::BackRun]);
+
+        // without the tens, there are only 16 point cards
+        let deck = [Pattern::Spade, Pattern::Diamond, Pattern::Heart, Pattern::Clover]
+            .iter()
+            .fold(DeckBuilder::from(DeckPreset::FullDeck), |deck, p| {
+                deck.remove(&Card::Normal(*p, 10))
+            });
+        let rule = rule.set_deck(deck.build());
+        assert_eq!(runs(true, 16, 0, &rule), vec![Multiplier::Run]);
+        assert!(runs(false, 9, 7, &rule).is_empty());
+        assert_eq!(runs(false, 8, 8, &rule), vec![Multiplier::BackRun]);
+    }
+}
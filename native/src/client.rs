@@ -0,0 +1,196 @@
+use crate::connection::Connection;
+use anyhow::{bail, ensure, Result};
+use mighty::prelude::Rule;
+use reqwest::header::SET_COOKIE;
+use serde::Serialize;
+use sha2::{Digest, Sha512};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::{HeaderValue, COOKIE};
+use types::{
+    ListToClient, ListToServer, MainToClient, MainToServer, ObserveToClient, ObserveToServer, RoomId, RoomInfo,
+    RoomKey, RoomUserToClient, RoomUserToServer, UserInfo,
+};
+
+/// Hashes the password the same way as the web page does before sending it.
+pub fn hash_password(password: &str) -> String {
+    hex::encode(Sha512::digest(password.as_bytes()))
+}
+
+#[derive(Debug, Serialize)]
+struct LoginForm<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<&'a str>,
+    password: String,
+}
+
+/// Logged in user of the server.
+///
+/// Only plain `http://` servers are supported; the address of websocket
+/// is made by changing the scheme to `ws://`.
+#[derive(Debug, Clone)]
+pub struct Client {
+    url: String,
+    cookie: String,
+}
+
+impl Client {
+    /// Logs in with user id and raw password.
+    pub async fn login(url: &str, user_id: &str, password: &str) -> Result<Client> {
+        Client::login_with(
+            url,
+            LoginForm {
+                user_id: Some(user_id),
+                email: None,
+                password: hash_password(password),
+            },
+        )
+        .await
+    }
+
+    /// Logs in with email and raw password.
+    pub async fn login_with_email(url: &str, email: &str, password: &str) -> Result<Client> {
+        Client::login_with(
+            url,
+            LoginForm {
+                user_id: None,
+                email: Some(email),
+                password: hash_password(password),
+            },
+        )
+        .await
+    }
+
+    async fn login_with(url: &str, form: LoginForm<'_>) -> Result<Client> {
+        let url = url.trim_end_matches('/').to_owned();
+        ensure!(url.starts_with("http://"), "only http is supported: {}", url);
+
+        let res = reqwest::Client::new()
+            .post(format!("{}/login", url))
+            .json(&form)
+            .send()
+            .await?;
+        ensure!(res.status().is_success(), "failed to login: {}", res.status());
+
+        let cookie = res
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| v.split(';').next())
+            .collect::<Vec<_>>()
+            .join("; ");
+        ensure!(!cookie.is_empty(), "no cookie from the server");
+
+        Ok(Client { url, cookie })
+    }
+
+    /// Connection for user status and joining rooms.
+    pub async fn main(&self) -> Result<Connection<MainToServer, MainToClient>> {
+        self.connect("/ws/main").await
+    }
+
+    /// Connection for the list of rooms.
+    pub async fn list(&self) -> Result<Connection<ListToServer, ListToClient>> {
+        self.connect("/ws/list").await
+    }
+
+    /// Connection for the joined room.
+    /// Join the room with `MainToServer::Join` first, or use `join` instead.
+    pub async fn room(&self) -> Result<Connection<RoomUserToServer, RoomUserToClient>> {
+        self.connect("/ws/room").await
+    }
+
+    /// Connection for observing the room.
+    pub async fn observe(&self, room_id: RoomId) -> Result<Connection<ObserveToServer, ObserveToClient>> {
+        self.connect(&format!("/ws/observe/{}", room_id.0)).await
+    }
+
+    /// Information of the logged in user.
+    pub async fn user_info(&self, main: &mut Connection<MainToServer, MainToClient>) -> Result<UserInfo> {
+        main.send(&MainToServer::GetInfo).await?;
+        main.wait_for(|msg| match msg {
+            MainToClient::UserInfo(info) => Some(info.clone()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Joins to the room and connects to it.
+    pub async fn join(
+        &self,
+        main: &mut Connection<MainToServer, MainToClient>,
+        room_id: RoomId,
+    ) -> Result<(RoomInfo, Connection<RoomUserToServer, RoomUserToClient>)> {
+        main.send(&MainToServer::Join(room_id)).await?;
+        self.wait_joined(main).await
+    }
+
+    /// Joins to the private room with password or invite token and connects to it.
+    pub async fn join_with(
+        &self,
+        main: &mut Connection<MainToServer, MainToClient>,
+        room_id: RoomId,
+        key: RoomKey,
+    ) -> Result<(RoomInfo, Connection<RoomUserToServer, RoomUserToClient>)> {
+        main.send(&MainToServer::JoinWith(room_id, key)).await?;
+        self.wait_joined(main).await
+    }
+
+    /// Makes new room, joins to it and connects to it.
+    pub async fn make_room(
+        &self,
+        main: &mut Connection<MainToServer, MainToClient>,
+        name: &str,
+        rule: Rule,
+        is_rank: bool,
+    ) -> Result<(RoomInfo, Connection<RoomUserToServer, RoomUserToClient>)> {
+        main.send(&MainToServer::MakeRoom(name.to_owned(), Box::new(rule), is_rank))
+            .await?;
+        self.wait_joined(main).await
+    }
+
+    async fn wait_joined(
+        &self,
+        main: &mut Connection<MainToServer, MainToClient>,
+    ) -> Result<(RoomInfo, Connection<RoomUserToServer, RoomUserToClient>)> {
+        // server responds with `UserInfo` when it couldn't join
+        let info = main
+            .wait_for(|msg| match msg {
+                MainToClient::Joined(info) => Some(Some(info.clone())),
+                MainToClient::UserInfo(_) => Some(None),
+                _ => None,
+            })
+            .await?;
+        match info {
+            Some(info) => Ok((info, self.room().await?)),
+            None => bail!("failed to join the room"),
+        }
+    }
+
+    async fn connect<S, R>(&self, path: &str) -> Result<Connection<S, R>>
+    where
+        S: Serialize,
+        R: serde::de::DeserializeOwned,
+    {
+        let mut req = format!("ws{}{}", &self.url[4..], path).into_client_request()?;
+        req.headers_mut().insert(COOKIE, HeaderValue::from_str(&self.cookie)?);
+        let (stream, _) = tokio_tungstenite::connect_async(req).await?;
+        Ok(Connection::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_password_test() {
+        assert_eq!(
+            hash_password("password"),
+            "b109f3bbbc244eb82441917ed06d618b9008dd09b3befd1b5e07394c706a8bb9\
+             80b1d7785e5976ec049b46df5f1326af5a2ea6d103fd07c95385ffab0cacbc86"
+        );
+    }
+}
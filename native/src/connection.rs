@@ -0,0 +1,197 @@
+use anyhow::{bail, Result};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Websocket connection sending `S` and receiving `R`.
+///
+/// Every message is json text, same as the wasm client.
+/// Use `split` to send and receive in different tasks.
+pub struct Connection<S, R> {
+    sender: Sender<S>,
+    receiver: Receiver<R>,
+}
+
+/// Sending half of `Connection`.
+pub struct Sender<S> {
+    sink: SplitSink<Stream, Message>,
+    _marker: PhantomData<S>,
+}
+
+/// Receiving half of `Connection`.
+///
+/// Messages skipped by `wait_for` are kept and returned by `recv` in order.
+pub struct Receiver<R> {
+    stream: SplitStream<Stream>,
+    pending: VecDeque<R>,
+}
+
+impl<S: Serialize, R: DeserializeOwned> Connection<S, R> {
+    pub(crate) fn new(stream: Stream) -> Connection<S, R> {
+        let (sink, stream) = stream.split();
+        Connection {
+            sender: Sender {
+                sink,
+                _marker: PhantomData,
+            },
+            receiver: Receiver {
+                stream,
+                pending: VecDeque::new(),
+            },
+        }
+    }
+
+    pub async fn send(&mut self, msg: &S) -> Result<()> {
+        self.sender.send(msg).await
+    }
+
+    pub async fn recv(&mut self) -> Result<Option<R>> {
+        self.receiver.recv().await
+    }
+
+    pub async fn wait_for<T, F: FnMut(&R) -> Option<T>>(&mut self, f: F) -> Result<T> {
+        self.receiver.wait_for(f).await
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        self.sender.close().await
+    }
+
+    pub fn split(self) -> (Sender<S>, Receiver<R>) {
+        (self.sender, self.receiver)
+    }
+}
+
+impl<S: Serialize> Sender<S> {
+    pub async fn send(&mut self, msg: &S) -> Result<()> {
+        self.sink.send(Message::Text(serde_json::to_string(msg)?)).await?;
+        Ok(())
+    }
+
+    pub async fn close(&mut self) -> Result<()> {
+        self.sink.send(Message::Close(None)).await?;
+        Ok(())
+    }
+}
+
+impl<R: DeserializeOwned> Receiver<R> {
+    /// Next message from the server, or `None` if the connection is closed.
+    /// Pings are answered by tungstenite while reading.
+    pub async fn recv(&mut self) -> Result<Option<R>> {
+        if let Some(msg) = self.pending.pop_front() {
+            return Ok(Some(msg));
+        }
+        self.read().await
+    }
+
+    /// Waits until `f` returns `Some` for a message.
+    /// Other messages are kept for `recv`.
+    pub async fn wait_for<T, F: FnMut(&R) -> Option<T>>(&mut self, mut f: F) -> Result<T> {
+        let mut skipped = VecDeque::new();
+        let res = loop {
+            let msg = match self.pending.pop_front() {
+                Some(msg) => msg,
+                None => match self.read().await? {
+                    Some(msg) => msg,
+                    None => {
+                        self.pending = skipped;
+                        bail!("connection closed");
+                    }
+                },
+            };
+            if let Some(res) = f(&msg) {
+                break res;
+            }
+            skipped.push_back(msg);
+        };
+        skipped.append(&mut self.pending);
+        self.pending = skipped;
+        Ok(res)
+    }
+
+    async fn read(&mut self) -> Result<Option<R>> {
+        while let Some(msg) = self.stream.next().await {
+            match msg? {
+                Message::Text(text) => return Ok(Some(serde_json::from_str(&text)?)),
+                Message::Close(_) => return Ok(None),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::net::TcpListener;
+    use types::{MainToClient, MainToServer, UserNo, UserStatus};
+
+    async fn echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.send(Message::Text(
+                serde_json::to_string(&MainToClient::UserStatus(UserNo(2), UserStatus::ONLINE)).unwrap(),
+            ))
+            .await
+            .unwrap();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                if let MainToServer::Subscribe(no) = serde_json::from_str(&text).unwrap() {
+                    let msg = MainToClient::UserStatus(no, UserStatus::OFFLINE);
+                    ws.send(Message::Text(serde_json::to_string(&msg).unwrap()))
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+        format!("ws://{}", addr)
+    }
+
+    async fn connect() -> Connection<MainToServer, MainToClient> {
+        let (stream, _) = tokio_tungstenite::connect_async(echo_server().await).await.unwrap();
+        Connection::new(stream)
+    }
+
+    #[tokio::test]
+    async fn send_recv_test() {
+        let mut conn = connect().await;
+        conn.send(&MainToServer::Subscribe(UserNo(3))).await.unwrap();
+        assert!(matches!(
+            conn.recv().await.unwrap(),
+            Some(MainToClient::UserStatus(UserNo(2), _))
+        ));
+        assert!(matches!(
+            conn.recv().await.unwrap(),
+            Some(MainToClient::UserStatus(UserNo(3), _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn wait_for_keeps_skipped_test() {
+        let mut conn = connect().await;
+        conn.send(&MainToServer::Subscribe(UserNo(3))).await.unwrap();
+        let no = conn
+            .wait_for(|msg| match msg {
+                MainToClient::UserStatus(no, UserStatus::OFFLINE) => Some(*no),
+                _ => None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(no, UserNo(3));
+        assert!(matches!(
+            conn.recv().await.unwrap(),
+            Some(MainToClient::UserStatus(UserNo(2), _))
+        ));
+    }
+}
@@ -0,0 +1,26 @@
+//! Native client of web-mighty.
+//!
+//! Same protocol as the wasm client in `client`, but runs on tokio so that
+//! bots, load tests and terminal clients can connect to the server.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use native::Client;
+//! use types::{RoomUserToServer, RoomId};
+//!
+//! let client = Client::login("http://localhost:8080", "user", "password").await?;
+//! let mut main = client.main().await?;
+//! let (info, mut room) = client.join(&mut main, RoomId(1)).await?;
+//! room.send(&RoomUserToServer::Chat("hello".to_owned())).await?;
+//! while let Some(msg) = room.recv().await? {
+//!     println!("{:?}", msg);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod connection;
+
+pub use client::{hash_password, Client};
+pub use connection::{Connection, Receiver, Sender};
@@ -0,0 +1,96 @@
+import "./modules/wasm.js";
+
+const AUTOPLAY_INTERVAL = 1000;
+const ANALYSIS_RETRY = 5000;
+
+function describe(flag) {
+    return `move ${flag.pos + 1}, seat ${flag.user}: ${flag.severity} ${flag.kind} (-${flag.cost.toFixed(1)}), ` +
+        `played ${JSON.stringify(flag.played)}, better ${JSON.stringify(flag.best)}`;
+}
+
+// analysis is made in the background, so it retries while the server responds 202
+async function loadAnalysis(gameId) {
+    const res = await fetch(`/api/analysis/${gameId}`);
+    if (res.status === 202) {
+        document.getElementById("analysis").textContent = "analyzing...";
+        await new Promise((resolve) => setTimeout(resolve, ANALYSIS_RETRY));
+        return loadAnalysis(gameId);
+    }
+    return res.ok ? res.json() : [];
+}
+
+function render(view) {
+    document.getElementById("phase").textContent = view.phase;
+    document.getElementById("hand").textContent = JSON.stringify(view.hand);
+    document.getElementById("trick").textContent = JSON.stringify(view.trick);
+    document.getElementById("points").textContent = JSON.stringify(view.points);
+}
+
+window.addEventListener("load", async () => {
+    const wasm = await import("./pkg");
+    const root = document.getElementById("replay");
+    const gameId = root.dataset.gameId;
+    const data = await fetch(`/api/replay/${gameId}`).then((res) => res.json());
+    const replay = new wasm.Replay(data, parseInt(root.dataset.start) || 0);
+
+    const slider = document.getElementById("move");
+    slider.max = replay.move_count - 1;
+    const play = document.getElementById("play");
+    let timer = null;
+
+    let flags = [];
+    replay.on("game_view", (view) => {
+        render(view);
+        slider.value = replay.pos;
+        document.getElementById("flag").textContent = flags
+            .filter((flag) => flag.pos === replay.pos)
+            .map(describe)
+            .join("\n");
+    });
+
+    loadAnalysis(gameId).then((result) => {
+        flags = result;
+        const list = document.getElementById("analysis");
+        list.textContent = flags.length === 0 ? "no mistakes found" : "";
+        for (const flag of flags) {
+            const item = document.createElement("li");
+            item.textContent = describe(flag);
+            item.addEventListener("click", () => replay.jump(flag.pos));
+            list.appendChild(item);
+        }
+    });
+
+    const stop = () => {
+        replay.pause();
+        clearInterval(timer);
+        timer = null;
+        play.textContent = "play";
+    };
+
+    document.getElementById("seat").addEventListener("change", (e) => replay.set_seat(parseInt(e.target.value)));
+    document.getElementById("prev").addEventListener("click", () => replay.backward());
+    document.getElementById("next").addEventListener("click", () => replay.forward());
+    slider.addEventListener("input", () => replay.jump(parseInt(slider.value)));
+    document.getElementById("trick-select").addEventListener("change", (e) => {
+        replay.jump_to_trick(parseInt(e.target.value));
+    });
+    play.addEventListener("click", () => {
+        if (replay.playing) {
+            stop();
+            return;
+        }
+        replay.play();
+        play.textContent = "pause";
+        timer = setInterval(() => {
+            replay.tick();
+            if (!replay.playing) {
+                stop();
+            }
+        }, AUTOPLAY_INTERVAL);
+    });
+    document.getElementById("share").addEventListener("click", () => {
+        const link = document.getElementById("link");
+        link.value = replay.share_link();
+        link.select();
+    });
+});
@@ -0,0 +1,75 @@
+use crate::db::analysis::{has_analysis, save_analysis};
+use crate::db::game::{game_line, get_game_rule, get_states};
+use crate::dev::*;
+use actix::prelude::*;
+use mighty::analysis::analyze;
+use mighty::prelude::State;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Analyzer Actor
+///
+/// This runs on `SyncArbiter` threads, since the analysis takes seconds for a game.
+/// Rooms send the finished games through `Hub`, so they are never blocked by the analysis.
+#[derive(Debug)]
+pub struct Analyzer {
+    pool: Pool,
+}
+
+impl Actor for Analyzer {
+    type Context = SyncContext<Self>;
+}
+
+/// Analyzes the finished game and saves the flags, if not analyzed yet.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Analyze(pub GameId);
+
+impl Handler<Analyze> for Analyzer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Analyze, _: &mut Self::Context) -> Self::Result {
+        if let Err(e) = self.analyze(&msg.0.to_string()) {
+            log::warn!("failed to analyze game {}: {:?}", msg.0, e);
+        }
+    }
+}
+
+impl Analyzer {
+    pub fn new(pool: Pool) -> Analyzer {
+        Analyzer { pool }
+    }
+
+    fn analyze(&self, game_id: &str) -> Result<()> {
+        if has_analysis(game_id, self.pool.clone())? {
+            return Ok(());
+        }
+        let rule = get_game_rule(game_id, self.pool.clone())?;
+        let saved = get_states(game_id, self.pool.clone())?;
+        // states taken back are not the line of the game, but they are kept in the replay
+        let line = game_line(&saved)
+            .into_iter()
+            .map(|i| (i, &saved[i]))
+            .collect::<Vec<_>>();
+        if !matches!(line.last(), Some((_, s)) if matches!(s.state, State::GameEnded { .. })) {
+            return Ok(());
+        }
+
+        let states = line.iter().map(|(_, s)| s.state.clone()).collect::<Vec<_>>();
+        let mut hasher = DefaultHasher::new();
+        game_id.hash(&mut hasher);
+        let mut rng = StdRng::seed_from_u64(hasher.finish());
+        let flags = analyze(&states, &rule, ANALYSIS_SAMPLES, &mut rng)
+            .into_iter()
+            // moves made by bot for absent user are not the decisions of the user
+            .filter(|flag| !line[flag.pos + 1].1.by_ghost)
+            .map(|mut flag| {
+                flag.pos = line[flag.pos].0;
+                flag
+            })
+            .collect::<Vec<_>>();
+        save_analysis(game_id, &flags, self.pool.clone())
+    }
+}
@@ -1,13 +1,19 @@
-use crate::actor::room::Room;
-use crate::actor::user::User;
-use crate::db::game::{save_rule, SaveRuleForm};
+use crate::actor::analyzer::{Analyze, Analyzer};
+use crate::actor::maintainer::{IndexGames, MaintainSeason, Maintainer};
+use crate::actor::recorder::{Recorder, RemoveSnapshot};
+use crate::actor::room::{self, Room};
+use crate::actor::user::{ChangeRating, User, UserRejoin};
+use crate::config::Ranked;
+use crate::db::game::{get_room_snapshots, make_room, save_rule, MakeRoomForm, SaveRuleForm};
 use crate::db::user::{get_user_info, GetInfoForm};
 use crate::dev::*;
+use crate::season;
 use actix::prelude::*;
+use futures::future::join_all;
 use mighty::prelude::Rule;
 use rand::distributions::{Distribution, Uniform};
 use rand::thread_rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Hub Actor
 ///
@@ -18,11 +24,51 @@ pub struct Hub {
     room: HashMap<RoomId, Addr<Room>>,
     counter: u64,
     users: HashMap<UserNo, Addr<User>>,
+    restored: HashMap<UserNo, RoomId>,
+    closing: bool,
     pool: Pool,
+    secret: String,
+    ranked: Ranked,
+    analyzer: Addr<Analyzer>,
+    // games queued to the analyzer and not analyzed yet
+    analyzing: HashSet<GameId>,
+    recorder: Addr<Recorder>,
+    maintainer: Addr<Maintainer>,
 }
 
 impl Actor for Hub {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.maintainer.do_send(MaintainSeason);
+        self.maintainer.do_send(IndexGames);
+        ctx.run_interval(SEASON_CHECK_INTERVAL, |act, _| {
+            act.maintainer.do_send(MaintainSeason);
+        });
+
+        let snapshots = match get_room_snapshots(self.pool.clone()) {
+            Ok(snapshots) => snapshots,
+            Err(_) => return,
+        };
+        for snapshot in snapshots {
+            let room_id = snapshot.info.id;
+            for user_no in snapshot.info.user.iter().chain(snapshot.info.standing.iter()) {
+                if user_no.0 != 0 {
+                    self.restored.insert(*user_no, room_id);
+                }
+            }
+            let room = Room::restore(
+                snapshot,
+                ctx.address(),
+                self.recorder.clone(),
+                self.pool.clone(),
+                self.secret.clone(),
+                self.ranked,
+            )
+            .start();
+            self.room.insert(room_id, room);
+        }
+    }
 }
 
 /// This would request the address of room.
@@ -41,37 +87,46 @@ impl Handler<GetRoom> for Hub {
 
 /// This would make room with `room_name`, `rule`, and `is_rank`.
 /// The `room_id` would generated with random value.
+/// Ranked rooms can only be made with the rules of `RANKED_PRESETS`.
 #[derive(Debug, Clone, Message)]
-#[rtype(result = "RoomId")]
+#[rtype(result = "Result<RoomId>")]
 pub struct MakeRoom(pub String, pub Rule, pub bool);
 
 impl Handler<MakeRoom> for Hub {
-    type Result = RoomId;
+    type Result = Result<RoomId>;
 
     fn handle(&mut self, msg: MakeRoom, ctx: &mut Self::Context) -> Self::Result {
+        ensure!(
+            !msg.2 || season::is_ranked_rule(&msg.1),
+            StatusCode::BAD_REQUEST,
+            "rule is not allowed in ranked rooms"
+        );
         let room_uuid = RoomUid::generate_random();
         let room_id = self.generate_room_id();
         let user_cnt = msg.1.user_cnt as usize;
         let rule = RuleHash::generate(&msg.1);
         let _ = save_rule(&SaveRuleForm { rule: msg.1.clone() }, self.pool.clone());
-        let room = Room::new(
-            RoomInfo {
+        let _ = make_room(
+            &MakeRoomForm {
                 uid: room_uuid,
                 id: room_id,
-                name: msg.0,
+                name: msg.0.clone(),
                 rule,
-                is_rank: msg.2,
-                head: UserNo(0),
-                user: vec![UserNo(0); user_cnt],
-                observer_cnt: 0,
-                is_game: false,
             },
+            self.pool.clone(),
+        );
+        let room = Room::new(
+            RoomInfo::new(room_uuid, room_id, msg.0, rule, msg.2, user_cnt),
+            msg.1,
             ctx.address(),
+            self.recorder.clone(),
             self.pool.clone(),
+            self.secret.clone(),
+            self.ranked,
         )
         .start();
         self.room.insert(room_id, room);
-        room_id
+        Ok(room_id)
     }
 }
 
@@ -85,6 +140,11 @@ impl Handler<RemoveRoom> for Hub {
 
     fn handle(&mut self, msg: RemoveRoom, _: &mut Self::Context) -> Self::Result {
         self.room.remove(&msg.0);
+        self.restored.retain(|_, room_id| *room_id != msg.0);
+        if self.closing {
+            return;
+        }
+        self.recorder.do_send(RemoveSnapshot(msg.0));
     }
 }
 
@@ -102,7 +162,10 @@ impl Handler<HubConnect> for Hub {
             Ok(addr.clone())
         } else {
             let user_info = get_user_info(&GetInfoForm::UserNo(msg.0 .0), self.pool.clone())?;
-            let user = User::new(user_info, ctx.address(), self.pool.clone()).start();
+            let user = User::new(user_info, ctx.address()).start();
+            if let Some(room) = self.restored.remove(&msg.0).and_then(|id| self.room.get(&id)) {
+                user.do_send(UserRejoin(room.clone()));
+            }
             self.users.insert(msg.0, user.clone());
             Ok(user)
         }
@@ -122,6 +185,49 @@ impl Handler<GetUser> for Hub {
     }
 }
 
+/// Tells the online users that their ratings are changed after the game,
+/// even if they already left the room.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct NotifyRating(pub Vec<(UserNo, u32)>);
+
+impl Handler<NotifyRating> for Hub {
+    type Result = ();
+
+    fn handle(&mut self, msg: NotifyRating, _: &mut Self::Context) -> Self::Result {
+        for (user_no, rating) in msg.0 {
+            if let Some(addr) = self.users.get(&user_no) {
+                addr.do_send(ChangeRating(rating));
+            }
+        }
+    }
+}
+
+/// Sends the finished game to the analyzer running in the background.
+/// The game already queued is not sent again.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct AnalyzeGame(pub GameId);
+
+impl Handler<AnalyzeGame> for Hub {
+    type Result = ();
+
+    fn handle(&mut self, msg: AnalyzeGame, ctx: &mut Self::Context) -> Self::Result {
+        let game_id = msg.0;
+        if !self.analyzing.insert(game_id) {
+            return;
+        }
+        ctx.spawn(
+            self.analyzer
+                .send(Analyze(game_id))
+                .into_actor(self)
+                .map(move |_, act, _| {
+                    act.analyzing.remove(&game_id);
+                }),
+        );
+    }
+}
+
 /// When user gets offline, this would remove user.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
@@ -135,13 +241,42 @@ impl Handler<HubDisconnect> for Hub {
     }
 }
 
+/// Saves every room and tells the users that the server is going down.
+/// Rooms are restored when the server starts again.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Shutdown;
+
+impl Handler<Shutdown> for Hub {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _: Shutdown, _: &mut Self::Context) -> Self::Result {
+        self.closing = true;
+        let rooms = self.room.values().cloned().collect::<Vec<_>>();
+        Box::pin(async move {
+            join_all(rooms.iter().map(|room| room.send(room::Shutdown))).await;
+        })
+    }
+}
+
 impl Hub {
-    pub fn new(pool: Pool) -> Hub {
+    pub fn new(pool: Pool, secret: String, ranked: Ranked) -> Hub {
+        let analyzer_pool = pool.clone();
+        let recorder_pool = pool.clone();
+        let maintainer_pool = pool.clone();
         Hub {
+            analyzer: SyncArbiter::start(ANALYSIS_WORKERS, move || Analyzer::new(analyzer_pool.clone())),
+            analyzing: HashSet::new(),
+            recorder: SyncArbiter::start(1, move || Recorder::new(recorder_pool.clone())),
+            maintainer: SyncArbiter::start(1, move || Maintainer::new(maintainer_pool.clone())),
             room: HashMap::new(),
             counter: 0,
             users: HashMap::new(),
+            restored: HashMap::new(),
+            closing: false,
             pool,
+            secret,
+            ranked,
         }
     }
 
@@ -1,7 +1,8 @@
-use crate::actor::hub::GetUser;
+use crate::actor::hub::{GetUser, MakeRoom};
+use crate::actor::matchmaker::{Dequeue, Enqueue};
 use crate::actor::session::{Session, SessionTrait};
-use crate::actor::user::{Update, UserConnect, UserDisconnect};
-use crate::actor::{Hub, User};
+use crate::actor::user::{GetInfo, Update, UserConnect, UserDisconnect, UserJoin, UserLeave};
+use crate::actor::{Hub, Matchmaker, User};
 use actix::prelude::*;
 use actix_web_actors::ws::WebsocketContext;
 use types::{MainToClient, MainToServer};
@@ -10,6 +11,7 @@ use types::{MainToClient, MainToServer};
 pub struct Main {
     user: Addr<User>,
     hub: Addr<Hub>,
+    matchmaker: Addr<Matchmaker>,
 }
 
 impl SessionTrait for Main {
@@ -19,8 +21,9 @@ impl SessionTrait for Main {
         act.inner.user.do_send(UserConnect::Main);
     }
 
-    fn stopped(act: &mut Session<Self>, _: &mut WebsocketContext<Session<Self>>) {
+    fn stopped(act: &mut Session<Self>, ctx: &mut WebsocketContext<Session<Self>>) {
         act.inner.user.do_send(UserDisconnect::Main);
+        act.inner.matchmaker.do_send(Dequeue(ctx.address()));
     }
 
     fn receive(act: &mut Session<Self>, msg: String, ctx: &mut WebsocketContext<Session<Self>>) {
@@ -57,12 +60,72 @@ impl SessionTrait for Main {
             MainToServer::Update => {
                 act.inner.user.do_send(Update);
             }
+            MainToServer::GetInfo => {
+                act.inner
+                    .user
+                    .send(GetInfo)
+                    .into_actor(act)
+                    .then(|res, _, ctx| {
+                        if let Ok(info) = res {
+                            ctx.notify(MainToClient::UserInfo(info));
+                        }
+
+                        fut::ready(())
+                    })
+                    .wait(ctx);
+            }
+            MainToServer::Join(room_id) => {
+                act.inner.user.do_send(UserJoin(room_id, ctx.address(), None));
+            }
+            MainToServer::JoinWith(room_id, key) => {
+                act.inner.user.do_send(UserJoin(room_id, ctx.address(), Some(key)));
+            }
+            MainToServer::MakeRoom(name, rule, is_rank) => {
+                act.inner
+                    .hub
+                    .send(MakeRoom(name, *rule, is_rank))
+                    .into_actor(act)
+                    .then(|res, act, ctx| {
+                        if let Ok(Ok(room_id)) = res {
+                            act.inner.user.do_send(UserJoin(room_id, ctx.address(), None));
+                        }
+
+                        fut::ready(())
+                    })
+                    .wait(ctx);
+            }
+            MainToServer::Leave => {
+                act.inner.user.do_send(UserLeave);
+            }
+            MainToServer::Queue(preset, range) => {
+                act.inner
+                    .user
+                    .send(GetInfo)
+                    .into_actor(act)
+                    .then(move |res, act, ctx| {
+                        if let Ok(info) = res {
+                            act.inner.matchmaker.do_send(Enqueue {
+                                info,
+                                user: act.inner.user.clone(),
+                                main: ctx.address(),
+                                preset,
+                                range,
+                            });
+                        }
+
+                        fut::ready(())
+                    })
+                    .wait(ctx);
+            }
+            MainToServer::CancelQueue => {
+                act.inner.matchmaker.do_send(Dequeue(ctx.address()));
+            }
         }
     }
 }
 
 impl Main {
-    pub fn new(user: Addr<User>, hub: Addr<Hub>) -> Main {
-        Main { user, hub }
+    pub fn new(user: Addr<User>, hub: Addr<Hub>, matchmaker: Addr<Matchmaker>) -> Main {
+        Main { user, hub, matchmaker }
     }
 }
@@ -0,0 +1,55 @@
+use crate::analytics;
+use crate::dev::*;
+use crate::season;
+use actix::prelude::*;
+
+/// Maintainer Actor
+///
+/// This runs the jobs over every user or game on a `SyncArbiter` thread,
+/// since they can take long and hub should not be blocked by them.
+#[derive(Debug)]
+pub struct Maintainer {
+    pool: Pool,
+}
+
+impl Actor for Maintainer {
+    type Context = SyncContext<Self>;
+}
+
+/// Archives the ended seasons, starts a new season and decays the inactive users.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct MaintainSeason;
+
+impl Handler<MaintainSeason> for Maintainer {
+    type Result = ();
+
+    fn handle(&mut self, _: MaintainSeason, _: &mut Self::Context) -> Self::Result {
+        if let Err(e) = season::maintain(self.pool.clone()) {
+            log::warn!("failed to maintain the season: {:?}", e);
+        }
+    }
+}
+
+/// Indexes the finished games saved before the analytics.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct IndexGames;
+
+impl Handler<IndexGames> for Maintainer {
+    type Result = ();
+
+    fn handle(&mut self, _: IndexGames, _: &mut Self::Context) -> Self::Result {
+        match analytics::index_missing_games(self.pool.clone()) {
+            Ok(count) if count > 0 => log::info!("indexed {} games", count),
+            Ok(_) => {}
+            Err(e) => log::warn!("failed to index the games: {:?}", e),
+        }
+    }
+}
+
+impl Maintainer {
+    pub fn new(pool: Pool) -> Maintainer {
+        Maintainer { pool }
+    }
+}
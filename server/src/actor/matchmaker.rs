@@ -0,0 +1,345 @@
+use crate::actor::hub::MakeRoom;
+use crate::actor::recorder::{GetCooldown, Recorder};
+use crate::actor::session::Session;
+use crate::actor::user::{User, UserJoin};
+use crate::actor::{Hub, Main};
+use crate::config::Ranked;
+use crate::dev::*;
+use actix::prelude::*;
+use mighty::prelude::{Preset, Rule};
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use types::{MainToClient, QueueStatus};
+
+/// Matchmaker Actor
+///
+/// This contains users waiting for ranked games by preset.
+/// Every `MATCH_INTERVAL`, it groups users with close ratings and makes a ranked room for them.
+/// The rating window of each user widens while waiting, up to `MATCH_MAX_WINDOW`.
+#[derive(Debug)]
+pub struct Matchmaker {
+    queue: HashMap<Preset, Vec<Waiting>>,
+    waits: HashMap<Preset, VecDeque<Duration>>,
+    hub: Addr<Hub>,
+    recorder: Addr<Recorder>,
+    ranked: Ranked,
+}
+
+#[derive(Debug, Clone)]
+struct Waiting {
+    user_no: UserNo,
+    rating: u32,
+    range: Option<(u32, u32)>,
+    since: Instant,
+    user: Addr<User>,
+    main: Addr<Session<Main>>,
+}
+
+impl Actor for Matchmaker {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(MATCH_INTERVAL, |act, ctx| act.match_users(ctx));
+    }
+}
+
+/// Puts the user in the queue of the preset, replacing the previous request of the user from any session.
+///
+/// - `range`: ratings of the opponents the user wants, on top of the rating window
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Enqueue {
+    pub info: UserInfo,
+    pub user: Addr<User>,
+    pub main: Addr<Session<Main>>,
+    pub preset: Preset,
+    pub range: Option<(u32, u32)>,
+}
+
+impl Handler<Enqueue> for Matchmaker {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: Enqueue, _: &mut Self::Context) -> Self::Result {
+        self.remove(&msg.main);
+        self.remove_user(msg.info.no, &msg.main);
+        let reason = if msg.info.room.is_some() {
+            Some("already in a room")
+        } else if !RANKED_PRESETS.contains(&msg.preset) {
+            Some("preset is not allowed in ranked games")
+        } else if matches!(msg.range, Some((low, high)) if low > high) {
+            Some("invalid rating range")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            msg.main.do_send(MainToClient::QueueCancelled(reason.to_owned()));
+            return Box::pin(fut::ready(()));
+        }
+
+        Box::pin(
+            self.recorder
+                .send(GetCooldown(msg.info.no, self.ranked.abandon_cooldown))
+                .into_actor(self)
+                .map(move |res, act, _| {
+                    let reason = match res {
+                        Ok(Ok(None)) => None,
+                        Ok(Ok(Some(_))) => Some("ranked games are blocked for a while after abandoning"),
+                        _ => Some("failed to check the cooldown"),
+                    };
+                    if let Some(reason) = reason {
+                        msg.main.do_send(MainToClient::QueueCancelled(reason.to_owned()));
+                        return;
+                    }
+
+                    // the user may have queued again from another session while checking the cooldown
+                    act.remove(&msg.main);
+                    act.remove_user(msg.info.no, &msg.main);
+                    act.queue.entry(msg.preset).or_default().push(Waiting {
+                        user_no: msg.info.no,
+                        rating: msg.info.rating,
+                        range: msg.range,
+                        since: Instant::now(),
+                        user: msg.user,
+                        main: msg.main,
+                    });
+                    act.send_status(msg.preset);
+                }),
+        )
+    }
+}
+
+/// Removes the session from the queue.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Dequeue(pub Addr<Session<Main>>);
+
+impl Handler<Dequeue> for Matchmaker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Dequeue, _: &mut Self::Context) -> Self::Result {
+        if let Some(preset) = self.remove(&msg.0) {
+            msg.0.do_send(MainToClient::QueueCancelled("cancelled".to_owned()));
+            self.send_status(preset);
+        }
+    }
+}
+
+impl Matchmaker {
+    pub fn new(hub: Addr<Hub>, pool: Pool, ranked: Ranked) -> Matchmaker {
+        Matchmaker {
+            queue: HashMap::new(),
+            waits: HashMap::new(),
+            hub,
+            recorder: SyncArbiter::start(1, move || Recorder::new(pool.clone())),
+            ranked,
+        }
+    }
+
+    /// Removes the session and returns the preset it was waiting for.
+    fn remove(&mut self, main: &Addr<Session<Main>>) -> Option<Preset> {
+        for (preset, queue) in self.queue.iter_mut() {
+            if let Some(pos) = queue.iter().position(|w| w.main == *main) {
+                queue.remove(pos);
+                return Some(*preset);
+            }
+        }
+        None
+    }
+
+    /// Removes the requests of the user from other sessions than `main`, and tells them they are replaced.
+    fn remove_user(&mut self, user_no: UserNo, main: &Addr<Session<Main>>) {
+        let mut changed = Vec::new();
+        for (preset, queue) in self.queue.iter_mut() {
+            let (removed, kept) = queue
+                .drain(..)
+                .partition::<Vec<_>, _>(|w| w.user_no == user_no && w.main != *main);
+            *queue = kept;
+            for waiting in &removed {
+                waiting
+                    .main
+                    .do_send(MainToClient::QueueCancelled("queued in another session".to_owned()));
+            }
+            if !removed.is_empty() {
+                changed.push(*preset);
+            }
+        }
+        for preset in changed {
+            self.send_status(preset);
+        }
+    }
+
+    /// Average wait of the recent matches in seconds
+    fn estimate(&self, preset: Preset) -> Option<u64> {
+        let waits = self.waits.get(&preset).filter(|w| !w.is_empty())?;
+        Some(waits.iter().sum::<Duration>().as_secs() / waits.len() as u64)
+    }
+
+    fn send_status(&self, preset: Preset) {
+        let queue = match self.queue.get(&preset) {
+            Some(queue) => queue,
+            None => return,
+        };
+        let status = QueueStatus {
+            preset,
+            waiting: queue.len(),
+            estimate: self.estimate(preset),
+        };
+        for waiting in queue {
+            waiting.main.do_send(MainToClient::Queued(status.clone()));
+        }
+    }
+
+    fn match_users(&mut self, ctx: &mut Context<Self>) {
+        let now = Instant::now();
+        let presets = self.queue.keys().copied().collect::<Vec<_>>();
+        for preset in presets {
+            let size = Rule::from(preset).user_cnt as usize;
+            let queue = self.queue.entry(preset).or_default();
+            queue.retain(|w| w.main.connected());
+            let mut groups = Vec::new();
+            loop {
+                let players = queue
+                    .iter()
+                    .map(|w| (w.rating, w.range, now - w.since))
+                    .collect::<Vec<_>>();
+                let mut group = match find_group(&players, size) {
+                    Some(group) => group,
+                    None => break,
+                };
+                group.sort_unstable_by_key(|&i| Reverse(i));
+                let users = group.into_iter().map(|i| queue.remove(i)).collect::<Vec<_>>();
+                let waits = self.waits.entry(preset).or_default();
+                for waiting in &users {
+                    waits.push_back(now - waiting.since);
+                    if waits.len() > MATCH_WAIT_SAMPLES {
+                        waits.pop_front();
+                    }
+                }
+                groups.push(users);
+            }
+            if !groups.is_empty() {
+                self.send_status(preset);
+            }
+            for users in groups {
+                self.make_room(preset, users, ctx);
+            }
+        }
+    }
+
+    fn make_room(&self, preset: Preset, users: Vec<Waiting>, ctx: &mut Context<Self>) {
+        self.hub
+            .send(MakeRoom("Ranked match".to_owned(), Rule::from(preset), true))
+            .into_actor(self)
+            .then(move |res, _, _| {
+                match res {
+                    Ok(Ok(room_id)) => {
+                        for waiting in users {
+                            waiting.main.do_send(MainToClient::Matched(room_id));
+                            waiting.user.do_send(UserJoin(room_id, waiting.main.clone(), None));
+                        }
+                    }
+                    _ => {
+                        for waiting in users {
+                            log::warn!("failed to make room for user {}", waiting.user_no.0);
+                            waiting
+                                .main
+                                .do_send(MainToClient::QueueCancelled("failed to make room".to_owned()));
+                        }
+                    }
+                }
+
+                fut::ready(())
+            })
+            .spawn(ctx);
+    }
+}
+
+/// Rating window of the user after waiting `waited`
+pub fn window(waited: Duration) -> u32 {
+    MATCH_BASE_WINDOW
+        .saturating_add(MATCH_WIDEN_RATE.saturating_mul(waited.as_secs() as u32))
+        .min(MATCH_MAX_WINDOW)
+}
+
+/// Finds a group of `size` players from `(rating, range, waited)`.
+///
+/// The player waited longest comes first, and then the players with the closest ratings
+/// who are compatible with everyone in the group. Returns the indices of the group.
+pub fn find_group(players: &[(u32, Option<(u32, u32)>, Duration)], size: usize) -> Option<Vec<usize>> {
+    let accepts = |i: usize, j: usize| {
+        let (rating, range, waited) = players[i];
+        let other = players[j].0;
+        rating.abs_diff(other) <= window(waited) && range.is_none_or(|(low, high)| low <= other && other <= high)
+    };
+    let compatible = |i: usize, j: usize| accepts(i, j) && accepts(j, i);
+
+    let mut order = (0..players.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| Reverse(players[i].2));
+    for &anchor in &order {
+        let mut candidates = order
+            .iter()
+            .copied()
+            .filter(|&j| j != anchor && compatible(anchor, j))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&j| players[j].0.abs_diff(players[anchor].0));
+        let mut group = vec![anchor];
+        for j in candidates {
+            if group.len() == size {
+                break;
+            }
+            if group.iter().all(|&k| compatible(k, j)) {
+                group.push(j);
+            }
+        }
+        if group.len() == size {
+            return Some(group);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window_test() {
+        assert_eq!(window(Duration::from_secs(0)), MATCH_BASE_WINDOW);
+        assert_eq!(
+            window(Duration::from_secs(10)),
+            MATCH_BASE_WINDOW + MATCH_WIDEN_RATE * 10
+        );
+        assert_eq!(window(Duration::from_secs(100000)), MATCH_MAX_WINDOW);
+    }
+
+    #[test]
+    fn find_group_test() {
+        let secs = Duration::from_secs;
+        let mut players = vec![
+            (1500, None, secs(0)),
+            (1550, None, secs(0)),
+            (1450, None, secs(0)),
+            (1520, None, secs(0)),
+            (1900, None, secs(0)),
+        ];
+        assert_eq!(find_group(&players, 5), None);
+        assert_eq!(find_group(&players, 4).map(|g| g.len()), Some(4));
+
+        players.push((1480, None, secs(0)));
+        let mut group = find_group(&players, 5).unwrap();
+        group.sort_unstable();
+        assert_eq!(group, vec![0, 1, 2, 3, 5]);
+
+        // the outlier is matched after waiting long enough with everyone
+        for player in players.iter_mut() {
+            player.2 = secs(200);
+        }
+        players.truncate(5);
+        assert_eq!(find_group(&players, 5).map(|g| g.len()), Some(5));
+
+        // explicit range is respected however long the wait is
+        players[0].1 = Some((1400, 1600));
+        assert_eq!(find_group(&players, 5), None);
+    }
+}
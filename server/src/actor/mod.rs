@@ -1,8 +1,12 @@
+pub mod analyzer;
 pub mod hub;
 pub mod list;
 pub mod mail;
 pub mod main;
+pub mod maintainer;
+pub mod matchmaker;
 pub mod observe;
+pub mod recorder;
 pub mod room;
 pub mod room_user;
 pub mod session;
@@ -12,6 +16,7 @@ pub use hub::Hub;
 pub use list::List;
 pub use mail::Mail;
 pub use main::Main;
+pub use matchmaker::Matchmaker;
 pub use observe::Observe;
 pub use room::Room;
 pub use room_user::RoomUser;
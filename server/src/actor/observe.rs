@@ -1,4 +1,4 @@
-use crate::actor::room::{Chat, RoomJoin, RoomLeave};
+use crate::actor::room::{Chat, Resync, RoomJoin, RoomLeave};
 use crate::actor::session::{Session, SessionTrait};
 use crate::actor::Room;
 use actix::prelude::*;
@@ -15,17 +15,18 @@ impl SessionTrait for Observe {
     type Sender = ObserveToClient;
 
     fn started(act: &mut Session<Self>, ctx: &mut WebsocketContext<Session<Self>>) {
-        act.inner.room.do_send(RoomJoin::Observe(ctx.address()));
+        act.inner.room.do_send(RoomJoin::Observe(act.inner.no, ctx.address()));
     }
 
     fn stopped(act: &mut Session<Self>, ctx: &mut WebsocketContext<Session<Self>>) {
         act.inner.room.do_send(RoomLeave::Observe(ctx.address()));
     }
 
-    fn receive(act: &mut Session<Self>, msg: String, _: &mut WebsocketContext<Session<Self>>) {
+    fn receive(act: &mut Session<Self>, msg: String, ctx: &mut WebsocketContext<Session<Self>>) {
         let msg: ObserveToServer = serde_json::from_str(&*msg).unwrap();
         match msg {
             ObserveToServer::Chat(chat) => act.inner.room.do_send(Chat::Observe(chat, act.inner.no)),
+            ObserveToServer::Resync => act.inner.room.do_send(Resync::Observe(ctx.address())),
         }
     }
 }
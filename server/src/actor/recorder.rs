@@ -0,0 +1,122 @@
+use crate::actor::room::RoomSnapshot;
+use crate::analytics::GameRecord;
+use crate::db::abandon::{get_ranked_cooldown, save_abandons};
+use crate::db::analytics::index_game;
+use crate::db::game::{remove_room, save_room_snapshot, RemoveRoomForm};
+use crate::db::profile::invalidate_stats;
+use crate::dev::*;
+use crate::rating::{self, Glicko};
+use actix::prelude::*;
+use mighty::prelude::State;
+use std::time::{Duration, SystemTime};
+
+/// Recorder Actor
+///
+/// This runs on a `SyncArbiter` thread and does the database work of the rooms,
+/// so that rooms and hub are not blocked by the database.
+/// There is only one thread, so the writes are done in the order they are sent.
+#[derive(Debug)]
+pub struct Recorder {
+    pool: Pool,
+}
+
+impl Actor for Recorder {
+    type Context = SyncContext<Self>;
+}
+
+/// Saves the room to restore it after the server restarts.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct SaveSnapshot(pub RoomSnapshot);
+
+impl Handler<SaveSnapshot> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: SaveSnapshot, _: &mut Self::Context) -> Self::Result {
+        if let Err(e) = save_room_snapshot(&msg.0, self.pool.clone()) {
+            log::warn!("failed to save snapshot of room {}: {:?}", msg.0.info.id.0, e);
+        }
+    }
+}
+
+/// Removes the room and its snapshot.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct RemoveSnapshot(pub RoomId);
+
+impl Handler<RemoveSnapshot> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: RemoveSnapshot, _: &mut Self::Context) -> Self::Result {
+        if let Err(e) = remove_room(&RemoveRoomForm { room_id: msg.0 }, self.pool.clone()) {
+            log::warn!("failed to remove room {}: {:?}", msg.0 .0, e);
+        }
+    }
+}
+
+/// Records the game which just ended: indexes it, and saves the abandonments and settles the ratings if ranked.
+/// Returns the new rating and the change of each seat, or nothing if not ranked.
+///
+/// - `abandoned`: bitmask of seats which abandoned the game
+/// - `penalty`: rating lost more by abandoning
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Vec<(Glicko, i32)>")]
+pub struct RecordGame {
+    pub game_id: GameId,
+    pub users: Vec<UserNo>,
+    pub state: State,
+    pub record: GameRecord,
+    pub abandoned: u8,
+    pub penalty: u32,
+}
+
+impl Handler<RecordGame> for Recorder {
+    type Result = MessageResult<RecordGame>;
+
+    fn handle(&mut self, msg: RecordGame, _: &mut Self::Context) -> Self::Result {
+        let game_id = msg.game_id;
+        if let Err(e) = index_game(&msg.record, self.pool.clone()) {
+            log::warn!("failed to index game {}: {:?}", game_id, e);
+        }
+        let mut settled = Vec::new();
+        if msg.record.is_rank {
+            if let Err(e) = save_abandons(game_id, &msg.users, msg.abandoned, self.pool.clone()) {
+                log::warn!("failed to save abandons of game {}: {:?}", game_id, e);
+            }
+            match rating::settle(
+                game_id,
+                &msg.users,
+                &msg.state,
+                msg.abandoned,
+                msg.penalty,
+                self.pool.clone(),
+            ) {
+                Ok(res) => settled = res,
+                Err(e) => log::warn!("failed to settle ratings of game {}: {:?}", game_id, e),
+            }
+        }
+        if let Err(e) = invalidate_stats(&msg.users, self.pool.clone()) {
+            log::warn!("failed to invalidate stats of game {}: {:?}", game_id, e);
+        }
+        MessageResult(settled)
+    }
+}
+
+/// Returns when the user can play ranked games again after abandoning, or `None` if the user can play now.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Option<SystemTime>>")]
+pub struct GetCooldown(pub UserNo, pub Duration);
+
+impl Handler<GetCooldown> for Recorder {
+    type Result = Result<Option<SystemTime>>;
+
+    fn handle(&mut self, msg: GetCooldown, _: &mut Self::Context) -> Self::Result {
+        get_ranked_cooldown(msg.0, msg.1, self.pool.clone())
+    }
+}
+
+impl Recorder {
+    pub fn new(pool: Pool) -> Recorder {
+        Recorder { pool }
+    }
+}
@@ -1,23 +1,104 @@
-use crate::actor::hub::RemoveRoom;
+use crate::actor::hub::{AnalyzeGame, NotifyRating, RemoveRoom};
+use crate::actor::recorder::{GetCooldown, RecordGame, Recorder, SaveSnapshot};
 use crate::actor::session::Session;
-use crate::actor::user::{ChangeRating, GotGameState, GotRoomInfo, SendChat};
-use crate::actor::{hub, Hub, List, Observe, User};
+use crate::actor::user::{
+    GotClock, GotGameState, GotMaintenance, GotReveal, GotRoomInfo, GotRule, GotSummary, Kicked, SendChat,
+};
+use crate::actor::{hub, Hub, List, Observe, RoomUser, User};
+use crate::analytics::GameRecord;
+use crate::config::Ranked;
 use crate::db::game::{
-    change_room_info, get_into_room, get_rule, leave_room, make_game, save_rule, save_state, ChangeRoomInfoForm,
-    GetInRoomForm, GetRuleForm, LeaveRoomForm, MakeGameForm, SaveRuleForm, SaveStateForm,
+    change_room_info, get_into_room, leave_room, make_game, save_rule, save_state, ChangeRoomInfoForm, GetInRoomForm,
+    LeaveRoomForm, MakeGameForm, SaveRuleForm, SaveStateForm,
 };
 use crate::dev::*;
+use crate::rating;
+use crate::season;
 use actix::prelude::*;
-use mighty::prelude::{Command, Game, Rule, State};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use mighty::bot::waiting_users;
+use mighty::prelude::{Agent, BasicAgent, Command, Game, Rule, State};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Information of game
+///
+/// - `moves`: moves made by each seat
+/// - `ghost_moves`: moves made by the ghost of each seat
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameInfo {
     id: GameId,
     no: u32,
     game: Game,
+    started: u64,
+    turn_start: u64,
+    paused_at: Option<u64>,
+    #[serde(default)]
+    moves: Vec<u32>,
+    #[serde(default)]
+    ghost_moves: Vec<u32>,
+}
+
+/// Everything needed to restore the room after the server restarts
+///
+/// - `password`: hash of the password from `hash_password`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub info: RoomInfo,
+    pub rule: Rule,
+    pub game: Option<GameInfo>,
+    pub password: Option<String>,
+}
+
+/// Claims of the invite token of room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Invitation {
+    room: RoomUid,
+    exp: usize,
+}
+
+impl GameInfo {
+    fn clock(&self) -> Clock {
+        Clock {
+            now: now_millis(),
+            game_start: self.started,
+            turn_start: self.turn_start,
+            paused_at: self.paused_at,
+        }
+    }
+}
+
+/// Password of the room hashed with the room and the server secret
+fn hash_password(uid: RoomUid, password: &str, secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}:{}:{}", secret, uid, password));
+    hex::encode(hasher.finalize())
+}
+
+/// Milliseconds since the unix epoch
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Seats the user to the first empty and unlocked seat, or lets the user observe if there's none.
+/// Returns the seat if the user is seated.
+fn place(info: &mut RoomInfo, user_no: UserNo) -> Option<usize> {
+    let locked = info.locked;
+    match (0..info.user.len()).find(|&i| info.user[i].0 == 0 && locked & (1 << i) == 0) {
+        Some(seat) => {
+            info.user[seat] = user_no;
+            Some(seat)
+        }
+        None => {
+            info.standing.push(user_no);
+            None
+        }
+    }
 }
 
 /// Room Actor
@@ -26,62 +107,95 @@ pub struct GameInfo {
 #[derive(Debug)]
 pub struct Room {
     info: RoomInfo,
+    rule: Rule,
     game: Option<GameInfo>,
     user_addr: HashMap<UserNo, Addr<User>>,
-    observe: HashSet<Addr<Session<Observe>>>,
+    observe: HashMap<Addr<Session<Observe>>, UserNo>,
     list: HashSet<Addr<Session<List>>>,
+    chat: VecDeque<(String, UserNo, bool)>,
+    ghost_handle: Option<SpawnHandle>,
+    countdown_handle: Option<SpawnHandle>,
+    vote_handle: Option<SpawnHandle>,
+    // hash of the password, so that it is not saved in the snapshot as is
+    password: Option<String>,
+    banned: HashMap<UserNo, SystemTime>,
+    closing: bool,
     hub: Addr<Hub>,
+    recorder: Addr<Recorder>,
     pool: Pool,
+    secret: String,
+    ranked: Ranked,
 }
 
 impl Actor for Room {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // only the restored room starts with users who are not connected
+        let restored = self
+            .info
+            .user
+            .iter()
+            .chain(self.info.standing.iter())
+            .any(|x| x.0 != 0 && !self.user_addr.contains_key(x));
+        if restored {
+            self.schedule_ghost(ctx);
+            ctx.run_later(RESTORE_WAIT, |act, ctx| act.drop_absent(ctx));
+        }
+    }
 }
 
 /// Joins to room
 /// This returns RoomInfo for receivers to check if they're joined successfully
+/// Users need the key to join the private room.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "RoomInfo")]
 pub enum RoomJoin {
-    User(UserNo, Addr<User>),
-    Observe(Addr<Session<Observe>>),
+    User(UserNo, Addr<User>, Option<RoomKey>),
+    Observe(UserNo, Addr<Session<Observe>>),
     List(Addr<Session<List>>),
 }
 
 impl Handler<RoomJoin> for Room {
-    type Result = RoomInfo;
+    type Result = ResponseActFuture<Self, RoomInfo>;
 
     fn handle(&mut self, msg: RoomJoin, _: &mut Self::Context) -> Self::Result {
         match msg {
-            RoomJoin::User(user_no, addr) => {
-                if self.info.is_game {
-                    return self.info.clone();
-                }
-                let mut is_full = true;
-                for i in self.info.user.iter_mut() {
-                    if i.0 == 0 {
-                        *i = user_no;
-                        is_full = false;
+            RoomJoin::User(user_no, addr, key) => {
+                // users who abandoned a ranked game recently can't join ranked rooms
+                let cooldown = if self.info.is_rank {
+                    Some(self.recorder.send(GetCooldown(user_no, self.ranked.abandon_cooldown)))
+                } else {
+                    None
+                };
+                return Box::pin(
+                    async move {
+                        match cooldown {
+                            Some(cooldown) => matches!(cooldown.await, Ok(Ok(None))),
+                            None => true,
+                        }
                     }
-                }
-                if is_full {
-                    return self.info.clone();
-                }
-                self.user_addr.insert(user_no, addr);
-                self.set_head();
-                self.spread_info();
-                let _ = get_into_room(&GetInRoomForm { room_id: self.info.id }, self.pool.clone());
+                    .into_actor(self)
+                    .map(move |allowed, act, ctx| {
+                        if allowed {
+                            act.join(user_no, addr, key, ctx);
+                        }
+                        act.info.clone()
+                    }),
+                );
             }
-            RoomJoin::Observe(addr) => {
-                self.observe.insert(addr);
+            RoomJoin::Observe(user_no, addr) => {
+                self.observe.insert(addr.clone(), user_no);
                 self.info.observer_cnt += 1;
                 self.spread_info();
+                self.resync_observe(&addr, user_no);
             }
             RoomJoin::List(addr) => {
+                addr.do_send(ListToClient::Room(SimpleRoomInfo::from(self.info.clone())));
                 self.list.insert(addr);
             }
         }
-        self.info.clone()
+        Box::pin(fut::ready(self.info.clone()))
     }
 }
 
@@ -101,20 +215,22 @@ impl Handler<RoomLeave> for Room {
     fn handle(&mut self, msg: RoomLeave, ctx: &mut Self::Context) -> Self::Result {
         match msg {
             RoomLeave::User(user_no) => {
-                if self.info.is_game {
+                if self.closing || self.info.is_game && self.info.user.contains(&user_no) {
                     return;
                 }
                 if self.user_addr.remove(&user_no).is_none() {
                     return;
                 }
 
-                for i in self.info.user.iter_mut() {
-                    if *i == user_no {
-                        i.0 = 0;
-                    }
+                self.vacate(user_no);
+                self.info.standing.retain(|x| *x != user_no);
+                if !self.observe.values().any(|x| *x == user_no) {
+                    self.info.coach.retain(|(observer, _)| *observer != user_no);
                 }
                 self.set_head();
+                self.countdown(ctx);
                 self.spread_info();
+                self.checkpoint();
 
                 if self.user_addr.is_empty() {
                     self.hub.do_send(RemoveRoom(self.info.id));
@@ -123,8 +239,12 @@ impl Handler<RoomLeave> for Room {
                 let _ = leave_room(&LeaveRoomForm { room_id: self.info.id }, self.pool.clone());
             }
             RoomLeave::Observe(addr) => {
-                if !self.observe.remove(&addr) {
-                    return;
+                let user_no = match self.observe.remove(&addr) {
+                    Some(user_no) => user_no,
+                    None => return,
+                };
+                if !self.observe.values().any(|x| *x == user_no) && !self.user_addr.contains_key(&user_no) {
+                    self.info.coach.retain(|(observer, _)| *observer != user_no);
                 }
                 self.info.observer_cnt -= 1;
                 self.spread_info();
@@ -151,6 +271,7 @@ impl Handler<ChangeName> for Room {
         }
         self.info.name = msg.1;
         self.spread_info();
+        self.checkpoint();
         let form = ChangeRoomInfoForm {
             room_id: self.info.id,
             name: Some(self.info.name.clone()),
@@ -161,7 +282,7 @@ impl Handler<ChangeName> for Room {
 }
 
 /// Changes the rule of the room.
-/// It won't be changed if the user is not head.
+/// It won't be changed if the user is not head, or the rule is not allowed in the ranked room.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
 pub struct ChangeRule(pub UserNo, pub Rule);
@@ -169,14 +290,30 @@ pub struct ChangeRule(pub UserNo, pub Rule);
 impl Handler<ChangeRule> for Room {
     type Result = ();
 
-    fn handle(&mut self, msg: ChangeRule, _: &mut Self::Context) -> Self::Result {
-        if msg.0 != self.info.head || self.info.is_game {
+    fn handle(&mut self, msg: ChangeRule, ctx: &mut Self::Context) -> Self::Result {
+        if msg.0 != self.info.head || self.info.is_game || (self.info.is_rank && !season::is_ranked_rule(&msg.1)) {
             return;
         }
         self.info.rule = RuleHash::generate(&msg.1);
+        self.rule = msg.1.clone();
+        let user_cnt = self.rule.user_cnt as usize;
+        if self.info.user.len() > user_cnt {
+            for user_no in self.info.user.split_off(user_cnt) {
+                if user_no.0 != 0 {
+                    self.info.swap.retain(|(a, b)| *a != user_no && *b != user_no);
+                    self.info.standing.push(user_no);
+                }
+            }
+        }
+        self.info.user.resize(user_cnt, UserNo(0));
+        self.info.locked &= ((1u16 << user_cnt) - 1) as u8;
+        self.info.ready = 0;
+        self.countdown(ctx);
         let _ = save_rule(&SaveRuleForm { rule: msg.1.clone() }, self.pool.clone());
 
         self.spread_info();
+        self.checkpoint();
+        self.spread_rule();
         let form = ChangeRoomInfoForm {
             room_id: self.info.id,
             name: None,
@@ -187,7 +324,7 @@ impl Handler<ChangeRule> for Room {
 }
 
 /// Starts the game.
-/// It won't be changed if the user is not head.
+/// It won't be started if the user is not head, or not every seat is filled and ready.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
 pub struct StartGame(pub UserNo);
@@ -195,35 +332,68 @@ pub struct StartGame(pub UserNo);
 impl Handler<StartGame> for Room {
     type Result = ();
 
-    fn handle(&mut self, msg: StartGame, _: &mut Self::Context) -> Self::Result {
-        if msg.0 != self.info.head || self.info.is_game {
+    fn handle(&mut self, msg: StartGame, ctx: &mut Self::Context) -> Self::Result {
+        if msg.0 != self.info.head || !self.can_start() {
             return;
         }
-        let id = GameId::generate_random();
-        let rule = get_rule(
-            &GetRuleForm {
-                rule_hash: self.info.rule,
-            },
-            self.pool.clone(),
-        )
-        .unwrap();
-        self.game = Some(GameInfo {
-            id,
-            no: 0,
-            game: Game::new(rule.clone()),
-        });
-        self.info.is_game = true;
-        self.spread_info();
-        self.spread_game();
-        let form = MakeGameForm {
-            game_id: id,
-            room_id: self.info.uid,
-            room_name: self.info.name.clone(),
-            users: self.info.user.iter().map(|x| x.0).collect(),
-            is_rank: true,
-            rule,
+        self.start(ctx);
+    }
+}
+
+/// Sets if the user is ready to start.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Ready(pub UserNo, pub bool);
+
+impl Handler<Ready> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Ready, ctx: &mut Self::Context) -> Self::Result {
+        if self.info.is_game {
+            return;
+        }
+        let seat = match self.info.user.iter().position(|x| *x == msg.0) {
+            Some(seat) => seat,
+            None => return,
         };
-        let _ = make_game(&form, self.pool.clone());
+        if msg.1 {
+            self.info.ready |= 1 << seat;
+        } else {
+            self.info.ready &= !(1 << seat);
+        }
+        self.countdown(ctx);
+        self.spread_info();
+    }
+}
+
+/// Changes the options for starting the game.
+/// It won't be changed if the user is not head, and bots can't fill the seats in rank rooms.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub enum StartOption {
+    AutoStart(UserNo, bool),
+    BotFill(UserNo, bool),
+}
+
+impl Handler<StartOption> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: StartOption, ctx: &mut Self::Context) -> Self::Result {
+        if self.info.is_game {
+            return;
+        }
+        match msg {
+            StartOption::AutoStart(user_no, auto_start) if user_no == self.info.head => {
+                self.info.auto_start = auto_start;
+            }
+            StartOption::BotFill(user_no, bot_fill) if user_no == self.info.head && !self.info.is_rank => {
+                self.info.bot_fill = bot_fill;
+            }
+            _ => return,
+        }
+        self.countdown(ctx);
+        self.spread_info();
+        self.checkpoint();
     }
 }
 
@@ -235,62 +405,350 @@ pub struct Go(pub UserNo, pub Command);
 impl Handler<Go> for Room {
     type Result = ();
 
-    fn handle(&mut self, msg: Go, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: Go, ctx: &mut Self::Context) -> Self::Result {
+        if !self.info.is_game || self.info.paused {
+            return;
+        }
+
+        let user_id = match self.info.user.iter().position(|x| *x == msg.0) {
+            Some(user_id) => user_id,
+            None => return,
+        };
+        if self.info.ghost & (1 << user_id) > 0 {
+            // the user replaced with bot by vote is back
+            self.info.ghost &= !(1 << user_id);
+            self.spread_info();
+        }
+        ignore!(self.go(user_id, msg.1, false, ctx));
+    }
+}
+
+/// Lets bot play for the user while the user is away, or gives the seat back to the user.
+/// It will do nothing if the game is not going on.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Ghost(pub UserNo, pub bool);
+
+impl Handler<Ghost> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Ghost, ctx: &mut Self::Context) -> Self::Result {
         if !self.info.is_game {
             return;
         }
+        let user_id = match self.info.user.iter().position(|x| *x == msg.0) {
+            Some(user_id) => user_id,
+            None => return,
+        };
+        let ghost = if msg.1 {
+            self.info.ghost | (1 << user_id)
+        } else {
+            self.info.ghost & !(1 << user_id)
+        };
+        if ghost == self.info.ghost {
+            return;
+        }
+        self.info.ghost = ghost;
+        self.spread_info();
+        self.schedule_ghost(ctx);
+    }
+}
+
+/// Kicks the user from the room, and bans the user from joining again during `KICK_BAN_TIME`.
+/// Only head can kick, and users playing the game can't be kicked.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Kick(pub UserNo, pub UserNo);
+
+impl Handler<Kick> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Kick, ctx: &mut Self::Context) -> Self::Result {
+        let (head, user_no) = (msg.0, msg.1);
+        if head != self.info.head || head == user_no {
+            return;
+        }
+        if self.info.is_game && self.info.user.contains(&user_no) {
+            return;
+        }
+        let addr = match self.user_addr.remove(&user_no) {
+            Some(addr) => addr,
+            None => return,
+        };
+        addr.do_send(Kicked(self.info.id));
+        self.banned.insert(user_no, SystemTime::now() + KICK_BAN_TIME);
+
+        self.vacate(user_no);
+        self.info.standing.retain(|x| *x != user_no);
+        if !self.observe.values().any(|x| *x == user_no) {
+            self.info.coach.retain(|(observer, _)| *observer != user_no);
+        }
+        self.countdown(ctx);
+        self.spread_info();
+        self.checkpoint();
+        let _ = leave_room(&LeaveRoomForm { room_id: self.info.id }, self.pool.clone());
+    }
+}
+
+/// Gives the head to other user in the room.
+/// It won't be changed if the user is not head.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct TransferHead(pub UserNo, pub UserNo);
+
+impl Handler<TransferHead> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransferHead, _: &mut Self::Context) -> Self::Result {
+        if msg.0 != self.info.head || !self.user_addr.contains_key(&msg.1) {
+            return;
+        }
+        self.info.head = msg.1;
+        self.spread_info();
+        self.checkpoint();
+    }
+}
 
-        let mut user_id = self.info.user.len();
-        for (i, x) in self.info.user.iter().enumerate() {
-            if *x == msg.0 {
-                user_id = i;
-                break;
+/// Changes who can join the room.
+/// It won't be changed if the user is not head.
+///
+/// - `Password`: sets the password, or removes it with `None`
+/// - `InviteOnly`: sets if invite token is needed to join
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub enum Privacy {
+    Password(UserNo, Option<String>),
+    InviteOnly(UserNo, bool),
+}
+
+impl Handler<Privacy> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Privacy, _: &mut Self::Context) -> Self::Result {
+        match msg {
+            Privacy::Password(user_no, password) if user_no == self.info.head => {
+                self.password = password
+                    .filter(|x| !x.is_empty())
+                    .map(|x| hash_password(self.info.uid, &x, &self.secret));
+                self.info.has_password = self.password.is_some();
+            }
+            Privacy::InviteOnly(user_no, invite_only) if user_no == self.info.head => {
+                self.info.invite_only = invite_only;
             }
+            _ => return,
         }
+        self.spread_info();
+        self.checkpoint();
+    }
+}
+
+/// Makes the invite token of this room and sends it to the session.
+/// Only head can invite.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Invite(pub UserNo, pub Addr<Session<RoomUser>>);
 
-        if user_id == self.info.user.len() {
+impl Handler<Invite> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Invite, _: &mut Self::Context) -> Self::Result {
+        if msg.0 != self.info.head {
             return;
         }
-        let finished = ignore!(self.next(user_id, msg.1));
-        let game = self.game.as_ref().unwrap();
-        let _ = save_state(
-            &SaveStateForm {
-                game_id: game.id,
-                room_id: self.info.uid,
-                number: game.no,
-                state: game.game.get_state(),
-            },
-            self.pool.clone(),
+        let invitation = Invitation {
+            room: self.info.uid,
+            exp: (now_millis() / 1000) as usize + INVITE_VALID_DURATION.as_secs() as usize,
+        };
+        let token = encode(
+            &Header::new(Algorithm::HS256),
+            &invitation,
+            &EncodingKey::from_secret(self.secret.as_ref()),
         );
+        if let Ok(token) = token {
+            msg.1.do_send(RoomUserToClient::Invite(token));
+        }
+    }
+}
 
-        if finished {
-            if self.info.is_rank {
-                if let Some(game) = &mut self.game {
-                    if let State::GameEnded {
-                        winner,
-                        president,
-                        score,
-                        ..
-                    } = game.game.state
-                    {
-                        for (i, &userno) in self.info.user.iter().enumerate() {
-                            let score = if (1 << i) & winner > 0 {
-                                score as i32
-                            } else {
-                                -(score as i32)
-                            };
-                            let score = if i == president { 2 * score } else { score };
-                            self.user_addr
-                                .get(&userno)
-                                .unwrap()
-                                .do_send(ChangeRating(score, game.id));
-                        }
-                    }
-                }
+/// Changes the seats of the room.
+/// It will do nothing if the game is going on or the request is invalid.
+///
+/// - `Sit`: takes the empty and unlocked seat
+/// - `Stand`: stands up from the seat
+/// - `Swap`: requests to swap seats with the user in the seat, and swaps if the user requested it too
+/// - `Lock`: locks or unlocks the seat, only for the head
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub enum Seat {
+    Sit(UserNo, usize),
+    Stand(UserNo),
+    Swap(UserNo, usize),
+    Lock(UserNo, usize, bool),
+}
+
+impl Handler<Seat> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Seat, ctx: &mut Self::Context) -> Self::Result {
+        if self.info.is_game {
+            return;
+        }
+        ignore!(self.change_seat(msg));
+        self.countdown(ctx);
+        self.spread_info();
+        self.checkpoint();
+    }
+}
+
+/// Proposes the vote in the game.
+/// It will do nothing if other vote is going on or the vote doesn't make sense now.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Propose(pub UserNo, pub VoteKind);
+
+impl Handler<Propose> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Propose, ctx: &mut Self::Context) -> Self::Result {
+        if !self.info.is_game || self.info.vote.is_some() {
+            return;
+        }
+        let seat = match self.info.user.iter().position(|x| *x == msg.0) {
+            Some(seat) => seat,
+            None => return,
+        };
+        let valid = match msg.1 {
+            VoteKind::Abort => true,
+            VoteKind::Pause => !self.info.paused,
+            VoteKind::Resume => self.info.paused,
+            VoteKind::ReplaceWithBot(target) => {
+                target < self.info.user.len() && target != seat && self.info.ghost & (1 << target) == 0
             }
-            self.info.is_game = false;
-            self.game = None;
-            self.spread_info();
+            VoteKind::Undo => !self.info.is_rank && self.game.as_ref().is_some_and(|g| g.game.can_undo(seat)),
+        };
+        // ghosted seats can't vote, so they can't propose either
+        if !valid || self.voters(msg.1) & (1 << seat) == 0 {
+            return;
+        }
+
+        let timeout = Duration::from_secs(self.info.vote_rule.timeout as u64);
+        self.info.vote = Some(Vote {
+            kind: msg.1,
+            proposer: msg.0,
+            yes: 1 << seat,
+            no: 0,
+            end_at: now_millis() + timeout.as_millis() as u64,
+        });
+        self.vote_handle = Some(ctx.run_later(timeout, |act, _| {
+            act.vote_handle = None;
+            act.info.vote = None;
+            act.spread_info();
+        }));
+        self.count_vote(ctx);
+    }
+}
+
+/// Votes for or against the vote going on.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Ballot(pub UserNo, pub bool);
+
+impl Handler<Ballot> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Ballot, ctx: &mut Self::Context) -> Self::Result {
+        let seat = match self.info.user.iter().position(|x| *x == msg.0) {
+            Some(seat) => seat,
+            None => return,
+        };
+        let vote = match &mut self.info.vote {
+            Some(vote) => vote,
+            None => return,
+        };
+        if msg.1 {
+            vote.yes |= 1 << seat;
+            vote.no &= !(1 << seat);
+        } else {
+            vote.no |= 1 << seat;
+            vote.yes &= !(1 << seat);
+        }
+        self.count_vote(ctx);
+    }
+}
+
+/// Changes the thresholds and timeout of votes.
+/// It won't be changed if the user is not head or the game is going on.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct ChangeVoteRule(pub UserNo, pub VoteRule);
+
+impl Handler<ChangeVoteRule> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChangeVoteRule, _: &mut Self::Context) -> Self::Result {
+        let rule = msg.1;
+        if msg.0 != self.info.head || self.info.is_game {
+            return;
+        }
+        if [rule.abort, rule.pause, rule.replace].iter().any(|x| *x > 100) || rule.timeout == 0 {
+            return;
+        }
+        self.info.vote_rule = rule;
+        self.spread_info();
+        self.checkpoint();
+    }
+}
+
+/// Changes what observers can see.
+/// It won't be changed if the user is not head or the game is going on.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct ChangeSpectatorPolicy(pub UserNo, pub SpectatorPolicy);
+
+impl Handler<ChangeSpectatorPolicy> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChangeSpectatorPolicy, _: &mut Self::Context) -> Self::Result {
+        if msg.0 != self.info.head || self.info.is_game {
+            return;
+        }
+        self.info.spectator = msg.1;
+        self.spread_info();
+        self.checkpoint();
+    }
+}
+
+/// Allows or disallows the observer to see the hand of the user.
+/// Only seated users can choose their coach, and only before the game starts.
+/// Ranked rooms have no coaches, since the coach could tell the hand to a teammate.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Coach(pub UserNo, pub UserNo, pub bool);
+
+impl Handler<Coach> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Coach, _: &mut Self::Context) -> Self::Result {
+        let (user_no, observer, allow) = (msg.0, msg.1, msg.2);
+        if self.info.is_rank || !self.info.user.contains(&user_no) || self.info.user.contains(&observer) {
+            return;
+        }
+        // coaches can be revoked during the game, but not granted
+        if allow && self.info.is_game {
+            return;
+        }
+        let is_observer = self.observe.values().any(|x| *x == observer) || self.user_addr.contains_key(&observer);
+        let pos = self.info.coach.iter().position(|x| *x == (observer, user_no));
+        match (allow, pos) {
+            (true, None) if is_observer => self.info.coach.push((observer, user_no)),
+            (false, Some(pos)) => {
+                self.info.coach.remove(pos);
+            }
+            _ => return,
+        }
+        self.spread_info();
+        if self.game.is_some() {
+            self.spread_game();
         }
     }
 }
@@ -307,18 +765,26 @@ impl Handler<Chat> for Room {
     type Result = ();
 
     fn handle(&mut self, msg: Chat, _: &mut Self::Context) -> Self::Result {
+        if self.chat.len() >= CHAT_BACKLOG {
+            self.chat.pop_front();
+        }
+        match &msg {
+            Chat::User(chat, no) => self.chat.push_back((chat.clone(), *no, false)),
+            Chat::Observe(chat, no) => self.chat.push_back((chat.clone(), *no, true)),
+        }
+
         match msg {
             Chat::User(chat, no) => {
                 for (_, i) in self.user_addr.iter() {
                     i.do_send(SendChat(chat.clone(), no));
                 }
 
-                for i in self.observe.iter() {
+                for i in self.observe.keys() {
                     i.do_send(ObserveToClient::Chat(chat.clone(), no));
                 }
             }
             Chat::Observe(chat, no) => {
-                for i in self.observe.iter() {
+                for i in self.observe.keys() {
                     i.do_send(ObserveToClient::Chat(chat.clone(), no));
                 }
             }
@@ -326,6 +792,88 @@ impl Handler<Chat> for Room {
     }
 }
 
+/// Sends the whole state of the room again to the session.
+/// It is sent when a session attaches, or when the client asks for it.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub enum Resync {
+    User(UserNo, Addr<Session<RoomUser>>),
+    Observe(Addr<Session<Observe>>),
+}
+
+impl Handler<Resync> for Room {
+    type Result = ();
+
+    fn handle(&mut self, msg: Resync, _: &mut Self::Context) -> Self::Result {
+        match msg {
+            Resync::User(user_no, addr) => {
+                if !self.user_addr.contains_key(&user_no) {
+                    return;
+                }
+                addr.do_send(RoomUserToClient::Room(self.info.clone()));
+                addr.do_send(RoomUserToClient::Rule(self.rule.clone()));
+                if let Some(game) = &self.game {
+                    if let Some(state) = self.user_state(user_no) {
+                        addr.do_send(RoomUserToClient::Game(state));
+                    }
+                    addr.do_send(RoomUserToClient::Clock(game.clock()));
+                }
+                addr.do_send(RoomUserToClient::ChatLog(self.chat_log(false)));
+            }
+            Resync::Observe(addr) => {
+                if let Some(user_no) = self.observe.get(&addr) {
+                    self.resync_observe(&addr, *user_no);
+                }
+            }
+        }
+    }
+}
+
+/// Puts the user back to the room restored after the server restarts.
+/// Returns `None` if the user was not in the room.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Option<RoomInfo>")]
+pub struct Rejoin(pub UserNo, pub Addr<User>);
+
+impl Handler<Rejoin> for Room {
+    type Result = Option<RoomInfo>;
+
+    fn handle(&mut self, msg: Rejoin, _: &mut Self::Context) -> Self::Result {
+        let in_room = self.info.user.contains(&msg.0) || self.info.standing.contains(&msg.0);
+        if !in_room || self.user_addr.contains_key(&msg.0) {
+            return None;
+        }
+        self.user_addr.insert(msg.0, msg.1);
+        self.set_head();
+        self.spread_info();
+        Some(self.info.clone())
+    }
+}
+
+/// Saves the room and tells everyone in the room that the server is going down.
+/// The room doesn't change anymore after this.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Shutdown;
+
+impl Handler<Shutdown> for Room {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _: Shutdown, _: &mut Self::Context) -> Self::Result {
+        let saved = self.recorder.send(SaveSnapshot(self.snapshot()));
+        self.closing = true;
+        for (_, i) in self.user_addr.iter() {
+            i.do_send(GotMaintenance);
+        }
+        for i in self.observe.keys() {
+            i.do_send(ObserveToClient::Maintenance);
+        }
+        Box::pin(async move {
+            let _ = saved.await;
+        })
+    }
+}
+
 /// Returns the information of this room.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "RoomInfo")]
@@ -340,35 +888,341 @@ impl Handler<GetInfo> for Room {
 }
 
 impl Room {
-    pub fn new(info: RoomInfo, server: Addr<hub::Hub>, pool: Pool) -> Room {
+    pub fn new(
+        info: RoomInfo,
+        rule: Rule,
+        server: Addr<hub::Hub>,
+        recorder: Addr<Recorder>,
+        pool: Pool,
+        secret: String,
+        ranked: Ranked,
+    ) -> Room {
         Room {
             info,
+            rule,
             game: None,
             user_addr: HashMap::new(),
-            observe: HashSet::new(),
+            observe: HashMap::new(),
             list: HashSet::new(),
+            chat: VecDeque::new(),
+            ghost_handle: None,
+            countdown_handle: None,
+            vote_handle: None,
+            password: None,
+            banned: HashMap::new(),
+            closing: false,
             hub: server,
+            recorder,
             pool,
+            secret,
+            ranked,
+        }
+    }
+
+    /// Makes the room from the snapshot saved before the server restarted.
+    /// Nobody is connected to the restored room, and the turn starts again.
+    pub fn restore(
+        snapshot: RoomSnapshot,
+        server: Addr<hub::Hub>,
+        recorder: Addr<Recorder>,
+        pool: Pool,
+        secret: String,
+        ranked: Ranked,
+    ) -> Room {
+        let mut room = Room::new(snapshot.info, snapshot.rule, server, recorder, pool, secret, ranked);
+        room.info.observer_cnt = 0;
+        room.info.start_at = None;
+        room.info.vote = None;
+        room.info.coach.clear();
+        room.password = snapshot.password;
+        room.game = snapshot.game;
+        if let Some(game) = &mut room.game {
+            if game.paused_at.is_none() {
+                game.turn_start = now_millis();
+            }
         }
+        room
     }
 
+    fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            info: self.info.clone(),
+            rule: self.rule.clone(),
+            game: self.game.clone(),
+            password: self.password.clone(),
+        }
+    }
+
+    /// Saves the room to restore it after the server restarts.
+    /// This is called only when the game or the settings of the room are changed.
+    fn checkpoint(&self) {
+        if !self.closing {
+            self.recorder.do_send(SaveSnapshot(self.snapshot()));
+        }
+    }
+
+    /// Lets bots play for the seated users who are not connected, and removes the others.
+    /// Closes the room if nobody is connected.
+    fn drop_absent(&mut self, ctx: &mut Context<Self>) {
+        let absent = self
+            .info
+            .user
+            .iter()
+            .chain(self.info.standing.iter())
+            .filter(|x| x.0 != 0 && !self.user_addr.contains_key(x))
+            .copied()
+            .collect::<Vec<_>>();
+        for user_no in absent {
+            match self.info.user.iter().position(|x| *x == user_no) {
+                Some(seat) if self.info.is_game => self.info.ghost |= 1 << seat,
+                _ => {
+                    self.vacate(user_no);
+                    self.info.standing.retain(|x| *x != user_no);
+                }
+            }
+        }
+        if self.user_addr.is_empty() && !self.info.is_game {
+            self.hub.do_send(RemoveRoom(self.info.id));
+            ctx.stop();
+            return;
+        }
+        self.set_head();
+        self.spread_info();
+        self.checkpoint();
+        self.schedule_ghost(ctx);
+    }
+
+    /// Picks new head if the head is not in the room.
     fn set_head(&mut self) {
-        if !self.user_addr.contains_key(&self.info.head) {
-            self.info.head.0 = 0;
+        if self.user_addr.contains_key(&self.info.head) {
+            return;
+        }
+        let user_addr = &self.user_addr;
+        self.info.head = self
+            .info
+            .user
+            .iter()
+            .chain(self.info.standing.iter())
+            .find(|x| user_addr.contains_key(x))
+            .copied()
+            .unwrap_or(UserNo(0));
+    }
+
+    /// Puts the user in the room if the user can join.
+    fn join(&mut self, user_no: UserNo, addr: Addr<User>, key: Option<RoomKey>, ctx: &mut Context<Self>) {
+        if self.info.is_game || !self.can_join(user_no, key.as_ref()) {
+            return;
+        }
+        place(&mut self.info, user_no);
+        self.user_addr.insert(user_no, addr);
+        self.set_head();
+        self.countdown(ctx);
+        self.spread_info();
+        self.checkpoint();
+        let _ = get_into_room(&GetInRoomForm { room_id: self.info.id }, self.pool.clone());
+    }
+
+    /// If the user is allowed to join with the key
+    fn can_join(&mut self, user_no: UserNo, key: Option<&RoomKey>) -> bool {
+        let now = SystemTime::now();
+        self.banned.retain(|_, until| *until > now);
+        if self.banned.contains_key(&user_no) {
+            return false;
+        }
+
+        match key {
+            Some(RoomKey::Invite(token)) => decode::<Invitation>(
+                token,
+                &DecodingKey::from_secret(self.secret.as_ref()),
+                &Validation::new(Algorithm::HS256),
+            )
+            .map(|token| token.claims.room == self.info.uid)
+            .unwrap_or(false),
+            Some(RoomKey::Password(password)) => {
+                !self.info.invite_only && self.password == Some(hash_password(self.info.uid, password, &self.secret))
+            }
+            None => !self.info.invite_only && self.password.is_none(),
+        }
+    }
+
+    /// If every seat is filled or played by bot, and every user is ready
+    fn can_start(&self) -> bool {
+        if self.info.is_game {
+            return false;
+        }
+        self.info.user.iter().enumerate().all(|(seat, user_no)| {
+            if user_no.0 == 0 {
+                self.info.bot_fill
+            } else {
+                self.info.ready & (1 << seat) > 0
+            }
+        }) && self.info.user.iter().any(|user_no| user_no.0 != 0)
+    }
+
+    /// Starts or cancels the countdown for auto start.
+    fn countdown(&mut self, ctx: &mut Context<Self>) {
+        let can_start = self.info.auto_start && self.can_start();
+        if can_start == self.countdown_handle.is_some() {
+            return;
+        }
+        if let Some(handle) = self.countdown_handle.take() {
+            ctx.cancel_future(handle);
+            self.info.start_at = None;
+            return;
+        }
+
+        self.info.start_at = Some(now_millis() + START_COUNTDOWN.as_millis() as u64);
+        self.countdown_handle = Some(ctx.run_later(START_COUNTDOWN, |act, ctx| {
+            act.countdown_handle = None;
+            act.info.start_at = None;
+            if act.can_start() {
+                act.start(ctx);
+            } else {
+                act.spread_info();
+            }
+        }));
+    }
+
+    /// Starts the game, letting bot play the empty seats.
+    fn start(&mut self, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.countdown_handle.take() {
+            ctx.cancel_future(handle);
         }
+        let id = GameId::generate_random();
+        let rule = self.rule.clone();
+        let now = now_millis();
+        self.game = Some(GameInfo {
+            id,
+            no: 0,
+            game: Game::new(rule.clone()),
+            started: now,
+            turn_start: now,
+            paused_at: None,
+            moves: vec![0; self.info.user.len()],
+            ghost_moves: vec![0; self.info.user.len()],
+        });
+        self.info.is_game = true;
+        self.info.start_at = None;
+        self.info.swap.clear();
+        self.info.ghost = self
+            .info
+            .user
+            .iter()
+            .enumerate()
+            .filter(|(_, user_no)| user_no.0 == 0)
+            .fold(0, |ghost, (seat, _)| ghost | 1 << seat);
+        self.spread_info();
+        self.spread_rule();
+        self.spread_game();
+        let form = MakeGameForm {
+            game_id: id,
+            room_id: self.info.uid,
+            room_name: self.info.name.clone(),
+            users: self.info.user.iter().map(|x| x.0).collect(),
+            is_rank: self.info.is_rank,
+            rule,
+        };
+        let _ = make_game(&form, self.pool.clone());
+        let _ = save_state(
+            &SaveStateForm {
+                game_id: id,
+                room_id: self.info.uid,
+                number: 0,
+                state: self.game.as_ref().unwrap().game.get_state(),
+                by_ghost: false,
+                takeback: false,
+            },
+            self.pool.clone(),
+        );
+        self.schedule_ghost(ctx);
+    }
 
-        if self.info.head.0 == 0 {
-            for i in self.info.user.iter() {
-                if i.0 != 0 {
-                    self.info.head.0 = 0;
+    fn change_seat(&mut self, msg: Seat) -> Result<()> {
+        let seat_cnt = self.info.user.len();
+        match msg {
+            Seat::Sit(user_no, seat) => {
+                ensure!(
+                    self.user_addr.contains_key(&user_no),
+                    StatusCode::BAD_REQUEST,
+                    "not in room"
+                );
+                ensure!(seat < seat_cnt, StatusCode::BAD_REQUEST, "invalid seat");
+                ensure!(self.info.user[seat].0 == 0, StatusCode::BAD_REQUEST, "seat is taken");
+                ensure!(
+                    self.info.locked & (1 << seat) == 0,
+                    StatusCode::BAD_REQUEST,
+                    "seat is locked"
+                );
+                self.vacate(user_no);
+                self.info.standing.retain(|x| *x != user_no);
+                self.info.coach.retain(|(observer, _)| *observer != user_no);
+                self.info.user[seat] = user_no;
+            }
+            Seat::Stand(user_no) => {
+                ensure!(self.info.user.contains(&user_no), StatusCode::BAD_REQUEST, "not seated");
+                self.vacate(user_no);
+                if !self.info.standing.contains(&user_no) {
+                    self.info.standing.push(user_no);
+                }
+            }
+            Seat::Swap(user_no, seat) => {
+                ensure!(seat < seat_cnt, StatusCode::BAD_REQUEST, "invalid seat");
+                let from = match self.info.user.iter().position(|x| *x == user_no) {
+                    Some(from) => from,
+                    None => bail!(StatusCode::BAD_REQUEST, "not seated"),
+                };
+                let target = self.info.user[seat];
+                ensure!(
+                    target.0 != 0 && target != user_no,
+                    StatusCode::BAD_REQUEST,
+                    "no user to swap"
+                );
+                ensure!(
+                    self.info.locked & (1 << seat | 1 << from) == 0,
+                    StatusCode::BAD_REQUEST,
+                    "seat is locked"
+                );
+                if self.info.swap.contains(&(target, user_no)) {
+                    self.info.user.swap(from, seat);
+                    self.info.ready &= !(1 << seat | 1 << from);
+                    self.info
+                        .swap
+                        .retain(|(a, b)| ![user_no, target].contains(a) && ![user_no, target].contains(b));
+                } else {
+                    self.info.swap.retain(|(a, _)| *a != user_no);
+                    self.info.swap.push((user_no, target));
+                }
+            }
+            Seat::Lock(user_no, seat, lock) => {
+                ensure!(user_no == self.info.head, StatusCode::FORBIDDEN, "not head");
+                ensure!(seat < seat_cnt, StatusCode::BAD_REQUEST, "invalid seat");
+                if lock {
+                    self.info.locked |= 1 << seat;
+                } else {
+                    self.info.locked &= !(1 << seat);
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Empties the seat of the user and cancels the swap requests of the user.
+    fn vacate(&mut self, user_no: UserNo) {
+        for (seat, i) in self.info.user.iter_mut().enumerate() {
+            if *i == user_no {
+                i.0 = 0;
+                self.info.ready &= !(1 << seat);
+            }
+        }
+        self.info.swap.retain(|(a, b)| *a != user_no && *b != user_no);
+        self.info.coach.retain(|(_, user)| *user != user_no);
     }
 
     fn next(&mut self, user_id: usize, cmd: Command) -> Result<bool> {
         if let Some(game) = &mut self.game {
             let res = game.game.next(user_id, cmd)?;
+            game.no += 1;
+            game.turn_start = now_millis();
             self.spread_game();
             Ok(res)
         } else {
@@ -376,12 +1230,253 @@ impl Room {
         }
     }
 
+    /// Processes the command, saves the state and finishes the game if it ended.
+    fn go(&mut self, user_id: usize, cmd: Command, by_ghost: bool, ctx: &mut Context<Self>) -> Result<()> {
+        let finished = self.next(user_id, cmd)?;
+        let game = self.game.as_mut().unwrap();
+        if let Some(moves) = game.moves.get_mut(user_id) {
+            *moves += 1;
+        }
+        if by_ghost {
+            if let Some(moves) = game.ghost_moves.get_mut(user_id) {
+                *moves += 1;
+            }
+        }
+        let game = self.game.as_ref().unwrap();
+        let _ = save_state(
+            &SaveStateForm {
+                game_id: game.id,
+                room_id: self.info.uid,
+                number: game.no,
+                state: game.game.get_state(),
+                by_ghost,
+                takeback: false,
+            },
+            self.pool.clone(),
+        );
+
+        if finished {
+            self.summarize(ctx);
+            self.end_game(ctx);
+        }
+
+        self.schedule_ghost(ctx);
+        Ok(())
+    }
+
+    /// Clears the game and everything going on with it.
+    fn end_game(&mut self, ctx: &mut Context<Self>) {
+        self.reveal();
+        for handle in [self.ghost_handle.take(), self.vote_handle.take()].iter().flatten() {
+            ctx.cancel_future(*handle);
+        }
+        self.info.is_game = false;
+        self.info.ghost = 0;
+        self.info.ready = 0;
+        self.info.vote = None;
+        self.info.paused = false;
+        self.game = None;
+        self.drop_absent(ctx);
+    }
+
+    /// Seats which can vote for the vote going on
+    fn voters(&self, kind: VoteKind) -> u8 {
+        let mut voters = 0;
+        for (seat, user_no) in self.info.user.iter().enumerate() {
+            if user_no.0 != 0 && self.info.ghost & (1 << seat) == 0 {
+                voters |= 1 << seat;
+            }
+        }
+        if let VoteKind::ReplaceWithBot(target) = kind {
+            voters &= !(1 << target);
+        }
+        voters
+    }
+
+    /// Finishes the vote if it passed or can't pass anymore.
+    fn count_vote(&mut self, ctx: &mut Context<Self>) {
+        let vote = match &self.info.vote {
+            Some(vote) => vote.clone(),
+            None => return,
+        };
+        let voters = self.voters(vote.kind);
+        let total = voters.count_ones() as usize;
+        let needed = self.info.vote_rule.threshold(vote.kind) as usize * total;
+        let yes = (vote.yes & voters).count_ones() as usize;
+        let no = (vote.no & voters).count_ones() as usize;
+
+        if total == 0 {
+            // nobody can vote, e.g. every other seat is a ghost
+            self.finish_vote(ctx);
+        } else if yes * 100 >= needed {
+            self.finish_vote(ctx);
+            self.pass_vote(&vote, ctx);
+        } else if (total - no) * 100 < needed {
+            self.finish_vote(ctx);
+        }
+        self.spread_info();
+    }
+
+    fn finish_vote(&mut self, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.vote_handle.take() {
+            ctx.cancel_future(handle);
+        }
+        self.info.vote = None;
+    }
+
+    fn pass_vote(&mut self, vote: &Vote, ctx: &mut Context<Self>) {
+        let now = now_millis();
+        match vote.kind {
+            VoteKind::Abort => self.end_game(ctx),
+            VoteKind::Pause => {
+                if let Some(handle) = self.ghost_handle.take() {
+                    ctx.cancel_future(handle);
+                }
+                if let Some(game) = &mut self.game {
+                    game.paused_at = Some(now);
+                }
+                self.info.paused = true;
+                self.spread_clock();
+            }
+            VoteKind::Resume => {
+                if let Some(game) = &mut self.game {
+                    if let Some(paused_at) = game.paused_at.take() {
+                        game.turn_start += now.saturating_sub(paused_at);
+                    }
+                }
+                self.info.paused = false;
+                self.spread_clock();
+                self.schedule_ghost(ctx);
+            }
+            VoteKind::ReplaceWithBot(seat) => {
+                self.info.ghost |= 1 << seat;
+                self.schedule_ghost(ctx);
+            }
+            VoteKind::Undo => {
+                if let Some(seat) = self.info.user.iter().position(|x| *x == vote.proposer) {
+                    ignore!(self.undo(seat, ctx));
+                }
+            }
+        }
+        self.checkpoint();
+    }
+
+    /// Takes back the last command of the user and records it as a takeback.
+    fn undo(&mut self, user_id: usize, ctx: &mut Context<Self>) -> Result<()> {
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => bail!("game not started"),
+        };
+        game.game.undo(user_id)?;
+        game.no += 1;
+        game.turn_start = now_millis();
+        let _ = save_state(
+            &SaveStateForm {
+                game_id: game.id,
+                room_id: self.info.uid,
+                number: game.no,
+                state: game.game.get_state(),
+                by_ghost: false,
+                takeback: true,
+            },
+            self.pool.clone(),
+        );
+        self.spread_game();
+        self.schedule_ghost(ctx);
+        Ok(())
+    }
+
+    /// Plays one move of a ghost after `GHOST_DELAY` if any ghost should act.
+    fn schedule_ghost(&mut self, ctx: &mut Context<Self>) {
+        if let Some(handle) = self.ghost_handle.take() {
+            ctx.cancel_future(handle);
+        }
+        let game = match &self.game {
+            Some(game) => &game.game,
+            None => return,
+        };
+        if self.info.paused || waiting_users(&game.state, &game.rule) & self.info.ghost == 0 {
+            return;
+        }
+
+        self.ghost_handle = Some(ctx.run_later(GHOST_DELAY, |act, ctx| {
+            act.ghost_handle = None;
+            let game = match &act.game {
+                Some(game) => &game.game,
+                None => return,
+            };
+            let waiting = waiting_users(&game.state, &game.rule) & act.info.ghost;
+            let user_id = match (0..act.info.user.len()).find(|i| waiting & (1 << i) > 0) {
+                Some(user_id) => user_id,
+                None => return,
+            };
+            let cmd = BasicAgent.decide(user_id, &game.state, &game.rule);
+            if act.go(user_id, cmd, true, ctx).is_err() {
+                let _ = act.go(user_id, Command::Random, true, ctx);
+            }
+        }));
+    }
+
+    /// Recent chats to send, including the ones of observers if `observer` is set.
+    fn chat_log(&self, observer: bool) -> Vec<(String, UserNo)> {
+        self.chat
+            .iter()
+            .filter(|(_, _, by_observer)| observer || !by_observer)
+            .map(|(chat, no, _)| (chat.clone(), *no))
+            .collect()
+    }
+
+    /// State of the game which the user can see.
+    /// Seated users see the state as it is, and the others follow the spectator policy.
+    fn user_state(&self, user_no: UserNo) -> Option<State> {
+        let game = &self.game.as_ref()?.game;
+        if self.info.user.contains(&user_no) {
+            return Some(game.get_state());
+        }
+        self.observer_state(user_no)
+    }
+
+    /// State of the game which the observer can see, or `None` if there's nothing to see yet.
+    fn observer_state(&self, user_no: UserNo) -> Option<State> {
+        let game = &self.game.as_ref()?.game;
+        let coached = self
+            .info
+            .coach
+            .iter()
+            .filter(|(observer, _)| *observer == user_no)
+            .filter_map(|(_, user)| self.info.user.iter().position(|x| x == user))
+            .fold(0, |visible, seat| visible | 1 << seat);
+
+        match self.info.spectator.view {
+            SpectatorView::Full => Some(game.get_state()),
+            SpectatorView::Delayed(_) if game.is_finished() => Some(game.get_state()),
+            SpectatorView::Hidden => Some(game.state.masked(coached)),
+            SpectatorView::Delayed(_) if coached != 0 => Some(game.state.masked(coached)),
+            SpectatorView::Delayed(delay) => {
+                let now = game.state.trick_count();
+                game.states().into_iter().rev().find(|s| s.trick_count() + delay <= now)
+            }
+        }
+    }
+
+    fn resync_observe(&self, addr: &Addr<Session<Observe>>, user_no: UserNo) {
+        addr.do_send(ObserveToClient::Room(self.info.clone()));
+        addr.do_send(ObserveToClient::Rule(self.rule.clone()));
+        if let Some(game) = &self.game {
+            if let Some(state) = self.observer_state(user_no) {
+                addr.do_send(ObserveToClient::Game(state));
+            }
+            addr.do_send(ObserveToClient::Clock(game.clock()));
+        }
+        addr.do_send(ObserveToClient::ChatLog(self.chat_log(true)));
+    }
+
     fn spread_info(&self) {
         for (_, i) in self.user_addr.iter() {
             i.do_send(GotRoomInfo(self.info.clone()));
         }
 
-        for i in self.observe.iter() {
+        for i in self.observe.keys() {
             i.do_send(ObserveToClient::Room(self.info.clone()));
         }
 
@@ -392,19 +1487,146 @@ impl Room {
         }
     }
 
+    fn spread_rule(&self) {
+        for (_, i) in self.user_addr.iter() {
+            i.do_send(GotRule(self.rule.clone()));
+        }
+    }
+
+    fn spread_clock(&self) {
+        let clock = match &self.game {
+            Some(game) => game.clock(),
+            None => return,
+        };
+        for (_, i) in self.user_addr.iter() {
+            i.do_send(GotClock(clock));
+        }
+
+        for i in self.observe.keys() {
+            i.do_send(ObserveToClient::Clock(clock));
+        }
+    }
+
     // assert: game is not `None`
     fn spread_game(&self) {
-        let state = self.game.as_ref().unwrap().game.get_state();
+        for (user_no, i) in self.user_addr.iter() {
+            if let Some(state) = self.user_state(*user_no) {
+                i.do_send(GotGameState(state));
+            }
+        }
+
+        for (i, user_no) in self.observe.iter() {
+            if let Some(state) = self.observer_state(*user_no) {
+                i.do_send(ObserveToClient::Game(state));
+            }
+        }
+        self.checkpoint();
+    }
+
+    /// Sends every state of the game to users and observers if the spectator policy allows.
+    fn reveal(&self) {
+        let game = match &self.game {
+            Some(game) if self.info.spectator.reveal => &game.game,
+            _ => return,
+        };
+        let states = game.states();
         for (_, i) in self.user_addr.iter() {
-            i.do_send(GotGameState(state.clone()));
+            i.do_send(GotReveal(states.clone()));
         }
 
-        for i in self.observe.iter() {
-            i.do_send(ObserveToClient::Game(state.clone()));
+        for i in self.observe.keys() {
+            i.do_send(ObserveToClient::Reveal(states.clone()));
         }
     }
 
-    /*fn calculate_rating(&self, score: i32) -> i32 {
+    /// Applies the rating changes and sends the summary of the game which just ended.
+    fn summarize(&self, ctx: &mut Context<Self>) {
+        let game = match &self.game {
+            Some(game) => game,
+            None => return,
+        };
+        let summary = match game.game.summary() {
+            Some(summary) => summary,
+            None => return,
+        };
+        let users = self.info.user.clone();
+        let record = RecordGame {
+            game_id: game.id,
+            users: users.clone(),
+            state: game.game.state.clone(),
+            record: GameRecord::new(game.id.to_string(), self.info.rule, self.info.is_rank, &summary),
+            abandoned: rating::abandoned(&game.moves, &game.ghost_moves, &users),
+            penalty: self.ranked.abandon_penalty,
+        };
+        self.hub.do_send(AnalyzeGame(game.id));
+
+        // the summary is sent after the ratings are settled in the background
+        ctx.spawn(self.recorder.send(record).into_actor(self).map(move |res, act, _| {
+            let settled = res.unwrap_or_default();
+            act.hub.do_send(NotifyRating(
+                users
+                    .iter()
+                    .zip(settled.iter())
+                    .filter(|(user_no, _)| user_no.0 != 0)
+                    .map(|(user_no, (glicko, _))| (*user_no, glicko.display()))
+                    .collect(),
+            ));
+            let summary = GameSummary {
+                game: summary,
+                rating: (0..users.len())
+                    .map(|i| settled.get(i).map(|(_, diff)| *diff).unwrap_or(0))
+                    .collect(),
+                user: users,
+            };
+            for (_, i) in act.user_addr.iter() {
+                i.do_send(GotSummary(summary.clone()));
+            }
+            for i in act.observe.keys() {
+                i.do_send(ObserveToClient::Summary(summary.clone()));
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::prelude::Rule;
 
-    }*/
+    fn info(user: Vec<UserNo>, locked: u8) -> RoomInfo {
+        RoomInfo {
+            user,
+            locked,
+            ..RoomInfo::new(
+                RoomUid::generate_random(),
+                RoomId(0),
+                String::new(),
+                RuleHash::generate(&Rule::new()),
+                false,
+                0,
+            )
+        }
+    }
+
+    #[test]
+    fn place_test() {
+        let mut room = info(vec![UserNo(1), UserNo(0), UserNo(0)], 0b010);
+        assert_eq!(place(&mut room, UserNo(2)), Some(2));
+        assert_eq!(room.user, vec![UserNo(1), UserNo(0), UserNo(2)]);
+
+        // only the locked seat is left, so the user observes
+        assert_eq!(place(&mut room, UserNo(3)), None);
+        assert_eq!(room.user, vec![UserNo(1), UserNo(0), UserNo(2)]);
+        assert_eq!(room.standing, vec![UserNo(3)]);
+    }
+
+    #[test]
+    fn hash_password_test() {
+        let (a, b) = (RoomUid::generate_random(), RoomUid::generate("other"));
+        let hash = hash_password(a, "hunter2", "secret");
+        assert_eq!(hash, hash_password(a, "hunter2", "secret"));
+        assert!(!hash.contains("hunter2"));
+        assert_ne!(hash, hash_password(b, "hunter2", "secret"));
+        assert_ne!(hash, hash_password(a, "hunter3", "secret"));
+    }
 }
@@ -21,9 +21,9 @@ impl SessionTrait for RoomUser {
         act.inner.user.do_send(UserDisconnect::Room(ctx.address()));
     }
 
-    fn receive(act: &mut Session<Self>, msg: String, _: &mut WebsocketContext<Session<Self>>) {
+    fn receive(act: &mut Session<Self>, msg: String, ctx: &mut WebsocketContext<Session<Self>>) {
         let msg: RoomUserToServer = serde_json::from_str(&*msg).unwrap();
-        act.inner.user.do_send(UserCommand(msg));
+        act.inner.user.do_send(UserCommand(msg, ctx.address()));
     }
 }
 
@@ -1,11 +1,13 @@
 use crate::actor::hub::GetRoom;
-use crate::actor::room::{ChangeName, ChangeRule, Chat, Go, RoomJoin, RoomLeave, StartGame};
+use crate::actor::room::{
+    Ballot, ChangeName, ChangeRule, ChangeSpectatorPolicy, ChangeVoteRule, Chat, Coach, Ghost, Go, Invite, Kick,
+    Privacy, Propose, Ready, Rejoin, Resync, RoomJoin, RoomLeave, Seat, StartGame, StartOption, TransferHead,
+};
 use crate::actor::session::Session;
 use crate::actor::{Hub, Main, Room, RoomUser};
-use crate::db::game::{change_rating, ChangeRatingForm};
 use crate::dev::*;
 use actix::prelude::*;
-use mighty::prelude::State;
+use mighty::prelude::{Rule, State};
 use std::collections::HashSet;
 use std::time::{Duration, SystemTime};
 
@@ -31,7 +33,6 @@ pub struct User {
     room: Option<JoinedRoom>,
     subscribers: HashSet<Addr<Session<Main>>>,
     hub: Addr<Hub>,
-    pool: Pool,
 }
 
 impl Actor for User {
@@ -56,7 +57,13 @@ impl Handler<UserConnect> for User {
         match msg {
             UserConnect::Room(addr) => {
                 ensure!(self.room.is_some(), StatusCode::BAD_REQUEST, "no joined room");
-                self.room.as_mut().unwrap().group.insert(addr);
+                let room = self.room.as_mut().unwrap();
+                if room.group.is_empty() && room.info.is_game {
+                    room.addr.do_send(Ghost(self.info.no, false));
+                }
+                room.addr.do_send(Resync::User(self.info.no, addr.clone()));
+                room.group.insert(addr);
+                self.update_status();
             }
             UserConnect::Subscribe(addr) => {
                 addr.do_send(MainToClient::UserStatus(self.info.no, self.status));
@@ -94,7 +101,9 @@ impl Handler<UserDisconnect> for User {
                 room.disconn += 1;
                 self.update_status();
                 ctx.run_later(RECONNECTION_TIME, |act, _| {
-                    act.room.as_mut().unwrap().disconn -= 1;
+                    if let Some(room) = act.room.as_mut() {
+                        room.disconn = room.disconn.saturating_sub(1);
+                    }
                     act.update_status();
                 });
             }
@@ -114,11 +123,11 @@ impl Handler<UserDisconnect> for User {
     }
 }
 
-/// Joins to the room
+/// Joins to the room with the key if the room is private
 /// It would respond `room_info` so that the client can know if the client is joined.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
-pub struct UserJoin(pub RoomId, pub Addr<Session<Main>>);
+pub struct UserJoin(pub RoomId, pub Addr<Session<Main>>, pub Option<RoomKey>);
 
 impl Handler<UserJoin> for User {
     type Result = ();
@@ -133,11 +142,12 @@ impl Handler<UserJoin> for User {
             .then(|res, act, ctx| {
                 if let Ok(Ok(room)) = res {
                     let room_addr = room.clone();
-                    room.send(RoomJoin::User(act.info.no, ctx.address()))
+                    room.send(RoomJoin::User(act.info.no, ctx.address(), msg.2.clone()))
                         .into_actor(act)
                         .then(move |res, act, _| {
                             if let Ok(info) = res {
-                                if info.user.contains(&act.info.no) {
+                                if info.user.contains(&act.info.no) || info.standing.contains(&act.info.no) {
+                                    msg.1.do_send(MainToClient::Joined(info.clone()));
                                     act.room = Some(JoinedRoom {
                                         addr: room_addr,
                                         info,
@@ -160,7 +170,40 @@ impl Handler<UserJoin> for User {
     }
 }
 
+/// Puts the user back to the room restored after the server restarts
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct UserRejoin(pub Addr<Room>);
+
+impl Handler<UserRejoin> for User {
+    type Result = ();
+
+    fn handle(&mut self, msg: UserRejoin, ctx: &mut Self::Context) -> Self::Result {
+        if self.room.is_some() {
+            return;
+        }
+        let room_addr = msg.0.clone();
+        msg.0
+            .send(Rejoin(self.info.no, ctx.address()))
+            .into_actor(self)
+            .then(move |res, act, _| {
+                if let Ok(Some(info)) = res {
+                    act.room = Some(JoinedRoom {
+                        addr: room_addr,
+                        info,
+                        group: HashSet::new(),
+                        disconn: 0,
+                    });
+                    act.update_status();
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+}
+
 /// Leaves the room
+/// The user can't leave the room while playing the game.
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
 pub struct UserLeave;
@@ -169,17 +212,19 @@ impl Handler<UserLeave> for User {
     type Result = ();
 
     fn handle(&mut self, _: UserLeave, _: &mut Self::Context) -> Self::Result {
-        if self.room.is_none() {
+        if self.room.is_none() || self.is_playing() {
             return;
         }
-        self.room.as_ref().unwrap().addr.do_send(RoomLeave::User(self.info.no));
+        let room = self.room.take().unwrap();
+        room.addr.do_send(RoomLeave::User(self.info.no));
+        self.update_status();
     }
 }
 
 /// Commands that user sent
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
-pub struct UserCommand(pub RoomUserToServer);
+pub struct UserCommand(pub RoomUserToServer, pub Addr<Session<RoomUser>>);
 
 impl Handler<UserCommand> for User {
     type Result = ();
@@ -206,10 +251,84 @@ impl Handler<UserCommand> for User {
             RoomUserToServer::Chat(chat) => {
                 room.addr.do_send(Chat::User(chat, self.info.no));
             }
+            RoomUserToServer::Resync => {
+                room.addr.do_send(Resync::User(user_no, msg.1));
+            }
+            RoomUserToServer::Sit(seat) => {
+                room.addr.do_send(Seat::Sit(user_no, seat));
+            }
+            RoomUserToServer::Stand => {
+                room.addr.do_send(Seat::Stand(user_no));
+            }
+            RoomUserToServer::Swap(seat) => {
+                room.addr.do_send(Seat::Swap(user_no, seat));
+            }
+            RoomUserToServer::Lock(seat, lock) => {
+                room.addr.do_send(Seat::Lock(user_no, seat, lock));
+            }
+            RoomUserToServer::Ready(ready) => {
+                room.addr.do_send(Ready(user_no, ready));
+            }
+            RoomUserToServer::SetAutoStart(auto_start) => {
+                room.addr.do_send(StartOption::AutoStart(user_no, auto_start));
+            }
+            RoomUserToServer::SetBotFill(bot_fill) => {
+                room.addr.do_send(StartOption::BotFill(user_no, bot_fill));
+            }
+            RoomUserToServer::Kick(target) => {
+                room.addr.do_send(Kick(user_no, target));
+            }
+            RoomUserToServer::TransferHead(target) => {
+                room.addr.do_send(TransferHead(user_no, target));
+            }
+            RoomUserToServer::SetPassword(password) => {
+                room.addr.do_send(Privacy::Password(user_no, password));
+            }
+            RoomUserToServer::SetInviteOnly(invite_only) => {
+                room.addr.do_send(Privacy::InviteOnly(user_no, invite_only));
+            }
+            RoomUserToServer::Invite => {
+                room.addr.do_send(Invite(user_no, msg.1));
+            }
+            RoomUserToServer::Propose(kind) => {
+                room.addr.do_send(Propose(user_no, kind));
+            }
+            RoomUserToServer::Vote(yes) => {
+                room.addr.do_send(Ballot(user_no, yes));
+            }
+            RoomUserToServer::SetVoteRule(rule) => {
+                room.addr.do_send(ChangeVoteRule(user_no, rule));
+            }
+            RoomUserToServer::SetSpectatorPolicy(policy) => {
+                room.addr.do_send(ChangeSpectatorPolicy(user_no, policy));
+            }
+            RoomUserToServer::Coach(observer, allow) => {
+                room.addr.do_send(Coach(user_no, observer, allow));
+            }
         }
     }
 }
 
+/// When the user is kicked from the room
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct Kicked(pub RoomId);
+
+impl Handler<Kicked> for User {
+    type Result = ();
+
+    fn handle(&mut self, msg: Kicked, _: &mut Self::Context) -> Self::Result {
+        if self.room.as_ref().map(|r| r.info.id) != Some(msg.0) {
+            return;
+        }
+        let room = self.room.take().unwrap();
+        for i in room.group.iter() {
+            i.do_send(RoomUserToClient::Kicked);
+        }
+        self.update_status();
+    }
+}
+
 /// Request for room info
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
@@ -226,7 +345,30 @@ impl Handler<GotRoomInfo> for User {
         for i in room.group.iter() {
             i.do_send(RoomUserToClient::Room(msg.0.clone()));
         }
+        let game_ended = room.info.is_game && !msg.0.is_game;
         room.info = msg.0;
+        if game_ended {
+            // the user who left during the game leaves the seat now
+            self.update_status();
+        }
+    }
+}
+
+/// Passing rule of the room to user
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct GotRule(pub Rule);
+
+impl Handler<GotRule> for User {
+    type Result = ();
+
+    fn handle(&mut self, msg: GotRule, _: &mut Self::Context) -> Self::Result {
+        if self.room.is_none() {
+            return;
+        }
+        for i in self.room.as_ref().unwrap().group.iter() {
+            i.do_send(RoomUserToClient::Rule(msg.0.clone()));
+        }
     }
 }
 
@@ -248,6 +390,93 @@ impl Handler<GotGameState> for User {
     }
 }
 
+/// Passing clock of the game to user
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct GotClock(pub Clock);
+
+impl Handler<GotClock> for User {
+    type Result = ();
+
+    fn handle(&mut self, msg: GotClock, _: &mut Self::Context) -> Self::Result {
+        if self.room.is_none() {
+            return;
+        }
+        for i in self.room.as_ref().unwrap().group.iter() {
+            i.do_send(RoomUserToClient::Clock(msg.0));
+        }
+    }
+}
+
+/// Passing every state of the game which just ended to user
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct GotReveal(pub Vec<State>);
+
+impl Handler<GotReveal> for User {
+    type Result = ();
+
+    fn handle(&mut self, msg: GotReveal, _: &mut Self::Context) -> Self::Result {
+        if self.room.is_none() {
+            return;
+        }
+        for i in self.room.as_ref().unwrap().group.iter() {
+            i.do_send(RoomUserToClient::Reveal(msg.0.clone()));
+        }
+    }
+}
+
+/// Passing the summary of the game which just ended to user
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct GotSummary(pub GameSummary);
+
+impl Handler<GotSummary> for User {
+    type Result = ();
+
+    fn handle(&mut self, msg: GotSummary, _: &mut Self::Context) -> Self::Result {
+        if self.room.is_none() {
+            return;
+        }
+        for i in self.room.as_ref().unwrap().group.iter() {
+            i.do_send(RoomUserToClient::Summary(msg.0.clone()));
+        }
+    }
+}
+
+/// When the server is going down for maintenance
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub struct GotMaintenance;
+
+impl Handler<GotMaintenance> for User {
+    type Result = ();
+
+    fn handle(&mut self, _: GotMaintenance, _: &mut Self::Context) -> Self::Result {
+        if self.room.is_none() {
+            return;
+        }
+        for i in self.room.as_ref().unwrap().group.iter() {
+            i.do_send(RoomUserToClient::Maintenance);
+        }
+    }
+}
+
+/// Request for the information of this user
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "UserInfo")]
+pub struct GetInfo;
+
+impl Handler<GetInfo> for User {
+    type Result = UserInfo;
+
+    fn handle(&mut self, _: GetInfo, _: &mut Self::Context) -> Self::Result {
+        let mut info = self.info.clone();
+        info.room = self.room.as_ref().map(|r| r.info.id);
+        info
+    }
+}
+
 /// Update for absent
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
@@ -265,31 +494,16 @@ impl Handler<Update> for User {
     }
 }
 
-/// change
+/// When the rating of the user is changed after the game
 #[derive(Debug, Clone, Message)]
 #[rtype(result = "()")]
-pub struct ChangeRating(pub i32, pub GameId);
+pub struct ChangeRating(pub u32);
 
 impl Handler<ChangeRating> for User {
     type Result = ();
 
     fn handle(&mut self, msg: ChangeRating, _: &mut Self::Context) -> Self::Result {
-        if msg.0 < 0 {
-            if (-msg.0) as u32 > self.info.rating {
-                self.info.rating = 0;
-            } else {
-                self.info.rating -= (-msg.0) as u32;
-            }
-        } else {
-            self.info.rating += msg.0 as u32;
-        }
-        let form = ChangeRatingForm {
-            user_no: self.info.no.0,
-            game_id: msg.1,
-            diff: msg.0 as u32,
-            rating: self.info.rating,
-        };
-        let _ = change_rating(&form, self.pool.clone());
+        self.info.rating = msg.0;
     }
 }
 
@@ -313,7 +527,7 @@ impl Handler<SendChat> for User {
 }
 
 impl User {
-    pub fn new(info: UserInfo, hub: Addr<Hub>, pool: Pool) -> User {
+    pub fn new(info: UserInfo, hub: Addr<Hub>) -> User {
         User {
             info,
             status: UserStatus::OFFLINE,
@@ -323,14 +537,23 @@ impl User {
             room: None,
             subscribers: HashSet::new(),
             hub,
-            pool,
+        }
+    }
+
+    /// If the user is seated in the room which is on gaming
+    fn is_playing(&self) -> bool {
+        match &self.room {
+            Some(room) => room.info.is_game && room.info.user.contains(&self.info.no),
+            None => false,
         }
     }
 
     fn get_status(&mut self) -> UserStatus {
         let mut status = UserStatus::empty();
         let mut leave_room = false;
+        let mut ghost = false;
 
+        let is_playing = self.is_playing();
         if let Some(room) = &self.room {
             status |= UserStatus::IN_ROOM;
 
@@ -341,8 +564,8 @@ impl User {
             if room.group.is_empty() {
                 if room.disconn > 0 {
                     status |= UserStatus::ROOM_DISCONN;
-                } else if room.info.is_game {
-                    // todo: ghost
+                } else if is_playing {
+                    ghost = true;
                 } else {
                     leave_room = true;
                 }
@@ -353,6 +576,10 @@ impl User {
             self.room.as_ref().unwrap().addr.do_send(RoomLeave::User(self.info.no));
         }
 
+        if ghost {
+            self.room.as_ref().unwrap().addr.do_send(Ghost(self.info.no, true));
+        }
+
         if self.conn == 0 {
             if self.disconn == 0 {
                 status |= UserStatus::OFFLINE;
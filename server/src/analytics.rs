@@ -0,0 +1,277 @@
+//! # Analytics module
+//!
+//! Every finished game is indexed by its rule, pledge, giruda, how the friend was called and the outcome.
+//! Admins can aggregate the indexed games grouped by one of them, and export the result as json or csv.
+
+use crate::db::analytics::{get_unindexed_games, index_game};
+use crate::db::game::{game_line, get_rule, get_states, GetRuleForm};
+use crate::dev::*;
+use mighty::prelude::{Card, FriendFunc, Pattern, Summary};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// How the friend was called
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FriendKind {
+    None,
+    Mighty,
+    Joker,
+    Card,
+    User,
+    First,
+    Last,
+}
+
+impl FriendKind {
+    pub fn new(func: &FriendFunc, giruda: Option<Pattern>) -> FriendKind {
+        let mighty = match giruda {
+            Some(Pattern::Spade) => Card::Normal(Pattern::Diamond, 14),
+            _ => Card::Normal(Pattern::Spade, 14),
+        };
+        match func {
+            FriendFunc::None => FriendKind::None,
+            FriendFunc::ByCard(card) if *card == mighty => FriendKind::Mighty,
+            FriendFunc::ByCard(Card::Joker(_)) => FriendKind::Joker,
+            FriendFunc::ByCard(_) => FriendKind::Card,
+            FriendFunc::ByUser(_) => FriendKind::User,
+            FriendFunc::First => FriendKind::First,
+            FriendFunc::Last => FriendKind::Last,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FriendKind::None => "none",
+            FriendKind::Mighty => "mighty",
+            FriendKind::Joker => "joker",
+            FriendKind::Card => "card",
+            FriendKind::User => "user",
+            FriendKind::First => "first",
+            FriendKind::Last => "last",
+        }
+    }
+}
+
+/// Giruda in the code of the card pattern, or `none`
+pub fn giruda_code(giruda: Option<Pattern>) -> String {
+    match giruda {
+        Some(pattern) => serde_json::to_value(pattern)
+            .ok()
+            .and_then(|v| v.as_str().map(|s| s.to_owned()))
+            .unwrap_or_default(),
+        None => "none".to_owned(),
+    }
+}
+
+/// Indexed game
+///
+/// - `won`: if the ruling party won
+/// - `points`: count of point cards taken by each seat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub game_id: String,
+    pub rule: String,
+    pub is_rank: bool,
+    pub pledge: u8,
+    pub giruda: Option<Pattern>,
+    pub friend: FriendKind,
+    pub won: bool,
+    pub score: u8,
+    pub points: Vec<u8>,
+}
+
+impl GameRecord {
+    pub fn new(game_id: String, rule: RuleHash, is_rank: bool, summary: &Summary) -> GameRecord {
+        GameRecord {
+            game_id,
+            rule: rule.to_string(),
+            is_rank,
+            pledge: summary.pledge,
+            giruda: summary.giruda,
+            friend: FriendKind::new(&summary.friend_func, summary.giruda),
+            won: summary.winner & (1 << summary.president) != 0,
+            score: summary.score,
+            points: summary.points.iter().map(|v| v.len() as u8).collect(),
+        }
+    }
+}
+
+/// Field to group the games by
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    #[default]
+    All,
+    Rule,
+    Pledge,
+    Giruda,
+    Friend,
+}
+
+impl GroupBy {
+    fn key(&self, record: &GameRecord) -> String {
+        match self {
+            GroupBy::All => "all".to_owned(),
+            GroupBy::Rule => record.rule.clone(),
+            GroupBy::Pledge => record.pledge.to_string(),
+            GroupBy::Giruda => giruda_code(record.giruda),
+            GroupBy::Friend => record.friend.as_str().to_owned(),
+        }
+    }
+}
+
+/// Aggregate of the games in a group
+///
+/// - `wins`: games the ruling party won
+/// - `avg_points`: average point cards taken by each seat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub key: String,
+    pub games: u32,
+    pub wins: u32,
+    pub win_rate: f64,
+    pub avg_score: f64,
+    pub avg_points: Vec<f64>,
+}
+
+/// Aggregates the games by `group`, sorted by the key.
+pub fn aggregate(records: &[GameRecord], group: GroupBy) -> Vec<Aggregate> {
+    let mut aggregates = Vec::<Aggregate>::new();
+    for record in records {
+        let key = group.key(record);
+        let pos = match aggregates.iter().position(|a| a.key == key) {
+            Some(pos) => pos,
+            None => {
+                aggregates.push(Aggregate {
+                    key,
+                    games: 0,
+                    wins: 0,
+                    win_rate: 0.0,
+                    avg_score: 0.0,
+                    avg_points: Vec::new(),
+                });
+                aggregates.len() - 1
+            }
+        };
+        let aggregate = &mut aggregates[pos];
+        aggregate.games += 1;
+        aggregate.wins += record.won as u32;
+        aggregate.avg_score += record.score as f64;
+        if aggregate.avg_points.len() < record.points.len() {
+            aggregate.avg_points.resize(record.points.len(), 0.0);
+        }
+        for (sum, points) in aggregate.avg_points.iter_mut().zip(record.points.iter()) {
+            *sum += *points as f64;
+        }
+    }
+
+    for aggregate in aggregates.iter_mut() {
+        let games = aggregate.games as f64;
+        aggregate.win_rate = aggregate.wins as f64 / games;
+        aggregate.avg_score /= games;
+        for points in aggregate.avg_points.iter_mut() {
+            *points /= games;
+        }
+    }
+    aggregates.sort_by(|a, b| (a.key.parse::<u32>().ok(), &a.key).cmp(&(b.key.parse::<u32>().ok(), &b.key)));
+    aggregates
+}
+
+/// Csv of the aggregates, where `avg_points` is separated by `;`
+pub fn to_csv(aggregates: &[Aggregate]) -> String {
+    let mut csv = "key,games,wins,win_rate,avg_score,avg_points\n".to_owned();
+    for a in aggregates {
+        let points = a
+            .avg_points
+            .iter()
+            .map(|x| format!("{:.3}", x))
+            .collect::<Vec<_>>()
+            .join(";");
+        let _ = writeln!(
+            csv,
+            "{},{},{},{:.4},{:.3},{}",
+            a.key, a.games, a.wins, a.win_rate, a.avg_score, points
+        );
+    }
+    csv
+}
+
+/// Indexes the finished games saved before the analytics, and returns how many games are indexed.
+pub fn index_missing_games(pool: Pool) -> Result<usize> {
+    let games = get_unindexed_games(pool.clone())?;
+    let mut count = 0;
+    for (game_id, rule_hash, is_rank) in games {
+        let saved = get_states(&game_id, pool.clone())?;
+        let states = game_line(&saved)
+            .into_iter()
+            .map(|i| saved[i].state.clone())
+            .collect::<Vec<_>>();
+        let rule = get_rule(&GetRuleForm { rule_hash }, pool.clone())?;
+        if let Some(summary) = Summary::from_states(&states, &rule) {
+            index_game(&GameRecord::new(game_id, rule_hash, is_rank, &summary), pool.clone())?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::prelude::Color;
+
+    fn record(pledge: u8, giruda: Option<Pattern>, friend: FriendKind, won: bool) -> GameRecord {
+        GameRecord {
+            game_id: String::new(),
+            rule: String::new(),
+            is_rank: false,
+            pledge,
+            giruda,
+            friend,
+            won,
+            score: if won { pledge + 1 } else { pledge - 2 },
+            points: vec![6, 4, 2, 8, 0],
+        }
+    }
+
+    #[test]
+    fn friend_kind_test() {
+        let mighty = FriendFunc::ByCard(Card::Normal(Pattern::Spade, 14));
+        assert_eq!(FriendKind::new(&mighty, Some(Pattern::Heart)), FriendKind::Mighty);
+        assert_eq!(FriendKind::new(&mighty, Some(Pattern::Spade)), FriendKind::Card);
+        let joker = FriendFunc::ByCard(Card::Joker(Color::Black));
+        assert_eq!(FriendKind::new(&joker, None), FriendKind::Joker);
+        assert_eq!(FriendKind::new(&FriendFunc::First, None), FriendKind::First);
+        assert_eq!(giruda_code(Some(Pattern::Clover)), "c");
+        assert_eq!(giruda_code(None), "none");
+    }
+
+    #[test]
+    fn aggregate_test() {
+        let records = [
+            record(15, Some(Pattern::Spade), FriendKind::Mighty, true),
+            record(15, Some(Pattern::Heart), FriendKind::Joker, false),
+            record(13, Some(Pattern::Heart), FriendKind::Mighty, true),
+            record(9, None, FriendKind::Mighty, false),
+        ];
+        let by_pledge = aggregate(&records, GroupBy::Pledge);
+        let keys = by_pledge.iter().map(|a| a.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["9", "13", "15"]);
+        assert_eq!((by_pledge[2].games, by_pledge[2].wins), (2, 1));
+        assert_eq!(by_pledge[2].win_rate, 0.5);
+        assert_eq!(by_pledge[2].avg_points, vec![6.0, 4.0, 2.0, 8.0, 0.0]);
+
+        let by_friend = aggregate(&records, GroupBy::Friend);
+        assert_eq!(by_friend[1].key, "mighty");
+        assert_eq!((by_friend[1].games, by_friend[1].wins), (3, 2));
+
+        let all = aggregate(&records, GroupBy::All);
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].avg_score, (16.0 + 13.0 + 14.0 + 7.0) / 4.0);
+
+        let csv = to_csv(&by_pledge);
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(3).unwrap().starts_with("15,2,1,0.5000,"));
+    }
+}
@@ -1,6 +1,8 @@
-use crate::actor::{Hub, Mail};
+use crate::actor::{Hub, Mail, Matchmaker};
+use crate::config::Ranked;
 use crate::db;
 use crate::dev::*;
+use crate::rating;
 use actix::prelude::*;
 use actix_web::web;
 use handlebars::{Handlebars, RenderError};
@@ -29,6 +31,7 @@ use {
 /// 4. `hub`: Websocket main server address.
 /// 5. `pool`: Database pool.
 /// 6. `mail`: Mail sender.
+/// 7. `matchmaker`: Ranked matchmaking queue address.
 pub struct AppState {
     #[cfg(not(feature = "watch-file"))]
     handlebars: Handlebars<'static>,
@@ -44,20 +47,32 @@ pub struct AppState {
     pub hub: Addr<Hub>,
     pub pool: Pool,
     pub mail: Addr<Mail>,
+    pub matchmaker: Addr<Matchmaker>,
     pub secret: String,
 }
 
 impl AppState {
     #[cfg(not(feature = "watch-file"))]
-    pub fn new<P: AsRef<Path>>(path: P, config: PgConfig, mail: Addr<Mail>, secret: String) -> web::Data<AppState> {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        config: PgConfig,
+        mail: Addr<Mail>,
+        secret: String,
+        ranked: Ranked,
+    ) -> web::Data<AppState> {
         let manager = PostgresConnectionManager::new(config, NoTls);
         let pool = Pool::new(manager).unwrap();
-        db::init(pool.clone()).expect("db init failed");
+        db::init(pool.clone())
+            .and_then(|_| rating::migrate(ranked.abandon_penalty, pool.clone()))
+            .expect("db init failed");
+        let hub = Hub::new(pool.clone(), secret.clone(), ranked).start();
+        let matchmaker = Matchmaker::new(hub.clone(), pool.clone(), ranked).start();
 
         web::Data::new(AppState {
             handlebars: make_handlebars(&path),
             resources: get_resources(&path),
-            hub: Hub::new(pool.clone()).start(),
+            hub,
+            matchmaker,
             pool,
             mail,
             secret,
@@ -65,7 +80,13 @@ impl AppState {
     }
 
     #[cfg(feature = "watch-file")]
-    pub fn new<P: AsRef<Path>>(path: P, config: PgConfig, mail: Addr<Mail>, secret: String) -> web::Data<AppState> {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        config: PgConfig,
+        mail: Addr<Mail>,
+        secret: String,
+        ranked: Ranked,
+    ) -> web::Data<AppState> {
         let path = path.as_ref();
         let (tx, rx) = channel();
         let mut watcher = raw_watcher(tx).unwrap();
@@ -74,13 +95,18 @@ impl AppState {
 
         let manager = PostgresConnectionManager::new(config, NoTls);
         let pool = Pool::new(manager).unwrap();
-        db::init(pool.clone()).expect("db init failed");
+        db::init(pool.clone())
+            .and_then(|_| rating::migrate(ranked.abandon_penalty, pool.clone()))
+            .expect("db init failed");
+        let hub = Hub::new(pool.clone(), secret.clone(), ranked).start();
+        let matchmaker = Matchmaker::new(hub.clone(), pool.clone(), ranked).start();
 
         let state = web::Data::new(AppState {
             handlebars: Mutex::new(make_handlebars(&path)),
             watcher,
             resources: Mutex::new(get_resources(&path)),
-            hub: Hub::new(pool.clone()).start(),
+            hub,
+            matchmaker,
             pool,
             mail,
             secret,
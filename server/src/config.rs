@@ -26,6 +26,9 @@
 //!   * `username`: username to mail server (defaults to `admin`)
 //!   * `password`: password to mail server (defaults to `admin`)
 //!   * `host`: host of mail server (defaults to `localhost:587`)
+//! - `ranked`: ranked game configuration
+//!   * `abandon_penalty`: rating taken from the user who abandoned the ranked game (defaults to `30`)
+//!   * `abandon_cooldown`: seconds the user can't join ranked rooms after abandoning (defaults to `1800`)
 //!
 //! # Examples
 //!
@@ -56,6 +59,10 @@
 //! username = "admin"
 //! password = "secret"
 //! host = "0.0.0.0"
+//!
+//! [ranked]
+//! abandon_penalty = 50
+//! abandon_cooldown = 3600
 //! ```
 //!
 //! ## Environment example
@@ -82,6 +89,9 @@
 //! MAIL__USERNAME="admin"
 //! MAIL__PASSWORD="secret"
 //! MAIL__HOST="0.0.0.0"
+//!
+//! RANKED__ABANDON_PENALTY="50"
+//! RANKED__ABANDON_COOLDOWN="3600"
 //! ```
 
 use crate::actor;
@@ -100,6 +110,7 @@ use slog_term::{FullFormat, PlainDecorator, TermDecorator};
 use std::env;
 use std::fs::OpenOptions;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Using `deadpoool_postgres::Config` just for serde
 type DpConfig = deadpool_postgres::Config;
@@ -258,6 +269,15 @@ impl Builder {
             }
         });
 
+        let mut abandon_penalty = None;
+        let mut abandon_cooldown = None;
+        for (_, c) in self.builders.iter() {
+            if let Some(ranked) = &c.ranked {
+                abandon_penalty = abandon_penalty.or(ranked.abandon_penalty);
+                abandon_cooldown = abandon_cooldown.or(ranked.abandon_cooldown);
+            }
+        }
+
         Config {
             postgres,
             host,
@@ -273,6 +293,10 @@ impl Builder {
                 password,
                 host: mail_host,
             },
+            ranked: Ranked {
+                abandon_penalty: abandon_penalty.unwrap_or(30),
+                abandon_cooldown: Duration::from_secs(abandon_cooldown.unwrap_or(30 * 60)),
+            },
         }
     }
 }
@@ -290,6 +314,7 @@ struct ConfigBuilder {
     serve_path: Option<String>,
     secret: Option<String>,
     mail: Option<MailBuilder>,
+    ranked: Option<RankedBuilder>,
 }
 
 /// Https configuration builder
@@ -310,6 +335,13 @@ struct MailBuilder {
     host: Option<String>,
 }
 
+/// Ranked game configuration builder
+#[derive(Debug, Clone, Default, Deserialize)]
+struct RankedBuilder {
+    abandon_penalty: Option<u32>,
+    abandon_cooldown: Option<u64>,
+}
+
 impl ConfigBuilder {
     /// Generate `ConfigBuilder` from the given path.
     pub fn from_file(path: PathBuf) -> ConfigBuilder {
@@ -337,6 +369,7 @@ pub struct Config {
     pub serve_path: PathBuf,
     pub secret: Vec<u8>,
     pub mail: Mail,
+    pub ranked: Ranked,
 }
 
 /// Https configuration struct
@@ -357,6 +390,13 @@ pub struct Mail {
     pub host: String,
 }
 
+/// Ranked game configuration struct
+#[derive(Debug, Clone, Copy)]
+pub struct Ranked {
+    pub abandon_penalty: u32,
+    pub abandon_cooldown: Duration,
+}
+
 impl Config {
     pub fn builder() -> Builder {
         Builder { builders: Vec::new() }
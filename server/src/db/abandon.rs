@@ -0,0 +1,62 @@
+use crate::dev::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Records the users of the `abandoned` seats as leavers of the ranked game.
+pub fn save_abandons(game_id: GameId, users: &[UserNo], abandoned: u8, pool: Pool) -> Result<()> {
+    let users = users
+        .iter()
+        .enumerate()
+        .filter(|(seat, user_no)| abandoned & (1 << seat) > 0 && user_no.0 != 0)
+        .map(|(_, user_no)| user_no.0 as i32)
+        .collect::<Vec<_>>();
+    if users.is_empty() {
+        return Ok(());
+    }
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "INSERT INTO abandons (user_no, game_id) SELECT unnest($1::INTEGER[]), $2 \
+         ON CONFLICT (game_id, user_no) DO NOTHING;",
+    )?;
+    client.execute(&stmt, &[&users, &game_id.to_string()])?;
+    Ok(())
+}
+
+/// Users who abandoned each ranked game
+pub fn get_all_abandons(pool: Pool) -> Result<HashMap<GameId, Vec<u32>>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT game_id, user_no FROM abandons;")?;
+    let mut abandons = HashMap::<GameId, Vec<u32>>::new();
+    for row in client.query(&stmt, &[])? {
+        if let Ok(game_id) = GameId::from_str(row.get(0)) {
+            abandons.entry(game_id).or_default().push(row.get::<_, i32>(1) as u32);
+        }
+    }
+    Ok(abandons)
+}
+
+/// Time until the user can join ranked rooms again, if the user abandoned a game during `cooldown`
+pub fn get_ranked_cooldown(user_no: UserNo, cooldown: Duration, pool: Pool) -> Result<Option<SystemTime>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT MAX(time) FROM abandons WHERE user_no=$1;")?;
+    let last: Option<SystemTime> = client.query_one(&stmt, &[&(user_no.0 as i32)])?.get(0);
+    Ok(last
+        .map(|last| last + cooldown)
+        .filter(|until| *until > SystemTime::now()))
+}
+
+/// Percentage of the ranked games the user finished without abandoning
+pub fn get_reliability(user_no: UserNo, pool: Pool) -> Result<u32> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT (SELECT COUNT(*) FROM ratings WHERE user_no=$1), (SELECT COUNT(*) FROM abandons WHERE user_no=$1);",
+    )?;
+    let row = client.query_one(&stmt, &[&(user_no.0 as i32)])?;
+    let (games, abandons): (i64, i64) = (row.get(0), row.get(1));
+    if games == 0 {
+        Ok(100)
+    } else {
+        Ok((100 * (games - abandons.min(games)) / games) as u32)
+    }
+}
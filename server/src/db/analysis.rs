@@ -0,0 +1,41 @@
+use crate::dev::*;
+use mighty::analysis::{Decision, Flag};
+use postgres::types::Json;
+
+pub fn save_analysis(game_id: &str, flags: &[Flag], pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let stmt = client
+        .prepare("INSERT INTO game_analysis (game_id, flags) VALUES ($1, $2) ON CONFLICT (game_id) DO NOTHING;")?;
+    client.execute(&stmt, &[&game_id, &Json(flags)])?;
+    Ok(())
+}
+
+pub fn has_analysis(game_id: &str, pool: Pool) -> Result<bool> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT 1 FROM game_analysis WHERE game_id=$1;")?;
+    Ok(!client.query(&stmt, &[&game_id])?.is_empty())
+}
+
+/// Analysis of the game as `viewer` can see, or `None` if not analyzed yet.
+/// Dropped cards are only shown to the president in unranked games, like the replay.
+pub fn get_analysis(game_id: GameId, viewer: UserNo, pool: Pool) -> Result<Option<Vec<Flag>>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT users, is_rank FROM games WHERE id=$1;")?;
+    let res = client.query(&stmt, &[&game_id.to_string()])?;
+    ensure!(res.len() == 1, StatusCode::NOT_FOUND, "no game found");
+    let users: Vec<i32> = res[0].get(0);
+    let is_rank: bool = res[0].get(1);
+
+    let stmt = client.prepare("SELECT flags FROM game_analysis WHERE game_id=$1;")?;
+    let res = client.query(&stmt, &[&game_id.to_string()])?;
+    let mut flags = match res.first() {
+        Some(row) => row.get::<_, Json<Vec<Flag>>>(0).0,
+        None => return Ok(None),
+    };
+    if !is_rank {
+        flags.retain(|flag| {
+            flag.kind != Decision::Drop || viewer.0 != 0 && users.get(flag.user) == Some(&(viewer.0 as i32))
+        });
+    }
+    Ok(Some(flags))
+}
@@ -0,0 +1,134 @@
+use crate::analytics::{giruda_code, FriendKind, GameRecord, GroupBy};
+use crate::db::ranking::preset_or_none;
+use crate::dev::*;
+use mighty::prelude::{Pattern, Preset, Rule};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Format of the exported aggregates
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Query of the analytics
+///
+/// - `preset`, `rule`: only counts the games with the rule, by the preset or the rule hash
+/// - `giruda`: code of the giruda pattern, or `none` for the games without giruda
+/// - `friend`: how the friend was called
+/// - `group`: field to group the games by
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AnalyticsForm {
+    #[serde(default, deserialize_with = "preset_or_none")]
+    pub preset: Option<Preset>,
+    pub rule: Option<String>,
+    pub is_rank: Option<bool>,
+    pub pledge: Option<u8>,
+    pub giruda: Option<String>,
+    pub friend: Option<FriendKind>,
+    #[serde(default)]
+    pub group: GroupBy,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+impl AnalyticsForm {
+    fn rule(&self) -> Result<Option<String>> {
+        match (self.preset, &self.rule) {
+            (Some(_), Some(_)) => bail!(StatusCode::BAD_REQUEST, "preset and rule can't be used together"),
+            (Some(preset), None) => Ok(Some(RuleHash::generate(&Rule::from(preset)).to_string())),
+            (None, Some(rule)) => Ok(Some(RuleHash::from_str(rule)?.to_string())),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+pub fn index_game(record: &GameRecord, pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "INSERT INTO game_analytics (game_id, rule, is_rank, pledge, giruda, friend, won, score, points) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (game_id) DO NOTHING;",
+    )?;
+    client.execute(
+        &stmt,
+        &[
+            &record.game_id,
+            &record.rule,
+            &record.is_rank,
+            &(record.pledge as i32),
+            &record.giruda.map(|g| giruda_code(Some(g))),
+            &record.friend.as_str(),
+            &record.won,
+            &(record.score as i32),
+            &record.points.iter().map(|x| *x as i32).collect::<Vec<_>>(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Finished games not indexed yet: game id, rule hash and if ranked
+///
+/// Games whose last state is not `GameEnded` were aborted, and are never indexed.
+pub fn get_unindexed_games(pool: Pool) -> Result<Vec<(String, RuleHash, bool)>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT g.id, g.rule, g.is_rank FROM games g \
+         WHERE NOT EXISTS (SELECT 1 FROM game_analytics a WHERE a.game_id=g.id) \
+         AND (SELECT r.state::JSONB ? 'GameEnded' FROM records r \
+         WHERE r.game_id=g.id ORDER BY r.number DESC LIMIT 1);",
+    )?;
+    Ok(client
+        .query(&stmt, &[])?
+        .iter()
+        .filter_map(|r| {
+            let rule = RuleHash::from_str(r.get(1)).ok()?;
+            Some((r.get::<_, String>(0).trim_end().to_owned(), rule, r.get(2)))
+        })
+        .collect())
+}
+
+/// Indexed games matching the query
+pub fn get_game_records(form: &AnalyticsForm, pool: Pool) -> Result<Vec<GameRecord>> {
+    let rule = form.rule()?;
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT game_id, rule, is_rank, pledge, giruda, friend, won, score, points FROM game_analytics \
+         WHERE ($1::TEXT IS NULL OR rule=$1) AND ($2::BOOLEAN IS NULL OR is_rank=$2) \
+         AND ($3::INTEGER IS NULL OR pledge=$3) AND ($4::TEXT IS NULL OR COALESCE(giruda, 'none')=$4) \
+         AND ($5::TEXT IS NULL OR friend=$5);",
+    )?;
+    let res = client.query(
+        &stmt,
+        &[
+            &rule,
+            &form.is_rank,
+            &form.pledge.map(|x| x as i32),
+            &form.giruda,
+            &form.friend.map(|x| x.as_str()),
+        ],
+    )?;
+    Ok(res
+        .iter()
+        .filter_map(|r| {
+            let giruda = match r.get::<_, Option<String>>(4) {
+                Some(code) => Some(serde_json::from_value::<Pattern>(serde_json::Value::String(code)).ok()?),
+                None => None,
+            };
+            let friend = serde_json::from_value(serde_json::Value::String(r.get(5))).ok()?;
+            Some(GameRecord {
+                game_id: r.get::<_, String>(0).trim_end().to_owned(),
+                rule: r.get::<_, String>(1).trim_end().to_owned(),
+                is_rank: r.get(2),
+                pledge: r.get::<_, i32>(3) as u8,
+                giruda,
+                friend,
+                won: r.get(6),
+                score: r.get::<_, i32>(7) as u8,
+                points: r.get::<_, Vec<i32>>(8).into_iter().map(|x| x as u8).collect(),
+            })
+        })
+        .collect())
+}
@@ -1,7 +1,10 @@
+use crate::actor::room::RoomSnapshot;
 use crate::dev::*;
+use crate::rating::Glicko;
 use mighty::prelude::{Rule, State};
 use postgres::types::Json;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::time::SystemTime;
 
@@ -9,21 +12,210 @@ use std::time::SystemTime;
 pub struct ChangeRatingForm {
     pub user_no: u32,
     pub game_id: GameId,
-    pub diff: u32,
-    pub rating: u32,
+    pub diff: i32,
+    pub glicko: Glicko,
+    pub season: Option<u32>,
 }
 
 pub fn change_rating(form: &ChangeRatingForm, pool: Pool) -> Result<()> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("INSERT INTO rating (user_no, game_id, diff, rating) VALUES ($1, $2, $3, $4);")?;
+    let rating = form.glicko.rating;
+    let stmt = client.prepare(
+        "INSERT INTO ratings (user_no, game_id, diff, rating, deviation, volatility, season) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7);",
+    )?;
+    let _ = client.query(
+        &stmt,
+        &[
+            &(form.user_no as i32),
+            &form.game_id.to_string(),
+            &form.diff,
+            &rating,
+            &form.glicko.deviation,
+            &form.glicko.volatility,
+            &form.season.map(|x| x as i32),
+        ],
+    )?;
+
+    let stmt = client.prepare("UPDATE users SET rating=$1, deviation=$2, volatility=$3 WHERE no=$4;")?;
     let _ = client.query(
         &stmt,
-        &[&form.user_no, &form.game_id.to_string(), &form.diff, &form.rating],
+        &[
+            &rating,
+            &form.glicko.deviation,
+            &form.glicko.volatility,
+            &(form.user_no as i32),
+        ],
     )?;
+    Ok(())
+}
 
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SettleForm {
+    pub game_id: GameId,
+    pub users: Vec<UserNo>,
+    pub season: Option<u32>,
+}
+
+/// Rates the game with `rate` and saves the ratings of every seat in one transaction.
+/// If the game is already settled, returns the saved result without rating again.
+/// Bots (user 0) are rated as the default rating and don't change.
+/// Returns the new rating and the change of the displayed rating of each seat.
+pub fn settle_ratings<F>(form: &SettleForm, rate: F, pool: Pool) -> Result<Vec<(Glicko, i32)>>
+where
+    F: FnOnce(&[Glicko]) -> Vec<Glicko>,
+{
     let mut client = pool.get()?;
-    let stmt = client.prepare("UPDATE users SET rating=$1 WHERE no=$2;")?;
-    let _ = client.query(&stmt, &[&form.rating, &form.user_no])?;
+    let mut transaction = client.transaction()?;
+    let game_id = form.game_id.to_string();
+    transaction.execute("SELECT 1 FROM games WHERE id=$1 FOR UPDATE;", &[&game_id])?;
+
+    let settled = transaction.query(
+        "SELECT user_no, rating, deviation, volatility, diff FROM ratings WHERE game_id=$1;",
+        &[&game_id],
+    )?;
+    if !settled.is_empty() {
+        let settled = settled
+            .iter()
+            .map(|r| {
+                let glicko = Glicko {
+                    rating: r.get(1),
+                    deviation: r.get(2),
+                    volatility: r.get(3),
+                };
+                (r.get::<_, i32>(0) as u32, (glicko, r.get::<_, i32>(4)))
+            })
+            .collect::<HashMap<_, _>>();
+        return Ok(form
+            .users
+            .iter()
+            .map(|user_no| settled.get(&user_no.0).copied().unwrap_or((Glicko::default(), 0)))
+            .collect());
+    }
+
+    let mut players = Vec::with_capacity(form.users.len());
+    for user_no in form.users.iter() {
+        if user_no.0 == 0 {
+            players.push(Glicko::default());
+            continue;
+        }
+        let row = transaction
+            .query(
+                "SELECT rating, deviation, volatility FROM users WHERE no=$1 FOR UPDATE;",
+                &[&(user_no.0 as i32)],
+            )?
+            .into_iter()
+            .next()
+            .ok_or_else(|| err!(StatusCode::NOT_FOUND, "no user"))?;
+        players.push(Glicko {
+            rating: row.get(0),
+            deviation: row.get(1),
+            volatility: row.get(2),
+        });
+    }
+    let rated = rate(&players);
+    ensure!(rated.len() == players.len(), "rated every seat");
+
+    let insert = transaction.prepare(
+        "INSERT INTO ratings (user_no, game_id, diff, rating, deviation, volatility, season) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7);",
+    )?;
+    let update = transaction.prepare("UPDATE users SET rating=$1, deviation=$2, volatility=$3 WHERE no=$4;")?;
+    let mut res = Vec::with_capacity(players.len());
+    for (i, user_no) in form.users.iter().enumerate() {
+        if user_no.0 == 0 {
+            res.push((players[i], 0));
+            continue;
+        }
+        let diff = rated[i].display() as i32 - players[i].display() as i32;
+        transaction.execute(
+            &insert,
+            &[
+                &(user_no.0 as i32),
+                &game_id,
+                &diff,
+                &rated[i].rating,
+                &rated[i].deviation,
+                &rated[i].volatility,
+                &form.season.map(|x| x as i32),
+            ],
+        )?;
+        transaction.execute(
+            &update,
+            &[
+                &rated[i].rating,
+                &rated[i].deviation,
+                &rated[i].volatility,
+                &(user_no.0 as i32),
+            ],
+        )?;
+        res.push((rated[i], diff));
+    }
+    transaction.commit()?;
+    Ok(res)
+}
+
+/// Users and the final state of every ranked game, in the order they were played
+pub fn get_rank_results(pool: Pool) -> Result<Vec<(GameId, Vec<u32>, State)>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT g.id, g.users, r.state FROM games g JOIN records r ON r.game_id=g.id \
+         WHERE g.is_rank AND r.number=(SELECT MAX(number) FROM records WHERE game_id=g.id) \
+         ORDER BY r.time ASC;",
+    )?;
+    let res = client.query(&stmt, &[])?;
+    Ok(res
+        .iter()
+        .filter_map(|r| {
+            let game_id = GameId::from_str(r.get(0)).ok()?;
+            let users = r.get::<_, Vec<i32>>(1).into_iter().map(|x| x as u32).collect();
+            let state: Json<State> = r.try_get(2).ok()?;
+            Some((game_id, users, state.0))
+        })
+        .collect())
+}
+
+/// Replaces the history of ratings with `ratings` in one transaction, in the order given.
+/// Users not in `ratings` get the default rating.
+pub fn replace_ratings(ratings: &[ChangeRatingForm], pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let mut transaction = client.transaction()?;
+    let glicko = Glicko::default();
+    transaction.execute("DELETE FROM ratings;", &[])?;
+    transaction.execute(
+        "UPDATE users SET rating=$1, deviation=$2, volatility=$3;",
+        &[&glicko.rating, &glicko.deviation, &glicko.volatility],
+    )?;
+
+    let insert = transaction.prepare(
+        "INSERT INTO ratings (user_no, game_id, diff, rating, deviation, volatility, season) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7);",
+    )?;
+    let update = transaction.prepare("UPDATE users SET rating=$1, deviation=$2, volatility=$3 WHERE no=$4;")?;
+    for form in ratings {
+        transaction.execute(
+            &insert,
+            &[
+                &(form.user_no as i32),
+                &form.game_id.to_string(),
+                &form.diff,
+                &form.glicko.rating,
+                &form.glicko.deviation,
+                &form.glicko.volatility,
+                &form.season.map(|x| x as i32),
+            ],
+        )?;
+        transaction.execute(
+            &update,
+            &[
+                &form.glicko.rating,
+                &form.glicko.deviation,
+                &form.glicko.volatility,
+                &(form.user_no as i32),
+            ],
+        )?;
+    }
+    transaction.commit()?;
     Ok(())
 }
 
@@ -34,10 +226,10 @@ pub struct GetRatingForm {
     pub end: SystemTime,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Rating {
     pub game_id: GameId,
-    pub diff: u32,
+    pub diff: i32,
     pub rating: u32,
     pub time: SystemTime,
 }
@@ -45,15 +237,16 @@ pub struct Rating {
 pub fn get_rating(form: &GetRatingForm, pool: Pool) -> Result<Vec<Rating>> {
     let mut client = pool.get()?;
     let stmt = client.prepare(
-        "SELECT game_id, diff, rating, time, FROM rating WHERE user_no=$1 AND $2<=time AND time<=$3 ORDER BY time ASC",
+        "SELECT game_id, diff, ROUND(rating)::INTEGER, time FROM ratings \
+         WHERE user_no=$1 AND $2<=time AND time<=$3 ORDER BY time ASC",
     )?;
-    let res = client.query(&stmt, &[&form.user_no, &form.start, &form.end])?;
+    let res = client.query(&stmt, &[&(form.user_no as i32), &form.start, &form.end])?;
     Ok(res
         .iter()
         .map(|r| Rating {
             game_id: GameId::from_str(r.get(0)).unwrap(),
             diff: r.get(1),
-            rating: r.get(2),
+            rating: r.get::<_, i32>(2) as u32,
             time: r.get(3),
         })
         .collect())
@@ -86,7 +279,7 @@ pub fn make_game(form: &MakeGameForm, pool: Pool) -> Result<()> {
             &form.room_name,
             &form.users,
             &form.is_rank,
-            &Json(&form.rule),
+            &RuleHash::generate(&form.rule).to_string(),
         ],
     )?;
     Ok(())
@@ -98,23 +291,141 @@ pub struct SaveStateForm {
     pub room_id: RoomUid,
     pub number: u32,
     pub state: State,
+    pub by_ghost: bool,
+    pub takeback: bool,
 }
 
 pub fn save_state(form: &SaveStateForm, pool: Pool) -> Result<()> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("INSERT INTO record (game_id, room_id, number, state) VALUES ($1, $2, $3, $4);")?;
+    let stmt = client.prepare(
+        "INSERT INTO records (game_id, room_id, number, state, by_ghost, takeback) VALUES ($1, $2, $3, $4, $5, $6);",
+    )?;
     let _ = client.query(
         &stmt,
         &[
             &form.game_id.to_string(),
             &form.room_id.to_string(),
-            &form.number,
+            &(form.number as i32),
             &Json(&form.state),
+            &form.by_ghost,
+            &form.takeback,
         ],
     )?;
     Ok(())
 }
 
+/// Every state of the game saved in `records`, in the order they were made
+///
+/// - `users`: user of each seat when the game started, 0 for bots
+/// - `states`: states from the start of the game, including the ones taken back
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Replay {
+    pub game_id: String,
+    pub room_name: String,
+    pub users: Vec<UserNo>,
+    pub is_rank: bool,
+    pub rule: Rule,
+    pub states: Vec<State>,
+}
+
+/// Replay of the game as `viewer` can see.
+/// Ranked games reveal every hand, otherwise only the hand of the viewer is shown.
+pub fn get_replay(game_id: GameId, viewer: Option<UserNo>, pool: Pool) -> Result<Replay> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT room_name, users, is_rank, rule FROM games WHERE id=$1;")?;
+    let res = client.query(&stmt, &[&game_id.to_string()])?;
+    ensure!(res.len() == 1, StatusCode::NOT_FOUND, "no game found");
+    let room_name = res[0].get::<_, String>(0).trim_end().to_owned();
+    let users = res[0]
+        .get::<_, Vec<i32>>(1)
+        .into_iter()
+        .map(|x| UserNo(x as u32))
+        .collect::<Vec<_>>();
+    let is_rank: bool = res[0].get(2);
+    let rule_hash = RuleHash::from_str(res[0].get(3))?;
+    let rule = get_rule(&GetRuleForm { rule_hash }, pool.clone())?;
+
+    let stmt = client.prepare("SELECT state FROM records WHERE game_id=$1 ORDER BY number ASC;")?;
+    let visible = if is_rank {
+        u8::MAX
+    } else {
+        users
+            .iter()
+            .position(|x| Some(*x) == viewer && x.0 != 0)
+            .map_or(0, |seat| 1 << seat)
+    };
+    let states = client
+        .query(&stmt, &[&game_id.to_string()])?
+        .iter()
+        .map(|r| r.get::<_, Json<State>>(0).0.masked(visible))
+        .collect();
+
+    Ok(Replay {
+        game_id: game_id.to_string(),
+        room_name,
+        users,
+        is_rank,
+        rule,
+        states,
+    })
+}
+
+/// State saved in `records`
+///
+/// - `by_ghost`: if the move is made by bot for absent user
+/// - `takeback`: if the state is rolled back by undo
+#[derive(Debug, Clone)]
+pub struct SavedState {
+    pub state: State,
+    pub by_ghost: bool,
+    pub takeback: bool,
+}
+
+/// Every state of the game in the order they were made, including the ones taken back
+pub fn get_states(game_id: &str, pool: Pool) -> Result<Vec<SavedState>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT state, by_ghost, takeback FROM records WHERE game_id=$1 ORDER BY number ASC;")?;
+    Ok(client
+        .query(&stmt, &[&game_id])?
+        .iter()
+        .filter_map(|r| {
+            Some(SavedState {
+                state: r.try_get::<_, Json<State>>(0).ok()?.0,
+                by_ghost: r.get(1),
+                takeback: r.get(2),
+            })
+        })
+        .collect())
+}
+
+/// Indices of the states in the line of the game, without the ones taken back.
+///
+/// A takeback row holds the state restored by undo,
+/// so the states after that state are dropped from the line.
+pub fn game_line(saved: &[SavedState]) -> Vec<usize> {
+    let mut line = Vec::<usize>::new();
+    for (i, s) in saved.iter().enumerate() {
+        if s.takeback {
+            if let Some(pos) = line.iter().rposition(|&j| saved[j].state == s.state) {
+                line.truncate(pos + 1);
+            }
+        } else {
+            line.push(i);
+        }
+    }
+    line
+}
+
+/// Rule of the game
+pub fn get_game_rule(game_id: &str, pool: Pool) -> Result<Rule> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT rule FROM games WHERE id=$1;")?;
+    let res = client.query(&stmt, &[&game_id])?;
+    ensure!(res.len() == 1, StatusCode::NOT_FOUND, "no game found");
+    let rule_hash = RuleHash::from_str(res[0].get(0))?;
+    get_rule(&GetRuleForm { rule_hash }, pool)
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct GetRuleForm {
     pub rule_hash: RuleHash,
@@ -122,7 +433,7 @@ pub struct GetRuleForm {
 
 pub fn get_rule(form: &GetRuleForm, pool: Pool) -> Result<Rule> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("")?; // todo
+    let stmt = client.prepare("SELECT rule FROM rules WHERE rule_hash=$1;")?;
     let res = client.query(&stmt, &[&form.rule_hash.to_string()])?;
     ensure!(res.len() == 1, "no rule found");
     let rule: Json<Rule> = res[0].get(0);
@@ -136,8 +447,9 @@ pub struct SaveRuleForm {
 
 pub fn save_rule(form: &SaveRuleForm, pool: Pool) -> Result<()> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("")?; // todo
-    let _ = client.query(&stmt, &[&Json(&form.rule)]);
+    let stmt =
+        client.prepare("INSERT INTO rules (rule_hash, rule) VALUES ($1, $2) ON CONFLICT (rule_hash) DO NOTHING;")?;
+    let _ = client.query(&stmt, &[&RuleHash::generate(&form.rule).to_string(), &Json(&form.rule)])?;
     Ok(())
 }
 
@@ -146,28 +458,62 @@ pub struct MakeRoomForm {
     pub uid: RoomUid,
     pub id: RoomId,
     pub name: String,
-    pub user_no: UserNo,
-    pub rule: Rule,
+    pub rule: RuleHash,
 }
 
 pub fn make_room(form: &MakeRoomForm, pool: Pool) -> Result<()> {
     let mut client = pool.get()?;
-    let stmt = client
-        .prepare("INSERT INTO curr_rooms (uid, id, name, master, users, rule) VALUES ($1, $2, $3, $4, $5, $6);")?;
+    let stmt = client.prepare("INSERT INTO curr_rooms (uid, id, name, users_cnt, rule) VALUES ($1, $2, $3, 0, $4);")?;
     let _ = client.query(
         &stmt,
         &[
             &form.uid.to_string(),
-            &form.id.0,
+            &(form.id.0 as i32),
             &form.name,
-            &form.user_no.0,
-            &vec![&form.user_no.0],
-            &Json(&form.rule),
+            &form.rule.to_string(),
         ],
     )?;
     Ok(())
 }
 
+#[derive(Deserialize, Serialize, Clone)]
+pub struct RemoveRoomForm {
+    pub room_id: RoomId,
+}
+
+pub fn remove_room(form: &RemoveRoomForm, pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("DELETE FROM curr_rooms WHERE id=$1;")?;
+    let _ = client.query(&stmt, &[&(form.room_id.0 as i32)])?;
+    let stmt = client.prepare("DELETE FROM room_snapshots WHERE id=$1;")?;
+    let _ = client.query(&stmt, &[&(form.room_id.0 as i32)])?;
+    Ok(())
+}
+
+pub fn save_room_snapshot(form: &RoomSnapshot, pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "INSERT INTO room_snapshots (uid, id, snapshot) VALUES ($1, $2, $3) \
+         ON CONFLICT (uid) DO UPDATE SET snapshot=$3, time=now();",
+    )?;
+    let _ = client.query(
+        &stmt,
+        &[&form.info.uid.to_string(), &(form.info.id.0 as i32), &Json(form)],
+    )?;
+    Ok(())
+}
+
+pub fn get_room_snapshots(pool: Pool) -> Result<Vec<RoomSnapshot>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT snapshot FROM room_snapshots;")?;
+    let res = client.query(&stmt, &[])?;
+    Ok(res
+        .iter()
+        .filter_map(|r| r.try_get::<_, Json<RoomSnapshot>>(0).ok())
+        .map(|snapshot| snapshot.0)
+        .collect())
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct GetInRoomForm {
     pub room_id: RoomId,
@@ -175,15 +521,8 @@ pub struct GetInRoomForm {
 
 pub fn get_into_room(form: &GetInRoomForm, pool: Pool) -> Result<()> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("SELECT 1 users_cnt FROM curr_rooms WHERE id=$1;")?;
-    let res = client.query(&stmt, &[&form.room_id.0])?;
-    let row = res
-        .first()
-        .ok_or_else(|| err!(StatusCode::UNAUTHORIZED, "no rooms exists"))?;
-    let users_cnt: u32 = row.get(0);
-    let mut client = pool.get()?;
-    let stmt = client.prepare("UPDATE curr_rooms SET users_cnt=$1 id=$2;")?;
-    let _ = client.query(&stmt, &[&users_cnt, &form.room_id.0])?;
+    let stmt = client.prepare("UPDATE curr_rooms SET users_cnt=users_cnt+1 WHERE id=$1;")?;
+    let _ = client.query(&stmt, &[&(form.room_id.0 as i32)])?;
 
     Ok(())
 }
@@ -195,15 +534,8 @@ pub struct LeaveRoomForm {
 
 pub fn leave_room(form: &LeaveRoomForm, pool: Pool) -> Result<()> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("SELECT 1 users_cnt FROM curr_rooms WHERE id=$1;")?;
-    let res = client.query(&stmt, &[&form.room_id.0])?;
-    let row = res
-        .first()
-        .ok_or_else(|| err!(StatusCode::UNAUTHORIZED, "no rooms exists"))?;
-    let users_cnt: u32 = row.get(0);
-    let mut client = pool.get()?;
-    let stmt = client.prepare("UPDATE curr_rooms SET users_cnt=$1 id=$2;")?;
-    let _ = client.query(&stmt, &[&users_cnt, &form.room_id.0])?;
+    let stmt = client.prepare("UPDATE curr_rooms SET users_cnt=users_cnt-1 WHERE id=$1;")?;
+    let _ = client.query(&stmt, &[&(form.room_id.0 as i32)])?;
 
     Ok(())
 }
@@ -243,7 +575,42 @@ pub struct GetRoomListForm {
 
 pub fn get_room_list(form: &GetRoomListForm, pool: Pool) -> Result<Vec<RoomId>> {
     let mut client = pool.get()?;
-    let stmt = client.prepare("SELECT id FROM curr_rooms ORDER BY users_cnt WHERE users_cnt=>$1 AND users_cnt<=$2;")?;
-    let res = client.query(&stmt, &[&form.user_num.0, &form.user_num.1])?;
-    Ok(res.iter().map(|x| RoomId(x.get(0))).collect())
+    let stmt = client.prepare("SELECT id FROM curr_rooms WHERE users_cnt>=$1 AND users_cnt<=$2 ORDER BY users_cnt;")?;
+    let res = client.query(&stmt, &[&(form.user_num.0 as i32), &(form.user_num.1 as i32)])?;
+    Ok(res.iter().map(|x| RoomId(x.get::<_, i32>(0) as u32)).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::prelude::{Command, Preset};
+
+    fn saved(state: &State, takeback: bool) -> SavedState {
+        SavedState {
+            state: state.clone(),
+            by_ghost: false,
+            takeback,
+        }
+    }
+
+    #[test]
+    fn game_line_test() {
+        let rule = Rule::from(Preset::Default5);
+        let mut states = vec![State::new(&rule)];
+        for user_id in 0..3 {
+            let next = states[user_id].next(user_id, Command::Pledge(None), &rule).unwrap();
+            states.push(next);
+        }
+        let saved = vec![
+            saved(&states[0], false),
+            saved(&states[1], false),
+            saved(&states[2], false),
+            saved(&states[3], false),
+            // undo of seat 1 restores the state before its command
+            saved(&states[1], true),
+            saved(&states[2], false),
+        ];
+        assert_eq!(game_line(&saved), vec![0, 1, 5]);
+        assert_eq!(game_line(&saved[..4]), vec![0, 1, 2, 3]);
+    }
 }
@@ -1,4 +1,10 @@
+pub mod abandon;
+pub mod analysis;
+pub mod analytics;
 pub mod game;
+pub mod profile;
+pub mod ranking;
+pub mod season;
 pub mod user;
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -11,6 +17,11 @@ const CREATE_ROOM_SQL: &str = include_str!("./sql/create_room.sql");
 #[cfg(target_os = "windows")]
 const CREATE_ROOM_SQL: &str = include_str!(".\\sql\\create_room.sql");
 
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const MIGRATE_GLICKO_SQL: &str = include_str!("./sql/migrate_glicko.sql");
+#[cfg(target_os = "windows")]
+const MIGRATE_GLICKO_SQL: &str = include_str!(".\\sql\\migrate_glicko.sql");
+
 use crate::dev::*;
 
 pub fn init(pool: Pool) -> Result<()> {
@@ -19,3 +30,21 @@ pub fn init(pool: Pool) -> Result<()> {
     client.simple_query(CREATE_ROOM_SQL)?;
     Ok(())
 }
+
+/// Migrates the integer ratings from before Glicko-2, only if they are not migrated yet.
+/// Every rating is reset to the default, and `true` is returned so that they can be recomputed.
+pub fn migrate_glicko(pool: Pool) -> Result<bool> {
+    let mut client = pool.get()?;
+    let mut transaction = client.transaction()?;
+    let row = transaction.query_opt(
+        "SELECT data_type FROM information_schema.columns \
+         WHERE table_schema=current_schema() AND table_name='users' AND column_name='rating';",
+        &[],
+    )?;
+    if !matches!(row, Some(row) if row.get::<_, String>(0) != "double precision") {
+        return Ok(false);
+    }
+    transaction.batch_execute(MIGRATE_GLICKO_SQL)?;
+    transaction.commit()?;
+    Ok(true)
+}
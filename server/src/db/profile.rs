@@ -0,0 +1,261 @@
+use crate::db::abandon::get_reliability;
+use crate::db::game::{get_rating, GetRatingForm, Rating};
+use crate::db::season::{get_active_season, get_season_games, get_user_standings, Standing};
+use crate::dev::*;
+use mighty::prelude::{Pattern, State};
+use postgres::types::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Role of the user in the game
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    President,
+    Friend,
+    Opposition,
+}
+
+/// Games played and won
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub games: u32,
+    pub wins: u32,
+}
+
+impl Record {
+    fn add(&mut self, won: bool) {
+        self.games += 1;
+        self.wins += won as u32;
+    }
+}
+
+/// Game recently played by the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentGame {
+    pub game_id: String,
+    pub role: Role,
+    pub won: bool,
+    pub pledge: u8,
+    pub giruda: Option<Pattern>,
+    pub time: SystemTime,
+}
+
+/// Statistics of the ended games of the user
+///
+/// - `avg_pledge`: average pledge as president
+/// - `favorite_giruda`: most chosen giruda as president
+/// - `no_giruda`: record as president without giruda
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserStats {
+    pub total: Record,
+    pub president: Record,
+    pub friend: Record,
+    pub opposition: Record,
+    pub avg_pledge: f64,
+    pub favorite_giruda: Option<Pattern>,
+    pub no_giruda: Record,
+    pub recent: Vec<RecentGame>,
+}
+
+impl UserStats {
+    /// Makes the statistics from the final states of the games, with the seat of the user.
+    /// The games should be sorted from the most recent one.
+    pub fn from_games(games: &[(GameId, usize, State, SystemTime)]) -> UserStats {
+        let mut stats = UserStats::default();
+        let mut pledge_sum = 0;
+        let mut giruda_cnt = HashMap::new();
+
+        for (game_id, seat, state, time) in games {
+            let (winner, president, friend, pledge, giruda) = match state {
+                State::GameEnded {
+                    winner,
+                    president,
+                    friend,
+                    pledge,
+                    giruda,
+                    ..
+                } => (*winner, *president, *friend, *pledge, *giruda),
+                _ => continue,
+            };
+            let won = winner & (1 << seat) > 0;
+            let role = if *seat == president {
+                Role::President
+            } else if friend == Some(*seat) {
+                Role::Friend
+            } else {
+                Role::Opposition
+            };
+
+            stats.total.add(won);
+            match role {
+                Role::President => {
+                    stats.president.add(won);
+                    pledge_sum += pledge as u32;
+                    match giruda {
+                        Some(giruda) => *giruda_cnt.entry(giruda).or_insert(0) += 1,
+                        None => stats.no_giruda.add(won),
+                    }
+                }
+                Role::Friend => stats.friend.add(won),
+                Role::Opposition => stats.opposition.add(won),
+            }
+            if stats.recent.len() < PROFILE_RECENT_GAMES {
+                stats.recent.push(RecentGame {
+                    game_id: game_id.to_string(),
+                    role,
+                    won,
+                    pledge,
+                    giruda,
+                    time: *time,
+                });
+            }
+        }
+
+        if stats.president.games > 0 {
+            stats.avg_pledge = pledge_sum as f64 / stats.president.games as f64;
+        }
+        stats.favorite_giruda = giruda_cnt
+            .into_iter()
+            .max_by_key(|(giruda, cnt)| (*cnt, *giruda))
+            .map(|(giruda, _)| giruda);
+        stats
+    }
+}
+
+/// Public profile of the user
+///
+/// - `history`: every rating change of the user
+/// - `placement`: placement games left in the active season
+/// - `standings`: final standings and badges of the past seasons
+/// - `reliability`: percentage of the ranked games finished without abandoning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub no: UserNo,
+    pub id: String,
+    pub name: String,
+    pub rating: u32,
+    pub stats: UserStats,
+    pub history: Vec<Rating>,
+    pub placement: u32,
+    pub standings: Vec<Standing>,
+    pub reliability: u32,
+}
+
+/// Computes the statistics of the user from `games` and `records`
+fn compute_stats(user_no: UserNo, pool: Pool) -> Result<UserStats> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT g.id, array_position(g.users, $1) - 1, r.state, r.time FROM games g \
+         JOIN records r ON r.game_id=g.id \
+         WHERE $1=ANY(g.users) AND r.number=(SELECT MAX(number) FROM records WHERE game_id=g.id) \
+         ORDER BY r.time DESC;",
+    )?;
+    let res = client.query(&stmt, &[&(user_no.0 as i32)])?;
+    let games = res
+        .iter()
+        .filter_map(|r| {
+            let game_id = r.get::<_, String>(0).parse().ok()?;
+            let seat = r.get::<_, i32>(1) as usize;
+            let state: Json<State> = r.try_get(2).ok()?;
+            Some((game_id, seat, state.0, r.get(3)))
+        })
+        .collect::<Vec<_>>();
+    Ok(UserStats::from_games(&games))
+}
+
+/// Statistics of the user, cached during `PROFILE_CACHE_TIME`
+pub fn get_stats(user_no: UserNo, pool: Pool) -> Result<UserStats> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT stats FROM user_stats WHERE user_no=$1 AND time>=$2;")?;
+    let valid_from = SystemTime::now() - PROFILE_CACHE_TIME;
+    let res = client.query(&stmt, &[&(user_no.0 as i32), &valid_from])?;
+    if let Some(Ok(Json(stats))) = res.first().map(|r| r.try_get::<_, Json<UserStats>>(0)) {
+        return Ok(stats);
+    }
+
+    let stats = compute_stats(user_no, pool.clone())?;
+    let stmt = client.prepare(
+        "INSERT INTO user_stats (user_no, stats) VALUES ($1, $2) \
+         ON CONFLICT (user_no) DO UPDATE SET stats=$2, time=now();",
+    )?;
+    let _ = client.query(&stmt, &[&(user_no.0 as i32), &Json(&stats)])?;
+    Ok(stats)
+}
+
+/// Removes the cached statistics of the users, when they finished the game.
+pub fn invalidate_stats(users: &[UserNo], pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let users = users.iter().map(|x| x.0 as i32).collect::<Vec<_>>();
+    client.execute("DELETE FROM user_stats WHERE user_no=ANY($1);", &[&users])?;
+    Ok(())
+}
+
+pub fn get_profile(info: UserInfo, pool: Pool) -> Result<Profile> {
+    let stats = get_stats(info.no, pool.clone())?;
+    let history = get_rating(
+        &GetRatingForm {
+            user_no: info.no.0,
+            start: UNIX_EPOCH,
+            end: SystemTime::now(),
+        },
+        pool.clone(),
+    )?;
+    let placement = match get_active_season(pool.clone())? {
+        Some(season) => PLACEMENT_GAMES.saturating_sub(get_season_games(info.no, season.no, pool.clone())?),
+        None => PLACEMENT_GAMES,
+    };
+    let standings = get_user_standings(info.no, pool.clone())?;
+    let reliability = get_reliability(info.no, pool)?;
+    Ok(Profile {
+        no: info.no,
+        id: info.id,
+        name: info.name,
+        rating: info.rating,
+        stats,
+        history,
+        placement,
+        standings,
+        reliability,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ended(winner: u8, president: usize, friend: Option<usize>, pledge: u8, giruda: Option<Pattern>) -> State {
+        State::GameEnded {
+            winner,
+            president,
+            friend,
+            score: 0,
+            pledge,
+            giruda,
+            score_deck: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn from_games_test() {
+        let id = GameId::generate("test");
+        let now = SystemTime::now();
+        let games = vec![
+            (id, 0, ended(0b00011, 0, Some(1), 14, Some(Pattern::Spade)), now),
+            (id, 0, ended(0b11100, 0, Some(1), 16, None), now),
+            (id, 1, ended(0b00101, 0, Some(2), 13, Some(Pattern::Heart)), now),
+            (id, 2, ended(0b00101, 0, Some(2), 13, Some(Pattern::Heart)), now),
+        ];
+        let stats = UserStats::from_games(&games);
+
+        assert_eq!(stats.total, Record { games: 4, wins: 2 });
+        assert_eq!(stats.president, Record { games: 2, wins: 1 });
+        assert_eq!(stats.friend, Record { games: 1, wins: 1 });
+        assert_eq!(stats.opposition, Record { games: 1, wins: 0 });
+        assert_eq!(stats.no_giruda, Record { games: 1, wins: 0 });
+        assert_eq!(stats.avg_pledge, 15.0);
+        assert_eq!(stats.favorite_giruda, Some(Pattern::Spade));
+        assert_eq!(stats.recent[2].role, Role::Opposition);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::dev::*;
+use mighty::prelude::{Preset, Rule};
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Period of the leaderboard
+///
+/// Only the games played during the period are counted for the games and the rating change,
+/// but users are always ranked by their current overall rating, since ratings are not kept per period.
+/// Season is the active ranked season, where users should finish the placement games to be ranked.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    #[default]
+    All,
+    Week,
+    Month,
+    Season,
+}
+
+impl Period {
+    /// Condition of the ratings `ra` counted in the period in sql
+    fn filter(&self) -> &'static str {
+        match self {
+            Period::All => "TRUE",
+            Period::Week => "ra.time>=date_trunc('week', now())",
+            Period::Month => "ra.time>=date_trunc('month', now())",
+            Period::Season => "ra.season=(SELECT no FROM seasons WHERE start_at<=now() AND now()<end_at)",
+        }
+    }
+}
+
+/// Query of the leaderboard
+///
+/// - `period`: period of the games counted
+/// - `min_games`: minimum games played during the period
+/// - `preset`: only counts the games played with the rule of the preset
+/// - `page`: page of the leaderboard starting from 0
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RankingForm {
+    #[serde(default)]
+    pub period: Period,
+    #[serde(default)]
+    pub min_games: u32,
+    #[serde(default, deserialize_with = "preset_or_none")]
+    pub preset: Option<Preset>,
+    #[serde(default)]
+    pub page: u32,
+}
+
+/// Empty preset is for every rule.
+pub fn preset_or_none<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Preset>, D::Error> {
+    match Option::<String>::deserialize(deserializer)?.as_deref() {
+        None | Some("") => Ok(None),
+        Some(preset) => Preset::deserialize(preset.into_deserializer()).map(Some),
+    }
+}
+
+impl RankingForm {
+    /// Users should play at least one game in the period or with the preset to be ranked,
+    /// and finish the placement games in the season.
+    fn min_games(&self) -> i64 {
+        match self.period {
+            Period::All if self.preset.is_none() => self.min_games as i64,
+            Period::Season => self.min_games.max(PLACEMENT_GAMES) as i64,
+            _ => self.min_games.max(1) as i64,
+        }
+    }
+
+    fn rule(&self) -> Option<String> {
+        self.preset
+            .map(|preset| RuleHash::generate(&Rule::from(preset)).to_string())
+    }
+
+    /// Users ranked in the leaderboard, with `$1` for the rule hash and `$2` for the minimum games.
+    /// The games are aggregated on every request, and it is used once in a query.
+    fn ranked(&self) -> String {
+        format!(
+            "WITH stats AS (
+                SELECT ra.user_no, COUNT(*) AS games, SUM(ra.diff) AS diff
+                FROM ratings ra JOIN games g ON g.id=ra.game_id
+                WHERE {} AND ($1::TEXT IS NULL OR g.rule=$1)
+                GROUP BY ra.user_no
+            ), ranked AS (
+                SELECT u.no, u.id, u.name, ROUND(u.rating)::INTEGER AS rating, COALESCE(s.games, 0) AS games, COALESCE(s.diff, 0) AS diff,
+                    RANK() OVER (ORDER BY ROUND(u.rating) DESC) AS rank
+                FROM users u LEFT JOIN stats s ON s.user_no=u.no
+                WHERE COALESCE(s.games, 0)>=$2
+            )",
+            self.period.filter()
+        )
+    }
+}
+
+/// A row of the leaderboard
+///
+/// - `diff`: sum of the rating changes during the period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankingEntry {
+    pub rank: i64,
+    pub no: u32,
+    pub id: String,
+    pub name: String,
+    pub rating: i32,
+    pub games: i64,
+    pub diff: i64,
+}
+
+/// Leaderboard page with the rank of the viewer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ranking {
+    pub entries: Vec<RankingEntry>,
+    pub total: i64,
+    pub page: u32,
+    pub me: Option<RankingEntry>,
+}
+
+fn to_entry(row: &postgres::Row) -> RankingEntry {
+    RankingEntry {
+        no: row.get::<_, i32>(0) as u32,
+        id: row.get(1),
+        name: row.get(2),
+        rating: row.get(3),
+        games: row.get(4),
+        diff: row.get(5),
+        rank: row.get(6),
+    }
+}
+
+pub fn get_ranking(form: &RankingForm, viewer: Option<UserNo>, pool: Pool) -> Result<Ranking> {
+    let mut client = pool.get()?;
+    // the page, the total and the viewer at once, so that the ranking is computed only once
+    // there is a row with only the total when nobody is ranked
+    let stmt = client.prepare(&format!(
+        "{}, page AS (
+            SELECT no FROM ranked ORDER BY rank, no LIMIT $3 OFFSET $4
+        )
+        SELECT r.no, r.id, r.name, r.rating, r.games, r.diff, r.rank, r.no IN (SELECT no FROM page), t.total
+        FROM (SELECT COUNT(*) AS total FROM ranked) t
+        LEFT JOIN ranked r ON r.no IN (SELECT no FROM page) OR r.no=$5::INTEGER
+        ORDER BY r.rank, r.no;",
+        form.ranked()
+    ))?;
+    let rows = client.query(
+        &stmt,
+        &[
+            &form.rule(),
+            &form.min_games(),
+            &(RANKING_PAGE_SIZE as i64),
+            &(form.page as i64 * RANKING_PAGE_SIZE as i64),
+            &viewer.map(|x| x.0 as i32),
+        ],
+    )?;
+
+    let mut ranking = Ranking {
+        entries: Vec::new(),
+        total: rows.first().map(|row| row.get(8)).unwrap_or(0),
+        page: form.page,
+        me: None,
+    };
+    for row in rows.iter().filter(|row| row.get::<_, Option<i32>>(0).is_some()) {
+        let entry = to_entry(row);
+        if viewer.is_some_and(|x| x.0 == entry.no) {
+            ranking.me = Some(entry.clone());
+        }
+        if row.get(7) {
+            ranking.entries.push(entry);
+        }
+    }
+    Ok(ranking)
+}
@@ -0,0 +1,222 @@
+use crate::dev::*;
+use crate::rating::Glicko;
+use crate::season::Badge;
+use postgres::types::Json;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Ranked season
+///
+/// - `archived`: if the final standings of the season are saved
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Season {
+    pub no: u32,
+    pub start_at: SystemTime,
+    pub end_at: SystemTime,
+    pub archived: bool,
+}
+
+fn to_season(row: &postgres::Row) -> Season {
+    Season {
+        no: row.get::<_, i32>(0) as u32,
+        start_at: row.get(1),
+        end_at: row.get(2),
+        archived: row.get(3),
+    }
+}
+
+/// Final standing of the user in the season
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Standing {
+    pub season: u32,
+    pub user_no: u32,
+    pub rank: u32,
+    pub rating: i32,
+    pub games: u32,
+    pub badge: Badge,
+}
+
+/// Standing with the id and name of the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StandingEntry {
+    #[serde(flatten)]
+    pub standing: Standing,
+    pub id: String,
+    pub name: String,
+}
+
+/// Season going on now
+pub fn get_active_season(pool: Pool) -> Result<Option<Season>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT no, start_at, end_at, archived FROM seasons WHERE start_at<=now() AND now()<end_at ORDER BY no DESC;",
+    )?;
+    Ok(client.query(&stmt, &[])?.first().map(to_season))
+}
+
+/// Every season from the latest one
+pub fn get_seasons(pool: Pool) -> Result<Vec<Season>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT no, start_at, end_at, archived FROM seasons ORDER BY no DESC;")?;
+    Ok(client.query(&stmt, &[])?.iter().map(to_season).collect())
+}
+
+/// Seasons ended but the standings are not saved yet
+pub fn get_ended_seasons(pool: Pool) -> Result<Vec<Season>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT no, start_at, end_at, archived FROM seasons WHERE NOT archived AND end_at<=now() ORDER BY no ASC;",
+    )?;
+    Ok(client.query(&stmt, &[])?.iter().map(to_season).collect())
+}
+
+pub fn make_season(start_at: SystemTime, pool: Pool) -> Result<Season> {
+    let mut client = pool.get()?;
+    let stmt = client
+        .prepare("INSERT INTO seasons (start_at, end_at) VALUES ($1, $2) RETURNING no, start_at, end_at, archived;")?;
+    let row = client.query_one(&stmt, &[&start_at, &(start_at + SEASON_DURATION)])?;
+    Ok(to_season(&row))
+}
+
+/// Users placed in the season from the highest rating: user number, rating and games played
+pub fn get_season_results(season: u32, pool: Pool) -> Result<Vec<(u32, i32, u32)>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT u.no, ROUND(u.rating)::INTEGER, COUNT(*) FROM ratings ra JOIN users u ON u.no=ra.user_no \
+         WHERE ra.season=$1 GROUP BY u.no, u.rating HAVING COUNT(*)>=$2 \
+         ORDER BY u.rating DESC, u.no ASC;",
+    )?;
+    let res = client.query(&stmt, &[&(season as i32), &(PLACEMENT_GAMES as i64)])?;
+    Ok(res
+        .iter()
+        .map(|r| (r.get::<_, i32>(0) as u32, r.get(1), r.get::<_, i64>(2) as u32))
+        .collect())
+}
+
+/// Saves the final standings, marks the season archived and resets the rating of every user with `reset`.
+/// Everything is done in one transaction, so the reset is retried with the archive if it fails.
+pub fn archive_season(season: u32, standings: &[Standing], reset: fn(&Glicko) -> Glicko, pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let mut transaction = client.transaction()?;
+    let stmt = transaction.prepare(
+        "INSERT INTO season_standings (season, user_no, rank, rating, games, badge) VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (season, user_no) DO NOTHING;",
+    )?;
+    for standing in standings {
+        transaction.execute(
+            &stmt,
+            &[
+                &(standing.season as i32),
+                &(standing.user_no as i32),
+                &(standing.rank as i32),
+                &standing.rating,
+                &(standing.games as i32),
+                &Json(&standing.badge),
+            ],
+        )?;
+    }
+    transaction.execute("UPDATE seasons SET archived=TRUE WHERE no=$1;", &[&(season as i32)])?;
+
+    let users = transaction.query("SELECT no, rating, deviation, volatility FROM users FOR UPDATE;", &[])?;
+    let update = transaction.prepare("UPDATE users SET rating=$1, deviation=$2, volatility=$3 WHERE no=$4;")?;
+    for (user_no, glicko) in users.iter().map(to_glicko) {
+        let glicko = reset(&glicko);
+        transaction.execute(
+            &update,
+            &[&glicko.rating, &glicko.deviation, &glicko.volatility, &(user_no as i32)],
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+pub fn get_standings(season: u32, pool: Pool) -> Result<Vec<StandingEntry>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT s.season, s.user_no, s.rank, s.rating, s.games, s.badge, u.id, u.name \
+         FROM season_standings s JOIN users u ON u.no=s.user_no WHERE s.season=$1 ORDER BY s.rank, s.user_no;",
+    )?;
+    Ok(client
+        .query(&stmt, &[&(season as i32)])?
+        .iter()
+        .map(|r| StandingEntry {
+            standing: to_standing(r),
+            id: r.get(6),
+            name: r.get(7),
+        })
+        .collect())
+}
+
+/// Standings of the user in every archived season
+pub fn get_user_standings(user_no: UserNo, pool: Pool) -> Result<Vec<Standing>> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare(
+        "SELECT season, user_no, rank, rating, games, badge FROM season_standings WHERE user_no=$1 ORDER BY season DESC;",
+    )?;
+    Ok(client
+        .query(&stmt, &[&(user_no.0 as i32)])?
+        .iter()
+        .map(to_standing)
+        .collect())
+}
+
+fn to_standing(row: &postgres::Row) -> Standing {
+    Standing {
+        season: row.get::<_, i32>(0) as u32,
+        user_no: row.get::<_, i32>(1) as u32,
+        rank: row.get::<_, i32>(2) as u32,
+        rating: row.get(3),
+        games: row.get::<_, i32>(4) as u32,
+        badge: row.get::<_, Json<Badge>>(5).0,
+    }
+}
+
+/// Ranked games played by the user in the season
+pub fn get_season_games(user_no: UserNo, season: u32, pool: Pool) -> Result<u32> {
+    let mut client = pool.get()?;
+    let stmt = client.prepare("SELECT COUNT(*) FROM ratings WHERE user_no=$1 AND season=$2;")?;
+    let games: i64 = client
+        .query_one(&stmt, &[&(user_no.0 as i32), &(season as i32)])?
+        .get(0);
+    Ok(games as u32)
+}
+
+/// Users above `DECAY_THRESHOLD` who didn't play ranked games during `DECAY_INACTIVE_TIME`,
+/// and didn't decay during `DECAY_INTERVAL`
+pub fn get_inactive_users(pool: Pool) -> Result<Vec<(u32, Glicko)>> {
+    let mut client = pool.get()?;
+    let now = SystemTime::now();
+    let stmt = client.prepare(
+        "SELECT no, rating, deviation, volatility FROM users u \
+         WHERE rating>$1 AND last_decay<=$2 \
+         AND NOT EXISTS (SELECT 1 FROM ratings ra WHERE ra.user_no=u.no AND ra.time>$3);",
+    )?;
+    let res = client.query(
+        &stmt,
+        &[&DECAY_THRESHOLD, &(now - DECAY_INTERVAL), &(now - DECAY_INACTIVE_TIME)],
+    )?;
+    Ok(res.iter().map(to_glicko).collect())
+}
+
+fn to_glicko(row: &postgres::Row) -> (u32, Glicko) {
+    (
+        row.get::<_, i32>(0) as u32,
+        Glicko {
+            rating: row.get(1),
+            deviation: row.get(2),
+            volatility: row.get(3),
+        },
+    )
+}
+
+/// Sets the decayed rating without the history.
+pub fn set_decayed(user_no: u32, glicko: Glicko, pool: Pool) -> Result<()> {
+    let mut client = pool.get()?;
+    let stmt =
+        client.prepare("UPDATE users SET rating=$1, deviation=$2, volatility=$3, last_decay=now() WHERE no=$4;")?;
+    client.execute(
+        &stmt,
+        &[&glicko.rating, &glicko.deviation, &glicko.volatility, &(user_no as i32)],
+    )?;
+    Ok(())
+}
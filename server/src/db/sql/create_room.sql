@@ -11,6 +11,14 @@ CREATE TABLE IF NOT EXISTS curr_rooms
 CREATE UNIQUE INDEX IF NOT EXISTS curr_rooms_uid_index ON curr_rooms (uid);
 CREATE UNIQUE INDEX IF NOT EXISTS curr_rooms_id_index ON curr_rooms (id);
 
+CREATE TABLE IF NOT EXISTS room_snapshots
+(
+    uid      CHAR(64) NOT NULL UNIQUE,
+    id       INTEGER  NOT NULL UNIQUE,
+    snapshot JSON     NOT NULL, -- room info, rule and the game going on
+    time     TIMESTAMP DEFAULT now()
+);
+
 CREATE TABLE IF NOT EXISTS games
 (
     id        CHAR(64)  NOT NULL UNIQUE,
@@ -23,6 +31,7 @@ CREATE TABLE IF NOT EXISTS games
 
 CREATE UNIQUE INDEX IF NOT EXISTS game_id_index ON games (id);
 CREATE INDEX IF NOT EXISTS game_room_id_index ON games (room_id);
+CREATE INDEX IF NOT EXISTS game_rule_index ON games (rule);
 
 CREATE TABLE IF NOT EXISTS records
 (
@@ -30,9 +39,14 @@ CREATE TABLE IF NOT EXISTS records
     room_id CHAR(64) NOT NULL,
     number  INTEGER  NOT NULL, -- nth state in game
     state   JSON     NOT NULL,
+    by_ghost BOOLEAN NOT NULL DEFAULT FALSE, -- if the move is made by bot for absent user
+    takeback BOOLEAN NOT NULL DEFAULT FALSE, -- if the state is rolled back by undo
     time    TIMESTAMP DEFAULT now()
 );
 
+ALTER TABLE records ADD COLUMN IF NOT EXISTS by_ghost BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE records ADD COLUMN IF NOT EXISTS takeback BOOLEAN NOT NULL DEFAULT FALSE;
+
 CREATE INDEX IF NOT EXISTS record_game_id_index ON records (game_id);
 CREATE INDEX IF NOT EXISTS record_room_id_index ON records (room_id);
 CREATE INDEX IF NOT EXISTS record_time_index ON records (time);
@@ -41,14 +55,82 @@ CREATE TABLE IF NOT EXISTS ratings
 (
     user_no INTEGER  NOT NULL,
     game_id CHAR(64) NOT NULL,
-    diff    INTEGER  NOT NULL,
-    rating  INTEGER  NOT NULL,
+    diff    INTEGER  NOT NULL, -- change of the displayed rating
+    rating  DOUBLE PRECISION NOT NULL,
+    deviation  DOUBLE PRECISION NOT NULL DEFAULT 350,
+    volatility DOUBLE PRECISION NOT NULL DEFAULT 0.06,
+    season  INTEGER, -- season the game counted toward
     time    TIMESTAMP DEFAULT now()
 );
 
+ALTER TABLE ratings ADD COLUMN IF NOT EXISTS season INTEGER;
+
 CREATE INDEX IF NOT EXISTS rating_user_id_index ON ratings (user_no);
 CREATE INDEX IF NOT EXISTS rating_game_id_index ON ratings (game_id);
+CREATE UNIQUE INDEX IF NOT EXISTS rating_game_id_user_no_index ON ratings (game_id, user_no);
 CREATE INDEX IF NOT EXISTS rating_time_index ON ratings (time);
+CREATE INDEX IF NOT EXISTS rating_time_user_no_index ON ratings (time, user_no) INCLUDE (game_id, diff);
+CREATE INDEX IF NOT EXISTS rating_season_user_no_index ON ratings (season, user_no);
+
+CREATE TABLE IF NOT EXISTS abandons
+(
+    user_no INTEGER  NOT NULL,
+    game_id CHAR(64) NOT NULL, -- ranked game played mostly by the ghost of the user
+    time    TIMESTAMP DEFAULT now(),
+    UNIQUE (game_id, user_no)
+);
+
+CREATE INDEX IF NOT EXISTS abandons_user_no_time_index ON abandons (user_no, time);
+
+CREATE SEQUENCE IF NOT EXISTS seasons_number_seq;
+
+CREATE TABLE IF NOT EXISTS seasons
+(
+    no       INTEGER UNIQUE NOT NULL DEFAULT nextval('seasons_number_seq'),
+    start_at TIMESTAMP      NOT NULL,
+    end_at   TIMESTAMP      NOT NULL,
+    archived BOOLEAN        NOT NULL DEFAULT FALSE -- if the final standings are saved
+);
+
+ALTER SEQUENCE seasons_number_seq OWNED BY seasons.no;
+
+CREATE INDEX IF NOT EXISTS seasons_time_index ON seasons (start_at, end_at);
+
+CREATE TABLE IF NOT EXISTS season_standings
+(
+    season  INTEGER     NOT NULL,
+    user_no INTEGER     NOT NULL,
+    rank    INTEGER     NOT NULL,
+    rating  INTEGER     NOT NULL,
+    games   INTEGER     NOT NULL,
+    badge   JSON        NOT NULL,
+    UNIQUE (season, user_no)
+);
+
+CREATE INDEX IF NOT EXISTS season_standings_user_no_index ON season_standings (user_no);
+
+CREATE TABLE IF NOT EXISTS game_analytics
+(
+    game_id CHAR(64)    NOT NULL UNIQUE,
+    rule    CHAR(64)    NOT NULL,
+    is_rank BOOLEAN     NOT NULL,
+    pledge  INTEGER     NOT NULL,
+    giruda  VARCHAR(1), -- NULL if no giruda
+    friend  VARCHAR(15) NOT NULL, -- how the friend was called
+    won     BOOLEAN     NOT NULL, -- if the ruling party won
+    score   INTEGER     NOT NULL,
+    points  INTEGER[]   NOT NULL, -- point cards taken by each seat
+    time    TIMESTAMP DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS game_analytics_rule_index ON game_analytics (rule, pledge);
+
+CREATE TABLE IF NOT EXISTS game_analysis
+(
+    game_id CHAR(64) NOT NULL UNIQUE,
+    flags   JSON     NOT NULL, -- decisions which cost expected points
+    time    TIMESTAMP DEFAULT now()
+);
 
 CREATE TABLE IF NOT EXISTS rules
 (
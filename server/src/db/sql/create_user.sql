@@ -18,16 +18,29 @@ CREATE TABLE IF NOT EXISTS users
     id       VARCHAR(31) UNIQUE NOT NULL,
     name     VARCHAR(63)        NOT NULL,
     email    VARCHAR(63) UNIQUE NOT NULL, -- primary email
-    rating   INT                          DEFAULT 0,
+    rating   DOUBLE PRECISION             DEFAULT 1500, -- glicko-2 rating
+    deviation  DOUBLE PRECISION NOT NULL DEFAULT 350,
+    volatility DOUBLE PRECISION NOT NULL DEFAULT 0.06,
     password CHAR(128)           NOT NULL, -- hashed password
     gen_time TIMESTAMP                    DEFAULT now(),
-    is_admin BOOLEAN                      DEFAULT FALSE
+    is_admin BOOLEAN                      DEFAULT FALSE,
+    last_decay TIMESTAMP                  DEFAULT now() -- last time the rating decayed by inactivity
 );
 
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_decay TIMESTAMP DEFAULT now();
+
 ALTER SEQUENCE users_number_seq OWNED BY users.no;
 ALTER SEQUENCE users_number_seq RESTART WITH 100;
 
 CREATE UNIQUE INDEX IF NOT EXISTS users_number_index ON users (no);
 CREATE UNIQUE INDEX IF NOT EXISTS users_id_index ON users (id);
 CREATE INDEX IF NOT EXISTS users_name_index ON users (name);
+CREATE INDEX IF NOT EXISTS users_rating_index ON users (rating DESC);
 CREATE UNIQUE INDEX IF NOT EXISTS users_email_index ON users (email);
+
+CREATE TABLE IF NOT EXISTS user_stats
+(
+    user_no INTEGER NOT NULL UNIQUE,
+    stats   JSON    NOT NULL, -- cached statistics of the games
+    time    TIMESTAMP DEFAULT now()
+);
@@ -1,7 +1,14 @@
 DROP TABLE IF EXISTS pre_users;
 DROP TABLE IF EXISTS users;
+DROP TABLE IF EXISTS user_stats;
 DROP TABLE IF EXISTS curr_rooms;
+DROP TABLE IF EXISTS room_snapshots;
 DROP TABLE IF EXISTS games;
 DROP TABLE IF EXISTS records;
 DROP TABLE IF EXISTS ratings;
+DROP TABLE IF EXISTS abandons;
+DROP TABLE IF EXISTS seasons;
+DROP TABLE IF EXISTS season_standings;
+DROP TABLE IF EXISTS game_analytics;
+DROP TABLE IF EXISTS game_analysis;
 DROP TABLE IF EXISTS rules;
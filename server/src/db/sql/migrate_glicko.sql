@@ -0,0 +1,11 @@
+-- ratings were integers changed by fixed amounts before glicko-2
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deviation DOUBLE PRECISION NOT NULL DEFAULT 350;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS volatility DOUBLE PRECISION NOT NULL DEFAULT 0.06;
+ALTER TABLE users ALTER COLUMN rating TYPE DOUBLE PRECISION;
+ALTER TABLE users ALTER COLUMN rating SET DEFAULT 1500;
+-- the old ratings are not glicko-2 ratings; they are recomputed from the ranked games after this
+UPDATE users SET rating=1500, deviation=350, volatility=0.06;
+
+ALTER TABLE ratings ADD COLUMN IF NOT EXISTS deviation DOUBLE PRECISION NOT NULL DEFAULT 350;
+ALTER TABLE ratings ADD COLUMN IF NOT EXISTS volatility DOUBLE PRECISION NOT NULL DEFAULT 0.06;
+ALTER TABLE ratings ALTER COLUMN rating TYPE DOUBLE PRECISION;
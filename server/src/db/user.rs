@@ -211,22 +211,24 @@ pub fn get_user_info(form: &GetInfoForm, pool: Pool) -> Result<UserInfo> {
     let mut client = pool.get()?;
     let res = match &form {
         GetInfoForm::UserNo(no) => {
-            let stmt = client.prepare("SELECT 1 no, id, name, email, rating, is_admin FROM users WHERE no=$1;")?;
-            client.query(&stmt, &[no])?
+            let stmt = client
+                .prepare("SELECT no, id, name, email, ROUND(rating)::INTEGER, is_admin FROM users WHERE no=$1;")?;
+            client.query(&stmt, &[&(*no as i32)])?
         }
         GetInfoForm::UserId(id) => {
             is_user_id_valid(id)?;
-            let stmt = client.prepare("SELECT 1 no, id, name, email, rating, is_admin FROM users WHERE id=$1;")?;
+            let stmt = client
+                .prepare("SELECT no, id, name, email, ROUND(rating)::INTEGER, is_admin FROM users WHERE id=$1;")?;
             client.query(&stmt, &[id])?
         }
     };
     let row = res.first().ok_or_else(|| err!(StatusCode::NOT_FOUND, "no user"))?;
     Ok(UserInfo {
-        no: UserNo(row.get(0)),
+        no: UserNo(row.get::<_, i32>(0) as u32),
         id: row.get(1),
         name: row.get(2),
         email: row.get(3),
-        rating: row.get(4),
+        rating: row.get::<_, i32>(4).max(0) as u32,
         room: None,
         is_admin: row.get(5),
     })
@@ -18,11 +18,14 @@
 #![allow(clippy::type_complexity)]
 
 mod actor;
+mod analytics;
 mod app_state;
 mod config;
 mod db;
 pub mod error;
 mod middlewares;
+mod rating;
+mod season;
 mod service;
 
 /// # Constant module
@@ -30,6 +33,7 @@ mod service;
 /// This module contains constants used in this crate. By defining in one place, it is easy to
 /// manipulate.
 mod constant {
+    use mighty::prelude::Preset;
     use std::time::Duration;
 
     /// Sends ping to client every `HEARTBEAT_INTERVAL`.
@@ -41,6 +45,90 @@ mod constant {
     /// If disconnected connection reconnects in `RECONNECTION_TIME`, client is not offline.
     pub const RECONNECTION_TIME: Duration = Duration::from_secs(10);
 
+    /// Ghost of the user waits `GHOST_DELAY` before each move.
+    pub const GHOST_DELAY: Duration = Duration::from_secs(1);
+
+    /// Game starts after `START_COUNTDOWN` when everyone is ready in the room with auto start.
+    pub const START_COUNTDOWN: Duration = Duration::from_secs(5);
+
+    /// Kicked user can't join the room again during `KICK_BAN_TIME`.
+    pub const KICK_BAN_TIME: Duration = Duration::from_secs(10 * 60);
+
+    /// Invite token of room is valid during `INVITE_VALID_DURATION`.
+    pub const INVITE_VALID_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Room keeps last `CHAT_BACKLOG` chats to send to newly attached sessions.
+    pub const CHAT_BACKLOG: usize = 50;
+
+    /// Restored room waits `RESTORE_WAIT` for its users to come back after the server restarts.
+    pub const RESTORE_WAIT: Duration = Duration::from_secs(60);
+
+    /// Leaderboard shows `RANKING_PAGE_SIZE` users in a page.
+    pub const RANKING_PAGE_SIZE: u32 = 50;
+
+    /// Statistics of user in the profile are cached during `PROFILE_CACHE_TIME`.
+    pub const PROFILE_CACHE_TIME: Duration = Duration::from_secs(10 * 60);
+
+    /// Profile shows last `PROFILE_RECENT_GAMES` games of user.
+    pub const PROFILE_RECENT_GAMES: usize = 10;
+
+    /// Seat is abandoned if the ghost made more than `ABANDON_RATIO` of its moves in the ranked game.
+    pub const ABANDON_RATIO: f64 = 0.5;
+
+    /// Teammates of the leaver lose only `LEAVER_TEAMMATE_LOSS_RATIO` of the rating they would lose.
+    pub const LEAVER_TEAMMATE_LOSS_RATIO: f64 = 0.5;
+
+    /// Ranked rooms can only be played with the rules of `RANKED_PRESETS`.
+    pub const RANKED_PRESETS: [Preset; 2] = [Preset::Default5, Preset::Ddshs5];
+
+    /// Matchmaker tries to make groups of the waiting users every `MATCH_INTERVAL`.
+    pub const MATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+    /// Users within `MATCH_BASE_WINDOW` of rating can be matched right after queued.
+    pub const MATCH_BASE_WINDOW: u32 = 100;
+
+    /// Rating window widens by `MATCH_WIDEN_RATE` every second of waiting.
+    pub const MATCH_WIDEN_RATE: u32 = 5;
+
+    /// Rating window doesn't widen more than `MATCH_MAX_WINDOW`.
+    pub const MATCH_MAX_WINDOW: u32 = 600;
+
+    /// Wait is estimated from the last `MATCH_WAIT_SAMPLES` matched users.
+    pub const MATCH_WAIT_SAMPLES: usize = 20;
+
+    /// Each command is evaluated with `ANALYSIS_SAMPLES` simulated games in the post-game analysis.
+    pub const ANALYSIS_SAMPLES: usize = 16;
+
+    /// Games are analyzed by `ANALYSIS_WORKERS` threads in the background.
+    pub const ANALYSIS_WORKERS: usize = 2;
+
+    /// Each season lasts `SEASON_DURATION`.
+    pub const SEASON_DURATION: Duration = Duration::from_secs(12 * 7 * 24 * 60 * 60);
+
+    /// Hub checks the end of the season and the inactive users every `SEASON_CHECK_INTERVAL`.
+    pub const SEASON_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    /// Users are placed in the season after playing `PLACEMENT_GAMES` ranked games.
+    pub const PLACEMENT_GAMES: u32 = 5;
+
+    /// At the rollover, ratings move `SEASON_RESET_RATIO` of the way back to the default.
+    pub const SEASON_RESET_RATIO: f64 = 0.5;
+
+    /// At the rollover, deviations go up to at least `SEASON_RESET_DEVIATION`.
+    pub const SEASON_RESET_DEVIATION: f64 = 200.0;
+
+    /// Ratings above `DECAY_THRESHOLD` decay when inactive, but not below it.
+    pub const DECAY_THRESHOLD: f64 = 1800.0;
+
+    /// Users who didn't play ranked games during `DECAY_INACTIVE_TIME` are inactive.
+    pub const DECAY_INACTIVE_TIME: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+    /// Ratings of inactive users decay once in `DECAY_INTERVAL`.
+    pub const DECAY_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+    /// Inactive users lose `DECAY_AMOUNT` every `DECAY_INTERVAL`.
+    pub const DECAY_AMOUNT: f64 = 25.0;
+
     /// If user doesn't have feedback during `ABSENT_TIME`, user is absent.
     pub const ABSENT_TIME: Duration = Duration::from_secs(300);
 
@@ -82,16 +170,25 @@ pub mod prelude {
 ///
 /// This module is used for `main.rs` to make `main.rs` short and easier to code.
 pub mod internal {
+    use crate::actor::hub::Shutdown;
     use crate::actor::mail::SetAppState;
+    use crate::actor::Hub;
     use crate::app_state::AppState;
     use crate::config::Config;
+    use crate::db;
+    use crate::dev::Pool;
     use crate::middlewares::https::RedirectHttps;
+    use crate::rating;
     use crate::service::{config_services, p404};
-    use actix::Actor;
+    use actix::{Actor, Addr};
     use actix_identity::{CookieIdentityPolicy, IdentityService};
+    use actix_web::dev::Server;
     use actix_web::middleware::Logger;
-    use actix_web::{web, App, HttpServer};
+    use actix_web::{rt, web, App, HttpServer};
     use clap::Clap;
+    use futures::FutureExt;
+    use r2d2_postgres::postgres::NoTls;
+    use r2d2_postgres::PostgresConnectionManager;
     use std::path::PathBuf;
 
     /// Configuration for this server
@@ -107,6 +204,42 @@ pub mod internal {
             about = "configuration file path (json, toml, yaml, hjson, ini files supported) (defaults to find server.*)"
         )]
         config: Option<PathBuf>,
+        #[clap(
+            long = "recompute-ratings",
+            about = "recomputes every rating from the ranked games saved and exits"
+        )]
+        recompute_ratings: bool,
+    }
+
+    /// Waits for the server to be stopped by a signal.
+    ///
+    /// Rooms are saved and users are notified of the maintenance before the server stops.
+    #[cfg(not(tarpaulin_include))]
+    async fn graceful_shutdown(server: Server, hub: Addr<Hub>) -> std::io::Result<()> {
+        let handle = server.handle();
+        rt::spawn(async move {
+            wait_signal().await;
+            let _ = hub.send(Shutdown).await;
+            handle.stop(true).await;
+        });
+        server.await
+    }
+
+    /// Waits for ctrl-c, or SIGTERM on unix
+    #[cfg(not(tarpaulin_include))]
+    async fn wait_signal() {
+        #[cfg(unix)]
+        {
+            use rt::signal::unix::{signal, SignalKind};
+            if let Ok(mut term) = signal(SignalKind::terminate()) {
+                futures::select! {
+                    _ = Box::pin(rt::signal::ctrl_c()).fuse() => {},
+                    _ = Box::pin(term.recv()).fuse() => {},
+                }
+                return;
+            }
+        }
+        let _ = rt::signal::ctrl_c().await;
     }
 
     /// Main function with https enabled
@@ -124,10 +257,17 @@ pub mod internal {
         let private_key = conf.secret.clone();
         let redirect = conf.https.as_ref().unwrap().redirect;
 
-        let state = AppState::new(serve_path, pg_config, mail.clone(), hex::encode(&conf.secret));
+        let state = AppState::new(
+            serve_path,
+            pg_config,
+            mail.clone(),
+            hex::encode(&conf.secret),
+            conf.ranked,
+        );
         mail.do_send(SetAppState(state.clone()));
+        let hub = state.hub.clone();
 
-        HttpServer::new(move || {
+        let server = HttpServer::new(move || {
             App::new()
                 .wrap(IdentityService::new(
                     CookieIdentityPolicy::new(&private_key)
@@ -142,8 +282,10 @@ pub mod internal {
         })
         .bind(format!("{}:{}", host, http_port))?
         .bind_openssl(format!("{}:{}", host, https_port), ssl_builder)?
-        .run()
-        .await
+        .disable_signals()
+        .run();
+
+        graceful_shutdown(server, hub).await
     }
 
     /// Main function with http only
@@ -158,10 +300,17 @@ pub mod internal {
         let pg_config = conf.get_pg_config();
         let private_key = conf.secret.clone();
 
-        let state = AppState::new(serve_path, pg_config, mail.clone(), hex::encode(&conf.secret));
+        let state = AppState::new(
+            serve_path,
+            pg_config,
+            mail.clone(),
+            hex::encode(&conf.secret),
+            conf.ranked,
+        );
         mail.do_send(SetAppState(state.clone()));
+        let hub = state.hub.clone();
 
-        HttpServer::new(move || {
+        let server = HttpServer::new(move || {
             App::new()
                 .wrap(IdentityService::new(
                     CookieIdentityPolicy::new(&private_key).name("web-mighty-auth"),
@@ -172,8 +321,10 @@ pub mod internal {
                 .default_service(web::to(p404))
         })
         .bind(format!("{}:{}", host, http_port))?
-        .run()
-        .await
+        .disable_signals()
+        .run();
+
+        graceful_shutdown(server, hub).await
     }
 
     /// Main function used in outer code
@@ -190,6 +341,14 @@ pub mod internal {
 
         let conf = Config::builder().add_file(path).add_env().build();
 
+        if opts.recompute_ratings {
+            let pool = Pool::new(PostgresConnectionManager::new(conf.get_pg_config(), NoTls)).unwrap();
+            return db::init(pool.clone())
+                .and_then(|_| db::migrate_glicko(pool.clone()))
+                .and_then(|_| rating::recompute(conf.ranked.abandon_penalty, pool))
+                .map_err(|e| std::io::Error::other(e.to_string()));
+        }
+
         if conf.https.is_some() {
             main_https(conf).await
         } else {
@@ -0,0 +1,350 @@
+//! # Rating module
+//!
+//! Ratings are calculated with [Glicko-2](http://www.glicko.net/glicko/glicko2.pdf).
+//! Each game is a rating period, where the ruling party (president and friend) plays against
+//! the opposition. Every player plays against the composite player of the other team.
+
+use crate::db::abandon::get_all_abandons;
+use crate::db::game::{get_rank_results, replace_ratings, settle_ratings, ChangeRatingForm, SettleForm};
+use crate::db::migrate_glicko;
+use crate::db::season::get_active_season;
+use crate::dev::*;
+use mighty::prelude::State;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// Scale between Glicko and Glicko-2
+const SCALE: f64 = 173.7178;
+
+/// Constrains the change of volatility
+const TAU: f64 = 0.5;
+
+/// Tolerance of the volatility iteration
+const EPSILON: f64 = 0.000001;
+
+/// Rating of a user
+///
+/// - `rating`: rating in the Glicko scale, starting from 1500
+/// - `deviation`: how uncertain the rating is
+/// - `volatility`: how much the rating fluctuates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Glicko {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Glicko {
+    fn default() -> Self {
+        Glicko {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+        }
+    }
+}
+
+impl Glicko {
+    /// Rating shown to the users
+    pub fn display(&self) -> u32 {
+        self.rating.round().max(0.0) as u32
+    }
+
+    fn mu(&self) -> f64 {
+        (self.rating - 1500.0) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+
+    /// Composite player of the team
+    fn composite(team: &[Glicko]) -> Glicko {
+        let n = team.len() as f64;
+        Glicko {
+            rating: team.iter().map(|g| g.rating).sum::<f64>() / n,
+            deviation: (team.iter().map(|g| g.deviation * g.deviation).sum::<f64>() / n).sqrt(),
+            volatility: team.iter().map(|g| g.volatility).sum::<f64>() / n,
+        }
+    }
+
+    /// New rating after the games against `results`, with the score 1 for win and 0 for lose.
+    pub fn update(&self, results: &[(Glicko, f64)]) -> Glicko {
+        if results.is_empty() {
+            let phi = (self.phi().powi(2) + self.volatility.powi(2)).sqrt();
+            return Glicko {
+                deviation: phi * SCALE,
+                ..*self
+            };
+        }
+
+        let (mu, phi, sigma) = (self.mu(), self.phi(), self.volatility);
+        let g = |phi: f64| 1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt();
+        let e = |mu_j: f64, phi_j: f64| 1.0 / (1.0 + (-g(phi_j) * (mu - mu_j)).exp());
+
+        let mut v_inv = 0.0;
+        let mut sum = 0.0;
+        for (opponent, score) in results {
+            let (g_j, e_j) = (g(opponent.phi()), e(opponent.mu(), opponent.phi()));
+            v_inv += g_j * g_j * e_j * (1.0 - e_j);
+            sum += g_j * (score - e_j);
+        }
+        let v = 1.0 / v_inv;
+        let delta = v * sum;
+
+        let a = (sigma * sigma).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2)) - (x - a) / (TAU * TAU)
+        };
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+        let (mut f_a, mut f_b) = (f(big_a), f(big_b));
+        while (big_b - big_a).abs() > EPSILON {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = big_c;
+            f_b = f_c;
+        }
+        let sigma = (big_a / 2.0).exp();
+
+        let phi_star = (phi * phi + sigma * sigma).sqrt();
+        let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu = mu + phi * phi * sum;
+        Glicko {
+            rating: mu * SCALE + 1500.0,
+            deviation: phi * SCALE,
+            volatility: sigma,
+        }
+    }
+}
+
+/// New ratings of every seat after the game.
+/// `ruling` and `winner` are bitmasks of the seats.
+pub fn rate_game(players: &[Glicko], ruling: u8, winner: u8) -> Vec<Glicko> {
+    let team = |ruling_side: bool| {
+        players
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (ruling & (1 << i) > 0) == ruling_side)
+            .map(|(_, g)| *g)
+            .collect::<Vec<_>>()
+    };
+    let (ruling_team, opposition) = (team(true), team(false));
+    if ruling_team.is_empty() || opposition.is_empty() {
+        return players.to_vec();
+    }
+    let composites = [Glicko::composite(&opposition), Glicko::composite(&ruling_team)];
+
+    players
+        .iter()
+        .enumerate()
+        .map(|(i, g)| {
+            let opponent = composites[(ruling & (1 << i) == 0) as usize];
+            let score = if winner & (1 << i) > 0 { 1.0 } else { 0.0 };
+            g.update(&[(opponent, score)])
+        })
+        .collect()
+}
+
+/// Bitmasks of the ruling party and the winners of the ended game
+pub fn game_result(state: &State) -> Option<(u8, u8)> {
+    match state {
+        State::GameEnded {
+            winner,
+            president,
+            friend,
+            ..
+        } => Some(((1 << president) | friend.map(|f| 1 << f).unwrap_or(0), *winner)),
+        _ => None,
+    }
+}
+
+/// Bitmask of the seats of users, where the ghost made most of the moves
+pub fn abandoned(moves: &[u32], ghost_moves: &[u32], users: &[UserNo]) -> u8 {
+    users
+        .iter()
+        .zip(moves.iter().zip(ghost_moves.iter()))
+        .enumerate()
+        .filter(|(_, (user_no, (moves, ghost)))| user_no.0 != 0 && **ghost as f64 > **moves as f64 * ABANDON_RATIO)
+        .fold(0, |mask, (seat, _)| mask | 1 << seat)
+}
+
+/// Applies the abandonment to the new ratings.
+/// Leavers lose `penalty` more, and their teammates who lost the game lose less.
+pub fn apply_abandon(players: &[Glicko], rated: Vec<Glicko>, ruling: u8, abandoned: u8, penalty: f64) -> Vec<Glicko> {
+    let team = |seat: usize| ruling & (1 << seat) > 0;
+    rated
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut g)| {
+            if abandoned & (1 << i) > 0 {
+                g.rating -= penalty;
+            } else if g.rating < players[i].rating
+                && (0..players.len()).any(|j| abandoned & (1 << j) > 0 && team(j) == team(i))
+            {
+                g.rating = players[i].rating + (g.rating - players[i].rating) * LEAVER_TEAMMATE_LOSS_RATIO;
+            }
+            g
+        })
+        .collect()
+}
+
+/// Rates the ended game and saves the new ratings of the users, counting toward the active season.
+/// Users of the `abandoned` seats lose `penalty` more.
+/// Settling the same game again returns the saved result.
+/// Returns the new rating and the change of the displayed rating of each seat.
+pub fn settle(
+    game_id: GameId,
+    users: &[UserNo],
+    state: &State,
+    abandoned: u8,
+    penalty: u32,
+    pool: Pool,
+) -> Result<Vec<(Glicko, i32)>> {
+    let (ruling, winner) = game_result(state).ok_or_else(|| err!("game is not ended"))?;
+    let season = get_active_season(pool.clone())?.map(|season| season.no);
+    settle_ratings(
+        &SettleForm {
+            game_id,
+            users: users.to_vec(),
+            season,
+        },
+        |players| {
+            let rated = rate_game(players, ruling, winner);
+            apply_abandon(players, rated, ruling, abandoned, penalty as f64)
+        },
+        pool,
+    )
+}
+
+/// Migrates the ratings from before Glicko-2 once, and recomputes them from the ranked games.
+/// If the server stops before the recompute is done, run it again with `--recompute-ratings`.
+pub fn migrate(penalty: u32, pool: Pool) -> Result<()> {
+    if migrate_glicko(pool.clone())? {
+        log::info!("migrated the ratings to glicko-2, recomputing them");
+        recompute(penalty, pool)?;
+    }
+    Ok(())
+}
+
+/// Recomputes every rating from the ranked games saved, in the order they were played.
+/// This is for migrating the ratings when the way of rating changes.
+/// Seasons are not kept, so every game is counted as played without a season.
+/// Abandonments are applied like `settle` with `penalty`, and the ratings are replaced at once.
+pub fn recompute(penalty: u32, pool: Pool) -> Result<()> {
+    let results = get_rank_results(pool.clone())?;
+    let abandons = get_all_abandons(pool.clone())?;
+
+    let mut ratings = HashMap::<u32, Glicko>::new();
+    let mut forms = Vec::new();
+    for (game_id, users, state) in results {
+        let (ruling, winner) = match game_result(&state) {
+            Some(result) => result,
+            None => continue,
+        };
+        let players = users
+            .iter()
+            .map(|no| ratings.get(no).copied().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let leavers = abandons.get(&game_id);
+        let abandoned = users
+            .iter()
+            .enumerate()
+            .filter(|(_, no)| **no != 0 && leavers.is_some_and(|v| v.contains(no)))
+            .fold(0, |mask, (seat, _)| mask | 1 << seat);
+        let rated = rate_game(&players, ruling, winner);
+        let rated = apply_abandon(&players, rated, ruling, abandoned, penalty as f64);
+        for (i, &no) in users.iter().enumerate() {
+            if no == 0 {
+                continue;
+            }
+            forms.push(ChangeRatingForm {
+                user_no: no,
+                game_id,
+                diff: rated[i].display() as i32 - players[i].display() as i32,
+                glicko: rated[i],
+                season: None,
+            });
+            ratings.insert(no, rated[i]);
+        }
+    }
+    replace_ratings(&forms, pool)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn glicko(rating: f64, deviation: f64) -> Glicko {
+        Glicko {
+            rating,
+            deviation,
+            volatility: 0.06,
+        }
+    }
+
+    #[test]
+    fn update_test() {
+        // example from the Glicko-2 paper
+        let player = glicko(1500.0, 200.0);
+        let res = player.update(&[
+            (glicko(1400.0, 30.0), 1.0),
+            (glicko(1550.0, 100.0), 0.0),
+            (glicko(1700.0, 300.0), 0.0),
+        ]);
+        assert!((res.rating - 1464.06).abs() < 0.01);
+        assert!((res.deviation - 151.52).abs() < 0.01);
+        assert!((res.volatility - 0.05999).abs() < 0.0001);
+
+        let res = player.update(&[]);
+        assert_eq!(res.rating, player.rating);
+        assert!(res.deviation > player.deviation);
+    }
+
+    #[test]
+    fn rate_game_test() {
+        let players = vec![Glicko::default(); 5];
+        let rated = rate_game(&players, 0b00011, 0b00011);
+        assert!(rated[0].rating > 1500.0);
+        assert_eq!(rated[0], rated[1]);
+        assert!(rated[2].rating < 1500.0);
+        assert_eq!(rated[2], rated[4]);
+        assert!(rated.iter().all(|g| g.deviation < 350.0));
+
+        // winning against the stronger team gives more
+        let mut strong = players.clone();
+        strong[2].rating = 1800.0;
+        let upset = rate_game(&strong, 0b00011, 0b00011);
+        assert!(upset[0].rating > rated[0].rating);
+    }
+
+    #[test]
+    fn abandon_test() {
+        let users = vec![UserNo(100), UserNo(101), UserNo(0), UserNo(102), UserNo(103)];
+        let abandoned = abandoned(&[10, 10, 10, 10, 10], &[1, 8, 10, 5, 0], &users);
+        assert_eq!(abandoned, 0b00010);
+
+        let players = vec![Glicko::default(); 5];
+        let rated = rate_game(&players, 0b00011, 0b11100);
+        let applied = apply_abandon(&players, rated.clone(), 0b00011, abandoned, 30.0);
+        assert!((applied[1].rating - (rated[1].rating - 30.0)).abs() < 1e-9);
+        assert!(applied[0].rating > rated[0].rating && applied[0].rating < 1500.0);
+        assert_eq!(applied[2], rated[2]);
+    }
+}
@@ -0,0 +1,157 @@
+//! # Season module
+//!
+//! Ranked games count toward the active season. When the season ends, the final standings are
+//! archived with badges and every rating is softly reset toward the default.
+//! Users are placed in the season after `PLACEMENT_GAMES` games, and the ratings of inactive users
+//! above `DECAY_THRESHOLD` decay while the season goes on.
+
+use crate::db::season::{
+    archive_season, get_active_season, get_ended_seasons, get_inactive_users, get_season_results, make_season,
+    set_decayed, Season, Standing,
+};
+use crate::dev::*;
+use crate::rating::Glicko;
+use mighty::prelude::Rule;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Badge given by the final standing of the season
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Badge {
+    Champion,
+    Top10,
+    Diamond,
+    Gold,
+    Silver,
+    Bronze,
+}
+
+impl Badge {
+    pub fn new(rank: u32, rating: i32) -> Badge {
+        match (rank, rating) {
+            (1, _) => Badge::Champion,
+            (2..=10, _) => Badge::Top10,
+            (_, 2000..) => Badge::Diamond,
+            (_, 1800..) => Badge::Gold,
+            (_, 1600..) => Badge::Silver,
+            _ => Badge::Bronze,
+        }
+    }
+}
+
+/// Only the rules of `RANKED_PRESETS` can be played in ranked rooms.
+pub fn is_ranked_rule(rule: &Rule) -> bool {
+    RANKED_PRESETS.iter().any(|preset| Rule::from(*preset) == *rule)
+}
+
+/// Rating at the start of the next season
+pub fn soft_reset(glicko: &Glicko) -> Glicko {
+    let default = Glicko::default();
+    Glicko {
+        rating: glicko.rating + (default.rating - glicko.rating) * SEASON_RESET_RATIO,
+        deviation: glicko.deviation.max(SEASON_RESET_DEVIATION),
+        volatility: glicko.volatility,
+    }
+}
+
+/// Rating after one `DECAY_INTERVAL` of inactivity
+pub fn decay(glicko: &Glicko) -> Glicko {
+    let inactive = glicko.update(&[]);
+    Glicko {
+        rating: (glicko.rating - DECAY_AMOUNT).max(DECAY_THRESHOLD.min(glicko.rating)),
+        ..inactive
+    }
+}
+
+/// Final standings from the results sorted by the rating.
+/// Users with the same rating get the same rank.
+pub fn rank_standings(season: u32, results: &[(u32, i32, u32)]) -> Vec<Standing> {
+    let mut standings = Vec::<Standing>::with_capacity(results.len());
+    for (i, &(user_no, rating, games)) in results.iter().enumerate() {
+        let rank = match standings.last() {
+            Some(last) if last.rating == rating => last.rank,
+            _ => i as u32 + 1,
+        };
+        standings.push(Standing {
+            season,
+            user_no,
+            rank,
+            rating,
+            games,
+            badge: Badge::new(rank, rating),
+        });
+    }
+    standings
+}
+
+/// Archives the ended seasons, starts a new season if none is going on, and decays the inactive users.
+/// Returns the active season.
+pub fn maintain(pool: Pool) -> Result<Season> {
+    for season in get_ended_seasons(pool.clone())? {
+        let standings = rank_standings(season.no, &get_season_results(season.no, pool.clone())?);
+        archive_season(season.no, &standings, soft_reset, pool.clone())?;
+    }
+
+    let season = match get_active_season(pool.clone())? {
+        Some(season) => season,
+        None => make_season(SystemTime::now(), pool.clone())?,
+    };
+
+    for (user_no, glicko) in get_inactive_users(pool.clone())? {
+        set_decayed(user_no, decay(&glicko), pool.clone())?;
+    }
+    Ok(season)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::prelude::Preset;
+
+    #[test]
+    fn soft_reset_test() {
+        let high = Glicko {
+            rating: 2100.0,
+            deviation: 60.0,
+            volatility: 0.06,
+        };
+        let reset = soft_reset(&high);
+        assert_eq!(reset.rating, 1800.0);
+        assert_eq!(reset.deviation, SEASON_RESET_DEVIATION);
+        assert_eq!(soft_reset(&Glicko::default()), Glicko::default());
+    }
+
+    #[test]
+    fn decay_test() {
+        let mut glicko = Glicko {
+            rating: 1830.0,
+            deviation: 60.0,
+            volatility: 0.06,
+        };
+        glicko = decay(&glicko);
+        assert_eq!(glicko.rating, 1805.0);
+        assert!(glicko.deviation > 60.0);
+        glicko = decay(&glicko);
+        assert_eq!(glicko.rating, DECAY_THRESHOLD);
+        assert_eq!(decay(&glicko).rating, DECAY_THRESHOLD);
+    }
+
+    #[test]
+    fn rank_standings_test() {
+        let results = [(100, 2050, 30), (101, 1900, 12), (102, 1900, 8), (103, 1700, 5)];
+        let standings = rank_standings(1, &results);
+        let ranks = standings.iter().map(|s| s.rank).collect::<Vec<_>>();
+        assert_eq!(ranks, vec![1, 2, 2, 4]);
+        assert_eq!(standings[0].badge, Badge::Champion);
+        assert_eq!(standings[2].badge, Badge::Top10);
+        assert_eq!(Badge::new(11, 1850), Badge::Gold);
+        assert_eq!(Badge::new(40, 1500), Badge::Bronze);
+    }
+
+    #[test]
+    fn is_ranked_rule_test() {
+        assert!(is_ranked_rule(&Rule::from(Preset::Default5)));
+        assert!(!is_ranked_rule(&Rule::from(Preset::Yu5)));
+    }
+}
@@ -1 +1,108 @@
+use crate::actor::hub::AnalyzeGame;
+use crate::analytics::{aggregate, to_csv};
+use crate::app_state::AppState;
+use crate::db::analysis::get_analysis;
+use crate::db::analytics::{get_game_records, AnalyticsForm, ExportFormat};
+use crate::db::game::get_replay;
+use crate::db::profile::get_profile;
+use crate::db::ranking::{get_ranking, RankingForm};
+use crate::db::season::{get_seasons, get_standings};
+use crate::db::user::{get_user_info, GetInfoForm};
+use crate::dev::*;
+use actix_identity::Identity;
+use actix_web::http::header;
+use actix_web::{get, web, HttpResponse};
 
+/// Aggregates of the finished games for admins
+#[get("/analytics")]
+pub async fn analytics(
+    id: Identity,
+    state: web::Data<AppState>,
+    form: web::Query<AnalyticsForm>,
+) -> Result<HttpResponse, Error> {
+    let user_no = id
+        .identity()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| err!(StatusCode::UNAUTHORIZED, "login required"))?;
+    let info = get_user_info(&GetInfoForm::UserNo(user_no), state.pool.clone())?;
+    ensure!(info.is_admin, StatusCode::FORBIDDEN, "admin only");
+
+    let records = get_game_records(&form, state.pool.clone())?;
+    let aggregates = aggregate(&records, form.group);
+    Ok(match form.format {
+        ExportFormat::Json => HttpResponse::Ok().json(aggregates),
+        ExportFormat::Csv => HttpResponse::Ok()
+            .insert_header(header::ContentType(mime::TEXT_CSV_UTF_8))
+            .insert_header((header::CONTENT_DISPOSITION, "attachment; filename=\"analytics.csv\""))
+            .body(to_csv(&aggregates)),
+    })
+}
+
+/// Flags of the decisions in the game, only for logged in users.
+/// Responds `202 Accepted` and starts the analysis if the game is not analyzed yet.
+#[get("/analysis/{game_id}")]
+pub async fn analysis(
+    id: Identity,
+    state: web::Data<AppState>,
+    game_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let game_id = game_id
+        .parse()
+        .map_err(|_| err!(StatusCode::NOT_FOUND, "no game found"))?;
+    let viewer = id
+        .identity()
+        .and_then(|id| id.parse().ok())
+        .map(UserNo)
+        .ok_or_else(|| err!(StatusCode::UNAUTHORIZED, "login required"))?;
+    match get_analysis(game_id, viewer, state.pool.clone())? {
+        Some(flags) => Ok(HttpResponse::Ok().json(flags)),
+        None => {
+            state.hub.do_send(AnalyzeGame(game_id));
+            Ok(HttpResponse::Accepted().finish())
+        }
+    }
+}
+
+#[get("/ranking")]
+pub async fn ranking(
+    id: Identity,
+    state: web::Data<AppState>,
+    form: web::Query<RankingForm>,
+) -> Result<HttpResponse, Error> {
+    let viewer = id.identity().and_then(|id| id.parse().ok()).map(UserNo);
+    let ranking = get_ranking(&form, viewer, state.pool.clone())?;
+    Ok(HttpResponse::Ok().json(ranking))
+}
+
+#[get("/replay/{game_id}")]
+pub async fn replay(
+    id: Identity,
+    state: web::Data<AppState>,
+    game_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let game_id = game_id
+        .parse()
+        .map_err(|_| err!(StatusCode::NOT_FOUND, "no game found"))?;
+    let viewer = id.identity().and_then(|id| id.parse().ok()).map(UserNo);
+    let replay = get_replay(game_id, viewer, state.pool.clone())?;
+    Ok(HttpResponse::Ok().json(replay))
+}
+
+#[get("/season")]
+pub async fn seasons(state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let seasons = get_seasons(state.pool.clone())?;
+    Ok(HttpResponse::Ok().json(seasons))
+}
+
+#[get("/season/{season}")]
+pub async fn season(state: web::Data<AppState>, season: web::Path<u32>) -> Result<HttpResponse, Error> {
+    let standings = get_standings(season.into_inner(), state.pool.clone())?;
+    Ok(HttpResponse::Ok().json(standings))
+}
+
+#[get("/user/{user_id}")]
+pub async fn user_info(state: web::Data<AppState>, user_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    let info = get_user_info(&GetInfoForm::UserId(user_id.into_inner()), state.pool.clone())?;
+    let profile = get_profile(info, state.pool.clone())?;
+    Ok(HttpResponse::Ok().json(profile))
+}
@@ -3,6 +3,10 @@ pub mod ws;
 
 use crate::actor::mail::SendVerification;
 use crate::app_state::AppState;
+use crate::db::game::get_replay;
+use crate::db::profile::get_profile;
+use crate::db::ranking::{get_ranking, RankingForm};
+use crate::db::season::{get_seasons, get_standings};
 use crate::db::user::{get_user_info, GetInfoForm};
 use crate::dev::*;
 use crate::service::p404;
@@ -10,6 +14,7 @@ use actix_identity::Identity;
 use actix_web::http::header;
 use actix_web::{get, web, HttpResponse, Responder};
 use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
 use serde_json::{json, Map};
 
 #[get("/admin")]
@@ -156,14 +161,66 @@ pub async fn list(id: Identity, state: web::Data<AppState>) -> impl Responder {
     }
 }
 
-// #[get("/ranking")]
-// pub async fn ranking(state: web::Data<AppState>) -> impl Responder {
-//     let body = state.render("ranking.hbs", &json!({})).unwrap();
-//     HttpResponse::Ok()
-//         .set(header::CacheControl(vec![header::CacheDirective::Private]))
-//         .set(header::ContentType(mime::TEXT_HTML_UTF_8))
-//         .body(body)
-// }
+#[get("/ranking")]
+pub async fn ranking(
+    id: Identity,
+    state: web::Data<AppState>,
+    form: web::Query<RankingForm>,
+) -> Result<HttpResponse, Error> {
+    let viewer = id.identity().and_then(|id| id.parse().ok()).map(UserNo);
+    let ranking = get_ranking(&form, viewer, state.pool.clone())?;
+    let pages = (ranking.total as u32).div_ceil(RANKING_PAGE_SIZE);
+    let body = state
+        .render(
+            "ranking.hbs",
+            &json!({
+                "id": id.identity(),
+                "ranking": ranking,
+                "query": form.into_inner(),
+                "has_prev": ranking.page > 0,
+                "has_next": ranking.page + 1 < pages,
+                "prev": ranking.page.saturating_sub(1),
+                "next": ranking.page + 1,
+            }),
+        )
+        .unwrap();
+    Ok(HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![header::CacheDirective::Private]))
+        .insert_header(header::ContentType(mime::TEXT_HTML_UTF_8))
+        .body(body))
+}
+
+/// Move of the replay to start from
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplayQuery {
+    #[serde(default, rename = "move")]
+    pub start: usize,
+}
+
+#[get("/replay/{game_id}")]
+pub async fn replay(
+    id: Identity,
+    state: web::Data<AppState>,
+    game_id: web::Path<String>,
+    query: web::Query<ReplayQuery>,
+) -> Result<HttpResponse, Error> {
+    let game_id = game_id
+        .parse()
+        .map_err(|_| err!(StatusCode::NOT_FOUND, "no game found"))?;
+    let viewer = id.identity().and_then(|id| id.parse().ok()).map(UserNo);
+    let replay = get_replay(game_id, viewer, state.pool.clone())?;
+    let start = query.start.min(replay.states.len().saturating_sub(1));
+    let body = state
+        .render(
+            "replay.hbs",
+            &json!({ "id": id.identity(), "replay": replay, "start": start }),
+        )
+        .unwrap();
+    Ok(HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![header::CacheDirective::Private]))
+        .insert_header(header::ContentType(mime::TEXT_HTML_UTF_8))
+        .body(body))
+}
 
 #[get("/res/{file:.*}")]
 pub async fn resource(state: web::Data<AppState>, file: web::Path<String>) -> impl Responder {
@@ -210,28 +267,41 @@ pub async fn room(id: Identity, state: web::Data<AppState>, room_id: web::Path<S
 //     }
 // }
 
-// #[get("/user/{user_id}")]
-// pub async fn user_info(
-//     id: Identity,
-//     state: web::Data<AppState>,
-//     web::Path(user_id): web::Path<String>,
-// ) -> Result<HttpResponse, Error> {
-//     let mut val = Map::new();
-//     if let Some(id) = id.identity() {
-//         val.insert("id".to_owned(), json!(id));
-//     }
-//
-//     let user_no = user_id.parse().unwrap();
-//     let user_info = get_user_info(GetInfoForm::UserNo(user_no), state.pool.clone())?;
-//
-//     val.insert("user_id".to_owned(), json!(user_info.id));
-//     val.insert("name".to_owned(), json!(user_info.name));
-//     val.insert("rating".to_owned(), json!(user_info.rating));
-//     val.insert("is_admin".to_owned(), json!(user_info.is_admin));
-//
-//     let body = state.render("user.hbs", &val).unwrap();
-//     Ok(HttpResponse::Ok()
-//         .set(header::CacheControl(vec![header::CacheDirective::Private]))
-//         .set(header::ContentType(mime::TEXT_HTML_UTF_8))
-//         .body(body))
-// }
+#[get("/season/{season}")]
+pub async fn season(id: Identity, state: web::Data<AppState>, season: web::Path<u32>) -> Result<HttpResponse, Error> {
+    let seasons = get_seasons(state.pool.clone())?;
+    let season = seasons
+        .iter()
+        .find(|s| s.no == *season)
+        .ok_or_else(|| err!(StatusCode::NOT_FOUND, "no season found"))?;
+    let standings = get_standings(season.no, state.pool.clone())?;
+
+    let body = state
+        .render(
+            "season.hbs",
+            &json!({ "id": id.identity(), "season": season, "seasons": seasons, "standings": standings }),
+        )
+        .unwrap();
+    Ok(HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![header::CacheDirective::Private]))
+        .insert_header(header::ContentType(mime::TEXT_HTML_UTF_8))
+        .body(body))
+}
+
+#[get("/user/{user_id}")]
+pub async fn user_info(
+    id: Identity,
+    state: web::Data<AppState>,
+    user_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let info = get_user_info(&GetInfoForm::UserId(user_id.into_inner()), state.pool.clone())?;
+    let profile = get_profile(info, state.pool.clone())?;
+
+    let body = state
+        .render("user.hbs", &json!({ "id": id.identity(), "profile": profile }))
+        .unwrap();
+    Ok(HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![header::CacheDirective::Private]))
+        .insert_header(header::ContentType(mime::TEXT_HTML_UTF_8))
+        .body(body))
+}
@@ -38,7 +38,11 @@ pub async fn main(
             .into_future()
             .await
             .unwrap()?;
-        ws::start(Main::new(addr, state.hub.clone()).make(), &req, stream)
+        ws::start(
+            Main::new(addr, state.hub.clone(), state.matchmaker.clone()).make(),
+            &req,
+            stream,
+        )
     } else {
         Ok(p404(state).await)
     }
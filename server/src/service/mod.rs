@@ -14,15 +14,27 @@ pub fn config_services(cfg: &mut web::ServiceConfig) {
         .service(get::logout)
         .service(get::observe)
         .service(get::list)
-        // .service(get::ranking)
+        .service(get::ranking)
         .service(get::pre_register)
         .service(get::pre_register_complete)
         .service(get::register)
         .service(get::register_complete)
+        .service(get::replay)
         .service(get::resource)
         .service(get::room)
+        .service(get::season)
         // .service(get::setting)
-        // .service(get::user_info)
+        .service(get::user_info)
+        .service(
+            web::scope("/api")
+                .service(get::api::analysis)
+                .service(get::api::analytics)
+                .service(get::api::ranking)
+                .service(get::api::replay)
+                .service(get::api::seasons)
+                .service(get::api::season)
+                .service(get::api::user_info),
+        )
         .service(
             web::scope("/ws")
                 .service(get::ws::list)
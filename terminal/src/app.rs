@@ -0,0 +1,824 @@
+//! State of the terminal client.
+//!
+//! `App` doesn't touch the network or the terminal.
+//! It takes messages from the server and key events, and returns `Action`s for `main` to run.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use mighty::bot::{is_valid_command, valid_commands, waiting_users};
+use mighty::prelude::{Card, Color, Command, FriendFunc, Pattern, Preset, Rule, State};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use types::{
+    GameSummary, ListToClient, ListToServer, MainToClient, MainToServer, RoomId, RoomInfo, RoomUserToClient,
+    RoomUserToServer, SimpleRoomInfo, SpectatorPolicy, SpectatorView, UserInfo, UserNo, VoteKind,
+};
+
+/// Giruda in the order of the pledge dialog. `None` is no giruda.
+pub const GIRUDA: [Option<Pattern>; 5] = [
+    Some(Pattern::Spade),
+    Some(Pattern::Diamond),
+    Some(Pattern::Heart),
+    Some(Pattern::Clover),
+    None,
+];
+
+/// Message from the server.
+#[derive(Debug, Clone)]
+pub enum Net {
+    Main(MainToClient),
+    List(ListToClient),
+    Room(RoomUserToClient),
+    Closed(&'static str),
+}
+
+/// What `main` should do for the app.
+#[derive(Debug, Clone)]
+pub enum Action {
+    Main(MainToServer),
+    List(ListToServer),
+    Room(RoomUserToServer),
+    ConnectRoom,
+    DisconnectRoom,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    List,
+    Room,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Hand,
+    Chat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dialog {
+    /// Pledge in election, or change of giruda while selecting friend.
+    Pledge { giruda: usize, pledge: u8 },
+    /// Dropping cards, then choosing the friend.
+    Friend {
+        drop: Vec<Card>,
+        funcs: Vec<FriendFunc>,
+        selected: Option<usize>,
+    },
+    /// Choosing one of the ways to play the card (joker rush, joker call).
+    Go { cmds: Vec<Command>, selected: usize },
+}
+
+#[derive(Debug)]
+pub struct App {
+    pub user: UserInfo,
+    pub screen: Screen,
+    pub rooms: BTreeMap<RoomId, SimpleRoomInfo>,
+    pub room_cursor: usize,
+    pub room: Option<RoomInfo>,
+    pub rule: Option<Rule>,
+    pub state: Option<State>,
+    pub summary: Option<GameSummary>,
+    pub chat: Vec<(UserNo, String)>,
+    pub input: String,
+    pub focus: Focus,
+    pub card_cursor: usize,
+    pub dialog: Option<Dialog>,
+    pub message: String,
+    joining: bool,
+}
+
+impl App {
+    pub fn new(user: UserInfo) -> App {
+        App {
+            user,
+            screen: Screen::List,
+            rooms: BTreeMap::new(),
+            room_cursor: 0,
+            room: None,
+            rule: None,
+            state: None,
+            summary: None,
+            chat: Vec::new(),
+            input: String::new(),
+            focus: Focus::Hand,
+            card_cursor: 0,
+            dialog: None,
+            message: String::new(),
+            joining: false,
+        }
+    }
+
+    /// Actions to run right after connecting.
+    pub fn init(&self) -> Vec<Action> {
+        vec![refresh()]
+    }
+
+    /// Seat of this user in the joined room.
+    pub fn seat(&self) -> Option<usize> {
+        self.room.as_ref()?.user.iter().position(|u| *u == self.user.no)
+    }
+
+    /// Cards of this user; jokers first, then giruda, then the others in `pattern_order`.
+    pub fn hand(&self) -> Vec<Card> {
+        let (state, rule, seat) = match (&self.state, &self.rule, self.seat()) {
+            (Some(state), Some(rule), Some(seat)) => (state, rule, seat),
+            _ => return Vec::new(),
+        };
+        let (mut hand, giruda) = match state {
+            State::Election { deck, .. } => (deck[seat].clone(), None),
+            State::SelectFriend { deck, giruda, .. } => (deck[seat].clone(), *giruda),
+            State::InGame { deck, giruda, .. } => (deck[seat].clone(), *giruda),
+            State::GameEnded { .. } => (Vec::new(), None),
+        };
+        let pattern_idx = |p: Pattern| rule.pattern_order.iter().position(|x| *x == p).unwrap_or(4);
+        hand.sort_by_key(|c| match c {
+            Card::Joker(Color::Black) => (0, 0, Reverse(0)),
+            Card::Joker(Color::Red) => (0, 1, Reverse(0)),
+            Card::Normal(p, n) if Some(*p) == giruda => (1, 0, Reverse(*n)),
+            Card::Normal(p, n) => (2, pattern_idx(*p), Reverse(*n)),
+        });
+        hand
+    }
+
+    /// Whether this user should act now.
+    pub fn is_my_turn(&self) -> bool {
+        match (&self.state, &self.rule, self.seat()) {
+            (Some(state), Some(rule), Some(seat)) => waiting_users(state, rule) & (1 << seat) > 0,
+            _ => false,
+        }
+    }
+
+    /// Commands this user can send now.
+    pub fn legal_commands(&self) -> Vec<Command> {
+        match (&self.state, &self.rule, self.seat()) {
+            (Some(state), Some(rule), Some(seat)) if self.is_my_turn() => valid_commands(state, seat, rule),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Cards this user can play now.
+    pub fn legal_cards(&self) -> Vec<Card> {
+        let mut cards = self
+            .legal_commands()
+            .into_iter()
+            .filter_map(|c| match c {
+                Command::Go(card, ..) => Some(card),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        cards.dedup();
+        cards
+    }
+
+    pub fn is_valid(&self, cmd: &Command) -> bool {
+        match (&self.state, &self.rule, self.seat()) {
+            (Some(state), Some(rule), Some(seat)) => is_valid_command(state, seat, cmd, rule),
+            _ => false,
+        }
+    }
+
+    /// Number of cards president should drop while selecting friend.
+    pub fn drop_cnt(&self) -> usize {
+        match (&self.state, &self.rule, self.seat()) {
+            (Some(State::SelectFriend { deck, .. }), Some(rule), Some(seat)) => {
+                deck[seat].len() - deck[(seat + 1) % (rule.user_cnt as usize)].len()
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn on_net(&mut self, msg: Net) -> Vec<Action> {
+        match msg {
+            Net::Main(MainToClient::Joined(info)) => {
+                self.joining = false;
+                self.screen = Screen::Room;
+                self.room = Some(info);
+                self.rule = None;
+                self.state = None;
+                self.chat.clear();
+                self.dialog = None;
+                self.message.clear();
+                return vec![Action::ConnectRoom];
+            }
+            Net::Main(MainToClient::UserInfo(info)) => {
+                if self.joining {
+                    self.joining = false;
+                    self.message = "couldn't join the room".to_owned();
+                }
+                self.user = info;
+            }
+            Net::Main(MainToClient::UserStatus(..)) => {}
+            Net::Main(MainToClient::Queued(status)) => {
+                self.message = format!("waiting for a match: {} in queue", status.waiting);
+            }
+            Net::Main(MainToClient::QueueCancelled(reason)) => {
+                self.message = format!("left the queue: {}", reason);
+            }
+            Net::Main(MainToClient::Matched(_)) => {
+                self.joining = true;
+                self.message = "match found".to_owned();
+            }
+            Net::List(ListToClient::RoomList(list)) => {
+                let mut actions = Vec::new();
+                let old = self.rooms.keys().copied().collect::<Vec<_>>();
+                for id in old.iter().filter(|id| !list.contains(id)) {
+                    self.rooms.remove(id);
+                    actions.push(Action::List(ListToServer::Unsubscribe(*id)));
+                }
+                for id in list.iter().filter(|id| !old.contains(id)) {
+                    actions.push(Action::List(ListToServer::Subscribe(*id)));
+                }
+                self.clamp_room_cursor();
+                return actions;
+            }
+            Net::List(ListToClient::Room(info)) => {
+                self.rooms.insert(info.id, info);
+            }
+            Net::Room(RoomUserToClient::Room(info)) => {
+                self.room = Some(info);
+            }
+            Net::Room(RoomUserToClient::Rule(rule)) => {
+                self.rule = Some(rule);
+            }
+            Net::Room(RoomUserToClient::Game(state)) => {
+                if !matches!(state, State::GameEnded { .. }) {
+                    self.summary = None;
+                }
+                self.state = Some(state);
+                self.after_state_change();
+            }
+            Net::Room(RoomUserToClient::Clock(_)) => {}
+            Net::Room(RoomUserToClient::Chat(chat, user_no)) => {
+                self.chat.push((user_no, chat));
+            }
+            Net::Room(RoomUserToClient::ChatLog(chat)) => {
+                self.chat = chat.into_iter().map(|(chat, user_no)| (user_no, chat)).collect();
+            }
+            Net::Room(RoomUserToClient::Invite(token)) => {
+                self.message = format!("invite token: {}", token);
+            }
+            Net::Room(RoomUserToClient::Reveal(_)) => {}
+            Net::Room(RoomUserToClient::Summary(summary)) => {
+                self.summary = Some(summary);
+            }
+            Net::Room(RoomUserToClient::Maintenance) => {
+                self.message = "server is going down for maintenance, the game will resume after it".to_owned();
+            }
+            Net::Room(RoomUserToClient::Kicked) => {
+                self.exit_room();
+                self.message = "kicked from the room".to_owned();
+                return vec![Action::DisconnectRoom, refresh()];
+            }
+            Net::Closed(name) => {
+                self.message = format!("{} connection is closed", name);
+            }
+        }
+        Vec::new()
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Vec<Action> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return vec![Action::Quit];
+        }
+        match self.screen {
+            Screen::List => self.on_list_key(key.code),
+            Screen::Room if self.focus == Focus::Chat => self.on_chat_key(key.code),
+            Screen::Room if self.dialog.is_some() => self.on_dialog_key(key.code),
+            Screen::Room => self.on_room_key(key.code),
+        }
+    }
+
+    fn on_list_key(&mut self, code: KeyCode) -> Vec<Action> {
+        match code {
+            KeyCode::Up => {
+                self.room_cursor = self.room_cursor.saturating_sub(1);
+            }
+            KeyCode::Down => {
+                self.room_cursor += 1;
+                self.clamp_room_cursor();
+            }
+            KeyCode::Enter => {
+                if let Some(id) = self.rooms.keys().nth(self.room_cursor) {
+                    self.joining = true;
+                    return vec![Action::Main(MainToServer::Join(*id))];
+                }
+            }
+            KeyCode::Char('n') => {
+                self.joining = true;
+                let name = format!("{}'s room", self.user.name);
+                let rule = Box::new(Rule::from(Preset::Default5));
+                return vec![Action::Main(MainToServer::MakeRoom(name, rule, false))];
+            }
+            KeyCode::Char('r') => return vec![refresh()],
+            KeyCode::Char('q') | KeyCode::Esc => return vec![Action::Quit],
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    fn on_chat_key(&mut self, code: KeyCode) -> Vec<Action> {
+        match code {
+            KeyCode::Esc => self.focus = Focus::Hand,
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                self.focus = Focus::Hand;
+                let input = input.trim();
+                if input.is_empty() {
+                    return Vec::new();
+                }
+                return match input.split_once(' ').unwrap_or((input, "")) {
+                    ("/start", _) => vec![Action::Room(RoomUserToServer::Start)],
+                    ("/leave", _) => self.leave(),
+                    ("/name", name) if !name.is_empty() => {
+                        vec![Action::Room(RoomUserToServer::ChangeName(name.to_owned()))]
+                    }
+                    ("/stand", _) => vec![Action::Room(RoomUserToServer::Stand)],
+                    ("/invite", _) => vec![Action::Room(RoomUserToServer::Invite)],
+                    ("/yes", _) => vec![Action::Room(RoomUserToServer::Vote(true))],
+                    ("/no", _) => vec![Action::Room(RoomUserToServer::Vote(false))],
+                    ("/spectate", view) if parse_spectator_view(view).is_some() => {
+                        let policy = SpectatorPolicy {
+                            view: parse_spectator_view(view).unwrap(),
+                            ..self.room.as_ref().map(|r| r.spectator).unwrap_or_default()
+                        };
+                        vec![Action::Room(RoomUserToServer::SetSpectatorPolicy(policy))]
+                    }
+                    ("/vote", kind) if parse_vote(kind).is_some() => {
+                        vec![Action::Room(RoomUserToServer::Propose(parse_vote(kind).unwrap()))]
+                    }
+                    ("/password", password) => {
+                        let password = Some(password.to_owned()).filter(|x| !x.is_empty());
+                        vec![Action::Room(RoomUserToServer::SetPassword(password))]
+                    }
+                    (cmd @ ("/kick" | "/head" | "/coach" | "/uncoach"), user_no) if user_no.parse::<u32>().is_ok() => {
+                        let user_no = UserNo(user_no.parse().unwrap());
+                        let msg = match cmd {
+                            "/kick" => RoomUserToServer::Kick(user_no),
+                            "/head" => RoomUserToServer::TransferHead(user_no),
+                            _ => RoomUserToServer::Coach(user_no, cmd == "/coach"),
+                        };
+                        vec![Action::Room(msg)]
+                    }
+                    ("/ready", _) => vec![Action::Room(RoomUserToServer::Ready(true))],
+                    ("/unready", _) => vec![Action::Room(RoomUserToServer::Ready(false))],
+                    (cmd @ ("/autostart" | "/botfill" | "/inviteonly"), on @ ("on" | "off")) => {
+                        let msg = match cmd {
+                            "/autostart" => RoomUserToServer::SetAutoStart(on == "on"),
+                            "/botfill" => RoomUserToServer::SetBotFill(on == "on"),
+                            _ => RoomUserToServer::SetInviteOnly(on == "on"),
+                        };
+                        vec![Action::Room(msg)]
+                    }
+                    (cmd @ ("/sit" | "/swap" | "/lock" | "/unlock"), seat) if seat.parse::<usize>().is_ok() => {
+                        let seat = seat.parse().unwrap();
+                        let msg = match cmd {
+                            "/sit" => RoomUserToServer::Sit(seat),
+                            "/swap" => RoomUserToServer::Swap(seat),
+                            _ => RoomUserToServer::Lock(seat, cmd == "/lock"),
+                        };
+                        vec![Action::Room(msg)]
+                    }
+                    _ if input.starts_with('/') => {
+                        self.message = format!("unknown command: {}", input);
+                        Vec::new()
+                    }
+                    _ => vec![Action::Room(RoomUserToServer::Chat(input.to_owned()))],
+                };
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    fn on_room_key(&mut self, code: KeyCode) -> Vec<Action> {
+        let hand_len = self.hand().len();
+        match code {
+            KeyCode::Left => self.card_cursor = self.card_cursor.saturating_sub(1),
+            KeyCode::Right if self.card_cursor + 1 < hand_len => self.card_cursor += 1,
+            KeyCode::Enter => return self.play(),
+            KeyCode::Char('b') => self.open_pledge(),
+            KeyCode::Char('f') => self.open_friend(),
+            KeyCode::Char('s') => return vec![Action::Room(RoomUserToServer::Start)],
+            KeyCode::Char('c') | KeyCode::Char('/') | KeyCode::Tab => {
+                self.focus = Focus::Chat;
+                if code == KeyCode::Char('/') {
+                    self.input.push('/');
+                }
+            }
+            KeyCode::Char('l') => return self.leave(),
+            KeyCode::Char('q') => return vec![Action::Quit],
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    fn on_dialog_key(&mut self, code: KeyCode) -> Vec<Action> {
+        if code == KeyCode::Esc {
+            match &mut self.dialog {
+                Some(Dialog::Friend { selected, .. }) if selected.is_some() => *selected = None,
+                _ => self.dialog = None,
+            }
+            return Vec::new();
+        }
+
+        let hand = self.hand();
+        let is_election = matches!(self.state, Some(State::Election { .. }));
+        let (min, max) = self
+            .rule
+            .as_ref()
+            .map(|r| (r.pledge.min, r.pledge.max))
+            .unwrap_or((0, 20));
+        let drop_cnt = self.drop_cnt();
+        let mut cmd = None;
+
+        match self.dialog.as_mut().unwrap() {
+            Dialog::Pledge { giruda, pledge } => match code {
+                KeyCode::Left => *giruda = (*giruda + GIRUDA.len() - 1) % GIRUDA.len(),
+                KeyCode::Right => *giruda = (*giruda + 1) % GIRUDA.len(),
+                KeyCode::Up if *pledge < max => *pledge += 1,
+                KeyCode::Down if *pledge > min => *pledge -= 1,
+                KeyCode::Char('p') if is_election => cmd = Some(Command::Pledge(None)),
+                KeyCode::Enter if is_election => cmd = Some(Command::Pledge(Some((GIRUDA[*giruda], *pledge)))),
+                KeyCode::Enter => cmd = Some(Command::ChangePledge(GIRUDA[*giruda])),
+                _ => {}
+            },
+            Dialog::Friend {
+                drop,
+                funcs,
+                selected: None,
+            } => match code {
+                KeyCode::Left => self.card_cursor = self.card_cursor.saturating_sub(1),
+                KeyCode::Right if self.card_cursor + 1 < hand.len() => self.card_cursor += 1,
+                KeyCode::Char(' ') => {
+                    if let Some(card) = hand.get(self.card_cursor) {
+                        if let Some(i) = drop.iter().position(|c| c == card) {
+                            drop.remove(i);
+                        } else {
+                            drop.push(*card);
+                        }
+                    }
+                }
+                KeyCode::Enter if drop.len() == drop_cnt && !funcs.is_empty() => {
+                    if let Dialog::Friend { selected, .. } = self.dialog.as_mut().unwrap() {
+                        *selected = Some(0);
+                    }
+                }
+                KeyCode::Enter => self.message = format!("select {} cards to drop", drop_cnt),
+                _ => {}
+            },
+            Dialog::Friend {
+                drop,
+                funcs,
+                selected: Some(i),
+            } => match code {
+                KeyCode::Up => *i = i.saturating_sub(1),
+                KeyCode::Down if *i + 1 < funcs.len() => *i += 1,
+                KeyCode::Enter => cmd = Some(Command::SelectFriend(drop.clone(), funcs[*i].clone())),
+                _ => {}
+            },
+            Dialog::Go { cmds, selected } => match code {
+                KeyCode::Up => *selected = selected.saturating_sub(1),
+                KeyCode::Down if *selected + 1 < cmds.len() => *selected += 1,
+                KeyCode::Enter => cmd = Some(cmds[*selected].clone()),
+                _ => {}
+            },
+        }
+
+        match cmd {
+            Some(cmd) if self.is_valid(&cmd) => {
+                self.dialog = None;
+                vec![Action::Room(RoomUserToServer::Command(cmd))]
+            }
+            Some(_) => {
+                self.message = "not allowed now".to_owned();
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn play(&mut self) -> Vec<Action> {
+        let card = match self.hand().get(self.card_cursor) {
+            Some(card) => *card,
+            None => return Vec::new(),
+        };
+        let cmds = self
+            .legal_commands()
+            .into_iter()
+            .filter(|c| matches!(c, Command::Go(c, ..) if *c == card))
+            .collect::<Vec<_>>();
+        match cmds.len() {
+            0 => {
+                self.message = "can't play this card now".to_owned();
+                Vec::new()
+            }
+            1 => vec![Action::Room(RoomUserToServer::Command(cmds[0].clone()))],
+            _ => {
+                self.dialog = Some(Dialog::Go { cmds, selected: 0 });
+                Vec::new()
+            }
+        }
+    }
+
+    fn open_pledge(&mut self) {
+        let (giruda, pledge) = match (&self.state, &self.rule) {
+            (Some(State::Election { .. }), Some(rule)) if self.is_my_turn() => (0, rule.pledge.min),
+            (Some(State::SelectFriend { giruda, pledge, .. }), _) if self.is_my_turn() => {
+                (GIRUDA.iter().position(|g| g == giruda).unwrap(), *pledge)
+            }
+            _ => {
+                self.message = "can't pledge now".to_owned();
+                return;
+            }
+        };
+        self.dialog = Some(Dialog::Pledge { giruda, pledge });
+    }
+
+    fn open_friend(&mut self) {
+        let (rule, seat) = match (&self.state, &self.rule, self.seat()) {
+            (Some(State::SelectFriend { .. }), Some(rule), Some(seat)) if self.is_my_turn() => (rule, seat),
+            _ => {
+                self.message = "can't select friend now".to_owned();
+                return;
+            }
+        };
+        let hand = self.hand();
+        let mut funcs = rule
+            .deck
+            .0
+            .iter()
+            .filter(|c| !hand.contains(c))
+            .map(|c| FriendFunc::ByCard(*c))
+            .collect::<Vec<_>>();
+        funcs.push(FriendFunc::First);
+        funcs.push(FriendFunc::Last);
+        funcs.push(FriendFunc::None);
+        funcs.extend(
+            (0..rule.user_cnt as usize)
+                .filter(|i| *i != seat)
+                .map(FriendFunc::ByUser),
+        );
+        self.dialog = Some(Dialog::Friend {
+            drop: Vec::new(),
+            funcs,
+            selected: None,
+        });
+    }
+
+    fn leave(&mut self) -> Vec<Action> {
+        if matches!(self.room, Some(RoomInfo { is_game: true, .. })) && self.seat().is_some() {
+            self.message = "can't leave while playing".to_owned();
+            return Vec::new();
+        }
+        self.exit_room();
+        vec![Action::Main(MainToServer::Leave), Action::DisconnectRoom, refresh()]
+    }
+
+    fn exit_room(&mut self) {
+        self.screen = Screen::List;
+        self.room = None;
+        self.rule = None;
+        self.state = None;
+        self.summary = None;
+        self.dialog = None;
+        self.focus = Focus::Hand;
+    }
+
+    fn after_state_change(&mut self) {
+        let hand_len = self.hand().len();
+        if self.card_cursor >= hand_len {
+            self.card_cursor = hand_len.saturating_sub(1);
+        }
+        let keep = match (&self.dialog, &self.state) {
+            (Some(Dialog::Pledge { .. }), Some(State::Election { .. }))
+            | (Some(Dialog::Pledge { .. }), Some(State::SelectFriend { .. }))
+            | (Some(Dialog::Friend { .. }), Some(State::SelectFriend { .. }))
+            | (Some(Dialog::Go { .. }), Some(State::InGame { .. })) => self.is_my_turn(),
+            _ => false,
+        };
+        if !keep {
+            self.dialog = None;
+        }
+    }
+
+    fn clamp_room_cursor(&mut self) {
+        self.room_cursor = self.room_cursor.min(self.rooms.len().saturating_sub(1));
+    }
+}
+
+fn refresh() -> Action {
+    Action::List(ListToServer::GetRoomList { user_num: (0, 100) })
+}
+
+/// Parses the argument of `/spectate`, like `full`, `hidden` or `delay 2`.
+fn parse_spectator_view(s: &str) -> Option<SpectatorView> {
+    match s.split_once(' ').unwrap_or((s, "")) {
+        ("full", _) => Some(SpectatorView::Full),
+        ("hidden", _) => Some(SpectatorView::Hidden),
+        ("delay", tricks) => tricks.parse().ok().map(SpectatorView::Delayed),
+        _ => None,
+    }
+}
+
+/// Parses the argument of `/vote`, like `abort`, `pause`, `resume`, `undo` or `bot 3`.
+fn parse_vote(s: &str) -> Option<VoteKind> {
+    match s.split_once(' ').unwrap_or((s, "")) {
+        ("abort", _) => Some(VoteKind::Abort),
+        ("pause", _) => Some(VoteKind::Pause),
+        ("resume", _) => Some(VoteKind::Resume),
+        ("undo", _) => Some(VoteKind::Undo),
+        ("bot", seat) => seat.parse().ok().map(VoteKind::ReplaceWithBot),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mighty::prelude::Game;
+    use types::{RoomUid, RuleHash, VoteRule};
+
+    fn user(no: u32) -> UserInfo {
+        UserInfo {
+            no: UserNo(no),
+            id: format!("user{}", no),
+            name: format!("user{}", no),
+            email: String::new(),
+            rating: 0,
+            room: None,
+            is_admin: false,
+        }
+    }
+
+    fn room_info(rule: &Rule) -> RoomInfo {
+        RoomInfo {
+            uid: RoomUid::generate("test"),
+            id: RoomId(1),
+            name: "test".to_owned(),
+            rule: RuleHash::generate(rule),
+            is_rank: false,
+            head: UserNo(1),
+            user: (1..=rule.user_cnt as u32).map(UserNo).collect(),
+            standing: Vec::new(),
+            observer_cnt: 0,
+            is_game: true,
+            ghost: 0,
+            locked: 0,
+            swap: Vec::new(),
+            ready: 0,
+            auto_start: false,
+            bot_fill: false,
+            start_at: None,
+            has_password: false,
+            invite_only: false,
+            vote: None,
+            vote_rule: VoteRule::default(),
+            paused: false,
+            spectator: SpectatorPolicy::default(),
+            coach: Vec::new(),
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    /// App of user 1 sitting at seat 0, in the game which just started.
+    fn in_game() -> App {
+        let rule = Rule::from(Preset::Default5);
+        let mut app = App::new(user(1));
+        app.on_net(Net::Main(MainToClient::Joined(room_info(&rule))));
+        app.on_net(Net::Room(RoomUserToClient::Rule(rule.clone())));
+        app.on_net(Net::Room(RoomUserToClient::Game(Game::new(rule).get_state())));
+        app
+    }
+
+    #[test]
+    fn join_test() {
+        let mut app = App::new(user(1));
+        let actions = app.on_net(Net::List(ListToClient::RoomList(vec![RoomId(3)])));
+        assert!(matches!(
+            actions[..],
+            [Action::List(ListToServer::Subscribe(RoomId(3)))]
+        ));
+
+        let rule = Rule::from(Preset::Default5);
+        app.on_net(Net::List(ListToClient::Room(SimpleRoomInfo::from(room_info(&rule)))));
+        let actions = app.on_key(key(KeyCode::Enter));
+        assert!(matches!(actions[..], [Action::Main(MainToServer::Join(RoomId(1)))]));
+
+        app.on_net(Net::Main(MainToClient::UserInfo(user(1))));
+        assert_eq!(app.screen, Screen::List);
+        assert!(!app.message.is_empty());
+
+        let actions = app.on_net(Net::Main(MainToClient::Joined(room_info(&rule))));
+        assert!(matches!(actions[..], [Action::ConnectRoom]));
+        assert_eq!(app.screen, Screen::Room);
+        assert_eq!(app.seat(), Some(0));
+    }
+
+    #[test]
+    fn chat_test() {
+        let mut app = in_game();
+        app.on_key(key(KeyCode::Char('c')));
+        for c in "hi".chars() {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        let actions = app.on_key(key(KeyCode::Enter));
+        assert!(matches!(&actions[..], [Action::Room(RoomUserToServer::Chat(s))] if s == "hi"));
+        assert_eq!(app.focus, Focus::Hand);
+
+        app.on_key(key(KeyCode::Char('/')));
+        for c in "start".chars() {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        let actions = app.on_key(key(KeyCode::Enter));
+        assert!(matches!(actions[..], [Action::Room(RoomUserToServer::Start)]));
+
+        app.on_key(key(KeyCode::Char('/')));
+        for c in "swap 2".chars() {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        let actions = app.on_key(key(KeyCode::Enter));
+        assert!(matches!(actions[..], [Action::Room(RoomUserToServer::Swap(2))]));
+
+        app.on_net(Net::Room(RoomUserToClient::Chat("a".to_owned(), UserNo(2))));
+        app.on_net(Net::Room(RoomUserToClient::ChatLog(vec![("b".to_owned(), UserNo(3))])));
+        assert_eq!(app.chat, vec![(UserNo(3), "b".to_owned())]);
+    }
+
+    #[test]
+    fn parse_vote_test() {
+        assert_eq!(parse_vote("abort"), Some(VoteKind::Abort));
+        assert_eq!(parse_vote("bot 3"), Some(VoteKind::ReplaceWithBot(3)));
+        assert_eq!(parse_vote("bot"), None);
+        assert_eq!(parse_vote("skip"), None);
+        assert_eq!(parse_spectator_view("delay 2"), Some(SpectatorView::Delayed(2)));
+        assert_eq!(parse_spectator_view("delay"), None);
+    }
+
+    #[test]
+    fn kicked_test() {
+        let mut app = in_game();
+        let actions = app.on_net(Net::Room(RoomUserToClient::Kicked));
+        assert!(matches!(actions[..], [Action::DisconnectRoom, _]));
+        assert_eq!(app.screen, Screen::List);
+        assert!(app.room.is_none());
+    }
+
+    #[test]
+    fn pledge_dialog_test() {
+        let mut app = in_game();
+        assert_eq!(app.hand().len(), 10);
+        if !app.is_my_turn() {
+            app.on_key(key(KeyCode::Char('b')));
+            assert_eq!(app.dialog, None);
+            return;
+        }
+
+        app.on_key(key(KeyCode::Char('b')));
+        assert!(matches!(app.dialog, Some(Dialog::Pledge { giruda: 0, .. })));
+        app.on_key(key(KeyCode::Right));
+        app.on_key(key(KeyCode::Up));
+        let actions = app.on_key(key(KeyCode::Enter));
+        let min = app.rule.as_ref().unwrap().pledge.min;
+        assert!(matches!(
+            actions[..],
+            [Action::Room(RoomUserToServer::Command(Command::Pledge(Some((Some(Pattern::Diamond), p)))))]
+                if p == min + 1
+        ));
+        assert_eq!(app.dialog, None);
+    }
+
+    #[test]
+    fn legal_cards_test() {
+        let mut app = in_game();
+        let rule = app.rule.clone().unwrap();
+        let mut game = Game::new(rule.clone());
+        let mut agent = mighty::prelude::BasicAgent;
+
+        // let bots play until seat 0 should play a card
+        while !matches!(game.state, State::InGame { current_user: 0, .. }) {
+            let seat = (0..rule.user_cnt as usize)
+                .find(|i| waiting_users(&game.state, &rule) & (1 << i) > 0)
+                .unwrap();
+            let cmd = mighty::prelude::Agent::decide(&mut agent, seat, &game.state, &rule);
+            game.next(seat, cmd).unwrap();
+        }
+        app.on_net(Net::Room(RoomUserToClient::Game(game.get_state())));
+
+        let legal = app.legal_cards();
+        assert!(!legal.is_empty());
+        let idx = app.hand().iter().position(|c| legal.contains(c)).unwrap();
+        app.card_cursor = idx;
+        let actions = app.on_key(key(KeyCode::Enter));
+        assert!(
+            matches!(actions[..], [Action::Room(RoomUserToServer::Command(Command::Go(..)))])
+                || matches!(app.dialog, Some(Dialog::Go { .. }))
+        );
+    }
+}
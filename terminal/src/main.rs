@@ -0,0 +1,165 @@
+//! Terminal client for web-mighty.
+//!
+//! Connects to the server with `native` and draws the rooms and the game with `tui`.
+//!
+//! ```text
+//! mighty-tui --url http://localhost:8080 --id user
+//! ```
+
+mod app;
+mod ui;
+
+use crate::app::{Action, App, Net};
+use anyhow::Result;
+use clap::Clap;
+use crossterm::event::{self, Event};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use native::{Client, Receiver, Sender};
+use serde::de::DeserializeOwned;
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::JoinHandle;
+use tui::backend::CrosstermBackend;
+use tui::Terminal;
+use types::{MainToServer, RoomUserToServer};
+
+/// Interval of telling the server that the user is not absent.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clap)]
+#[clap(version = "0.1.0", about = "The Mighty Mighty Card Game in Terminal")]
+struct Opts {
+    #[clap(
+        short = 'u',
+        long = "url",
+        default_value = "http://localhost:8080",
+        about = "address of the server"
+    )]
+    url: String,
+    #[clap(short = 'i', long = "id", about = "user id")]
+    id: String,
+    #[clap(short = 'p', long = "password", about = "password (asked if not given)")]
+    password: Option<String>,
+}
+
+enum Input {
+    Key(event::KeyEvent),
+    Net(Box<Net>),
+    Resize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let password = match opts.password {
+        Some(password) => password,
+        None => {
+            print!("password: ");
+            io::stdout().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(&['\r', '\n'][..]).to_owned()
+        }
+    };
+
+    let client = Client::login(&opts.url, &opts.id, &password).await?;
+    let mut main = client.main().await?;
+    let user = client.user_info(&mut main).await?;
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let res = run(&mut terminal, client, main, App::new(user)).await;
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    res
+}
+
+async fn run(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    client: Client,
+    main: native::Connection<MainToServer, types::MainToClient>,
+    mut app: App,
+) -> Result<()> {
+    let (tx, mut rx) = unbounded_channel();
+
+    let key_tx = tx.clone();
+    std::thread::spawn(move || loop {
+        let input = match event::read() {
+            Ok(Event::Key(key)) => Input::Key(key),
+            Ok(Event::Resize(..)) => Input::Resize,
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+        if key_tx.send(input).is_err() {
+            break;
+        }
+    });
+
+    let (mut main_tx, main_rx) = main.split();
+    forward(main_rx, tx.clone(), Net::Main, "main");
+    let (mut list_tx, list_rx) = client.list().await?.split();
+    forward(list_rx, tx.clone(), Net::List, "list");
+    let mut room: Option<(Sender<RoomUserToServer>, JoinHandle<()>)> = None;
+    let mut last_update = Instant::now();
+
+    let mut actions = app.init();
+    loop {
+        for action in actions.drain(..) {
+            match action {
+                Action::Main(msg) => main_tx.send(&msg).await?,
+                Action::List(msg) => list_tx.send(&msg).await?,
+                Action::Room(msg) => {
+                    if let Some((room_tx, _)) = &mut room {
+                        room_tx.send(&msg).await?;
+                    }
+                }
+                Action::ConnectRoom => {
+                    let (room_tx, room_rx) = client.room().await?.split();
+                    room = Some((room_tx, forward(room_rx, tx.clone(), Net::Room, "room")));
+                }
+                Action::DisconnectRoom => {
+                    if let Some((mut room_tx, handle)) = room.take() {
+                        handle.abort();
+                        let _ = room_tx.close().await;
+                    }
+                }
+                Action::Quit => return Ok(()),
+            }
+        }
+
+        terminal.draw(|f| ui::draw(f, &app))?;
+
+        actions = match rx.recv().await {
+            Some(Input::Key(key)) => {
+                if last_update.elapsed() >= UPDATE_INTERVAL {
+                    last_update = Instant::now();
+                    main_tx.send(&MainToServer::Update).await?;
+                }
+                app.on_key(key)
+            }
+            Some(Input::Net(msg)) => app.on_net(*msg),
+            Some(Input::Resize) => Vec::new(),
+            None => return Ok(()),
+        };
+    }
+}
+
+/// Passes every message of `rx` to the app until the connection is closed.
+fn forward<R, F>(mut rx: Receiver<R>, tx: UnboundedSender<Input>, f: F, name: &'static str) -> JoinHandle<()>
+where
+    R: DeserializeOwned + Send + 'static,
+    F: Fn(R) -> Net + Send + 'static,
+{
+    tokio::spawn(async move {
+        while let Ok(Some(msg)) = rx.recv().await {
+            if tx.send(Input::Net(Box::new(f(msg)))).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(Input::Net(Box::new(Net::Closed(name))));
+    })
+}
@@ -0,0 +1,548 @@
+use crate::app::{App, Dialog, Focus, Screen, GIRUDA};
+use mighty::prelude::{Card, Command, FriendFunc, Pattern, Rush, State};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tui::backend::Backend;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use tui::Frame;
+use types::VoteKind;
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(f.size());
+
+    match app.screen {
+        Screen::List => draw_list(f, app, chunks[0]),
+        Screen::Room => draw_room(f, app, chunks[0]),
+    }
+
+    let help = match (app.screen, app.focus, &app.dialog) {
+        (Screen::List, ..) => "↑↓ select  enter join  n new room  r refresh  q quit",
+        (Screen::Room, Focus::Chat, _) => {
+            "enter send  esc cancel  /start  /leave  /name <name>  /ready  /sit <n>  /stand  /swap <n>  /lock <n>  /kick <no>"
+        }
+        (Screen::Room, _, Some(Dialog::Pledge { .. })) => "←→ giruda  ↑↓ pledge  enter ok  p pass  esc close",
+        (Screen::Room, _, Some(Dialog::Friend { selected: None, .. })) => "←→ card  space drop  enter next  esc close",
+        (Screen::Room, _, Some(_)) => "↑↓ select  enter ok  esc back",
+        (Screen::Room, ..) => "←→ card  enter play  b pledge  f friend  s start  c chat  l leave  q quit",
+    };
+    let status = Spans::from(vec![
+        Span::styled(&app.message, Style::default().fg(Color::Yellow)),
+        Span::raw(if app.message.is_empty() { "" } else { "  " }),
+        Span::styled(help, Style::default().fg(Color::DarkGray)),
+    ]);
+    f.render_widget(Paragraph::new(status), chunks[1]);
+}
+
+fn draw_list<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let items = app
+        .rooms
+        .values()
+        .map(|r| {
+            let mut spans = vec![
+                Span::styled(format!("{:>5} ", r.id.0), Style::default().fg(Color::DarkGray)),
+                Span::raw(format!("{:<24} ", r.name)),
+                Span::raw(format!("{} users  {} observers", r.user_cnt, r.observer_cnt)),
+            ];
+            if r.is_rank {
+                spans.push(Span::styled("  rank", Style::default().fg(Color::Cyan)));
+            }
+            if r.is_game {
+                spans.push(Span::styled("  playing", Style::default().fg(Color::Green)));
+            }
+            if r.is_private {
+                spans.push(Span::styled("  private", Style::default().fg(Color::Yellow)));
+            }
+            ListItem::new(Spans::from(spans))
+        })
+        .collect::<Vec<_>>();
+    let title = format!(" rooms - {} ({}) ", app.user.name, app.user.rating);
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default();
+    if !app.rooms.is_empty() {
+        state.select(Some(app.room_cursor));
+    }
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_room<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let user_cnt = app.room.as_ref().map(|r| r.user.len()).unwrap_or(0) as u16;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(user_cnt + 3),
+            Constraint::Min(5),
+            Constraint::Length(3),
+            Constraint::Length(3),
+        ])
+        .split(area);
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+
+    draw_seats(f, app, chunks[0]);
+    draw_table(f, app, middle[0]);
+    draw_chat(f, app, middle[1]);
+    draw_hand(f, app, chunks[2]);
+
+    let input_style = if app.focus == Focus::Chat {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let input = Paragraph::new(app.input.as_str()).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" chat ")
+            .style(input_style),
+    );
+    f.render_widget(input, chunks[3]);
+    if app.focus == Focus::Chat {
+        f.set_cursor(chunks[3].x + 1 + app.input.chars().count() as u16, chunks[3].y + 1);
+    }
+
+    if let Some(dialog) = &app.dialog {
+        draw_dialog(f, app, dialog, area);
+    }
+}
+
+fn draw_seats<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let room = match &app.room {
+        Some(room) => room,
+        None => return,
+    };
+    let (president, friend) = match &app.state {
+        Some(State::SelectFriend { president, .. }) => (Some(*president), None),
+        Some(State::InGame {
+            president,
+            friend,
+            is_friend_known,
+            ..
+        }) => (
+            Some(*president),
+            friend.filter(|f| *is_friend_known || Some(*f) == app.seat()),
+        ),
+        Some(State::GameEnded { president, friend, .. }) => (Some(*president), *friend),
+        _ => (None, None),
+    };
+    let waiting = match (&app.state, &app.rule) {
+        (Some(state), Some(rule)) if !matches!(state, State::GameEnded { .. }) => {
+            mighty::bot::waiting_users(state, rule)
+        }
+        _ => 0,
+    };
+    let points = match &app.state {
+        Some(State::InGame { score_deck, .. }) => score_deck.iter().map(|d| d.len()).collect(),
+        _ => vec![0; room.user.len()],
+    };
+
+    let mut lines = vec![Spans::from(vec![
+        Span::styled(format!("{} ", room.name), Style::default().add_modifier(Modifier::BOLD)),
+        Span::styled(format!("#{}", room.id.0), Style::default().fg(Color::DarkGray)),
+        Span::raw(if room.is_game { "  playing" } else { "  waiting" }),
+        Span::styled(
+            match (room.auto_start, room.bot_fill) {
+                (true, true) => "  auto start, bot fill",
+                (true, false) => "  auto start",
+                (false, true) => "  bot fill",
+                (false, false) => "",
+            },
+            Style::default().fg(Color::DarkGray),
+        ),
+    ])];
+    if room.paused {
+        lines.push(Spans::from(Span::styled(
+            "  paused",
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    if let Some(vote) = &room.vote {
+        let kind = match vote.kind {
+            VoteKind::Abort => "abort".to_owned(),
+            VoteKind::Pause => "pause".to_owned(),
+            VoteKind::Resume => "resume".to_owned(),
+            VoteKind::ReplaceWithBot(seat) => format!("bot for seat {}", seat),
+            VoteKind::Undo => "undo".to_owned(),
+        };
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "  vote {} by #{}: {} yes, {} no  (/yes /no)",
+                kind,
+                vote.proposer.0,
+                vote.yes.count_ones(),
+                vote.no.count_ones()
+            ),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    if let Some(start_at) = room.start_at {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        lines.push(Spans::from(Span::styled(
+            format!("  starting in {}s", start_at.saturating_sub(now) / 1000 + 1),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
+    for (seat, user) in room.user.iter().enumerate() {
+        let mut spans = vec![Span::raw(if waiting & (1 << seat) > 0 { "▶ " } else { "  " })];
+        if user.0 == 0 {
+            spans.push(Span::styled("(empty)", Style::default().fg(Color::DarkGray)));
+        } else {
+            spans.push(Span::raw(format!("#{}", user.0)));
+        }
+        if Some(seat) == app.seat() {
+            spans.push(Span::styled(" (you)", Style::default().fg(Color::Cyan)));
+        }
+        if !room.is_game && room.ready & (1 << seat) > 0 {
+            spans.push(Span::styled(" [ready]", Style::default().fg(Color::Green)));
+        }
+        if room.locked & (1 << seat) > 0 {
+            spans.push(Span::styled(" [locked]", Style::default().fg(Color::DarkGray)));
+        }
+        if room.swap.iter().any(|(a, b)| *b == app.user.no && a == user) {
+            spans.push(Span::styled(" [wants swap]", Style::default().fg(Color::Yellow)));
+        }
+        if room.ghost & (1 << seat) > 0 {
+            spans.push(Span::styled(" [bot]", Style::default().fg(Color::DarkGray)));
+        }
+        if *user == room.head {
+            spans.push(Span::styled(" [head]", Style::default().fg(Color::DarkGray)));
+        }
+        if Some(seat) == president {
+            spans.push(Span::styled(" [president]", Style::default().fg(Color::Magenta)));
+        }
+        if Some(seat) == friend {
+            spans.push(Span::styled(" [friend]", Style::default().fg(Color::Magenta)));
+        }
+        if points[seat] > 0 {
+            spans.push(Span::raw(format!("  {} pts", points[seat])));
+        }
+        lines.push(Spans::from(spans));
+    }
+    if !room.standing.is_empty() {
+        let standing = room.standing.iter().map(|x| format!("#{}", x.0)).collect::<Vec<_>>();
+        lines.push(Spans::from(Span::styled(
+            format!("  standing: {}", standing.join(", ")),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+    f.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" seats ")),
+        area,
+    );
+}
+
+fn draw_table<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let mut lines = Vec::new();
+    match &app.state {
+        None => lines.push(Spans::from("waiting for the game to start")),
+        Some(State::Election { pledge, .. }) => {
+            lines.push(Spans::from("election"));
+            for (seat, p) in pledge.iter().enumerate() {
+                let text = match p {
+                    Some((g, n)) => format!("{}: {} {}", seat, giruda_str(*g), n),
+                    None => format!("{}: -", seat),
+                };
+                lines.push(Spans::from(text));
+            }
+        }
+        Some(State::SelectFriend { giruda, pledge, .. }) => {
+            lines.push(Spans::from("president is selecting friend"));
+            lines.push(Spans::from(format!("pledge: {} {}", giruda_str(*giruda), pledge)));
+        }
+        Some(State::InGame {
+            giruda,
+            pledge,
+            friend_func,
+            placed_cards,
+            start_user,
+            current_user,
+            turn_count,
+            ..
+        }) => {
+            let user_cnt = placed_cards.len();
+            lines.push(Spans::from(format!(
+                "turn {}  pledge: {} {}  friend: {}",
+                turn_count + 1,
+                giruda_str(*giruda),
+                pledge,
+                friend_func_str(friend_func)
+            )));
+            for seat in (0..user_cnt)
+                .map(|i| (start_user + i) % user_cnt)
+                .take_while(|i| i != current_user)
+            {
+                lines.push(Spans::from(vec![
+                    Span::raw(format!("{}: ", seat)),
+                    card_span(placed_cards[seat].0),
+                ]));
+            }
+        }
+        Some(State::GameEnded {
+            winner,
+            president,
+            score,
+            pledge,
+            ..
+        }) => {
+            let won = winner & (1 << president) > 0;
+            lines.push(Spans::from(format!(
+                "game ended: ruling party {} with {} / {}",
+                if won { "won" } else { "lost" },
+                score,
+                pledge
+            )));
+            if let Some(seat) = app.seat() {
+                lines.push(Spans::from(if winner & (1 << seat) > 0 {
+                    "you won"
+                } else {
+                    "you lost"
+                }));
+            }
+            if let Some(summary) = &app.summary {
+                if !summary.game.multipliers.is_empty() {
+                    lines.push(Spans::from(format!("multipliers: {:?}", summary.game.multipliers)));
+                }
+                for (seat, points) in summary.game.points.iter().enumerate() {
+                    lines.push(Spans::from(format!(
+                        "{}: {} points, rating {:+}",
+                        seat,
+                        points.len(),
+                        summary.rating.get(seat).copied().unwrap_or(0)
+                    )));
+                }
+            }
+        }
+    }
+    f.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" table ")),
+        area,
+    );
+}
+
+fn draw_chat<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let height = area.height.saturating_sub(2) as usize;
+    let lines = app.chat[app.chat.len().saturating_sub(height)..]
+        .iter()
+        .map(|(user, chat)| {
+            Spans::from(vec![
+                Span::styled(format!("#{}: ", user.0), Style::default().fg(Color::DarkGray)),
+                Span::raw(chat.as_str()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    f.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title(" chat ")),
+        area,
+    );
+}
+
+fn draw_hand<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let legal = app.legal_cards();
+    let drop = match &app.dialog {
+        Some(Dialog::Friend { drop, .. }) => drop.clone(),
+        _ => Vec::new(),
+    };
+    let mut spans = Vec::new();
+    for (i, card) in app.hand().into_iter().enumerate() {
+        let mut span = card_span(card);
+        if !legal.is_empty() && !legal.contains(&card) {
+            span.style = span.style.add_modifier(Modifier::DIM);
+        }
+        if legal.contains(&card) {
+            span.style = span.style.add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        }
+        if drop.contains(&card) {
+            span.style = span.style.add_modifier(Modifier::CROSSED_OUT);
+        }
+        if i == app.card_cursor && app.focus == Focus::Hand {
+            span.style = span.style.add_modifier(Modifier::REVERSED);
+        }
+        spans.push(span);
+        spans.push(Span::raw(" "));
+    }
+    let title = if app.is_my_turn() {
+        " hand - your turn "
+    } else {
+        " hand "
+    };
+    f.render_widget(
+        Paragraph::new(Spans::from(spans)).block(Block::default().borders(Borders::ALL).title(title)),
+        area,
+    );
+}
+
+fn draw_dialog<B: Backend>(f: &mut Frame<B>, app: &App, dialog: &Dialog, area: Rect) {
+    let (title, lines) = match dialog {
+        Dialog::Pledge { giruda, pledge } => {
+            let mut spans = Vec::new();
+            for (i, g) in GIRUDA.iter().enumerate() {
+                let style = if i == *giruda {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                spans.push(Span::styled(format!(" {} ", giruda_str(*g)), style));
+            }
+            let lines = match &app.state {
+                Some(State::Election { .. }) => vec![Spans::from(spans), Spans::from(format!("pledge: {}", pledge))],
+                _ => vec![Spans::from(spans)],
+            };
+            (" pledge ", lines)
+        }
+        Dialog::Friend {
+            drop, selected: None, ..
+        } => {
+            let text = format!("select {} cards to drop ({} selected)", app.drop_cnt(), drop.len());
+            (" drop cards ", vec![Spans::from(text)])
+        }
+        Dialog::Friend {
+            drop,
+            funcs,
+            selected: Some(selected),
+        } => {
+            let lines = list_lines(
+                funcs.iter().map(|func| {
+                    let cmd = Command::SelectFriend(drop.clone(), func.clone());
+                    (friend_func_str(func), app.is_valid(&cmd))
+                }),
+                *selected,
+                area.height.saturating_sub(6) as usize,
+            );
+            (" select friend ", lines)
+        }
+        Dialog::Go { cmds, selected } => {
+            let lines = list_lines(cmds.iter().map(|c| (go_str(c), true)), *selected, cmds.len());
+            (" play ", lines)
+        }
+    };
+
+    let width = 40.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let rect = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    f.render_widget(Clear, rect);
+    f.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        rect,
+    );
+}
+
+/// Lines of the list which scrolls to show `selected`. Invalid items are dimmed.
+fn list_lines<I: Iterator<Item = (String, bool)>>(items: I, selected: usize, height: usize) -> Vec<Spans<'static>> {
+    let height = height.max(1);
+    let skip = (selected + 1).saturating_sub(height);
+    items
+        .enumerate()
+        .skip(skip)
+        .take(height)
+        .map(|(i, (text, valid))| {
+            let mut style = Style::default();
+            if !valid {
+                style = style.add_modifier(Modifier::DIM);
+            }
+            if i == selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            Spans::from(Span::styled(text, style))
+        })
+        .collect()
+}
+
+fn pattern_str(p: Pattern) -> &'static str {
+    match p {
+        Pattern::Spade => "♠",
+        Pattern::Diamond => "♦",
+        Pattern::Heart => "♥",
+        Pattern::Clover => "♣",
+    }
+}
+
+fn giruda_str(giruda: Option<Pattern>) -> &'static str {
+    giruda.map(pattern_str).unwrap_or("no giruda")
+}
+
+pub fn card_str(card: Card) -> String {
+    match card {
+        Card::Normal(p, n) => {
+            let rank = match n {
+                11 => "J".to_owned(),
+                12 => "Q".to_owned(),
+                13 => "K".to_owned(),
+                14 => "A".to_owned(),
+                n => n.to_string(),
+            };
+            format!("{}{}", pattern_str(p), rank)
+        }
+        Card::Joker(mighty::prelude::Color::Black) => "JK(b)".to_owned(),
+        Card::Joker(mighty::prelude::Color::Red) => "JK(r)".to_owned(),
+    }
+}
+
+fn card_span(card: Card) -> Span<'static> {
+    let color = match card {
+        Card::Normal(Pattern::Diamond, _) | Card::Normal(Pattern::Heart, _) => Color::Red,
+        Card::Joker(mighty::prelude::Color::Red) => Color::LightRed,
+        Card::Joker(_) => Color::LightBlue,
+        _ => Color::Reset,
+    };
+    Span::styled(card_str(card), Style::default().fg(color))
+}
+
+fn friend_func_str(func: &FriendFunc) -> String {
+    match func {
+        FriendFunc::None => "no friend".to_owned(),
+        FriendFunc::ByCard(card) => format!("owner of {}", card_str(*card)),
+        FriendFunc::ByUser(seat) => format!("seat {}", seat),
+        FriendFunc::First => "winner of first trick".to_owned(),
+        FriendFunc::Last => "winner of last trick".to_owned(),
+    }
+}
+
+fn go_str(cmd: &Command) -> String {
+    match cmd {
+        Command::Go(card, rush, call) => {
+            let mut text = card_str(*card);
+            if card.is_joker() {
+                let pattern = [Pattern::Spade, Pattern::Diamond, Pattern::Heart, Pattern::Clover]
+                    .iter()
+                    .find(|p| Rush::from(**p) == *rush)
+                    .map(|p| pattern_str(*p))
+                    .unwrap_or("-");
+                text += &format!(" as {}", pattern);
+            }
+            if *call {
+                text += " with joker call";
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn card_str_test() {
+        assert_eq!(card_str(Card::Normal(Pattern::Spade, 14)), "♠A");
+        assert_eq!(card_str(Card::Normal(Pattern::Heart, 10)), "♥10");
+        assert_eq!(card_str(Card::Joker(mighty::prelude::Color::Red)), "JK(r)");
+    }
+}
@@ -1,15 +1,16 @@
 #![cfg(not(tarpaulin_include))]
 
 use bitflags::bitflags;
-use mighty::prelude::{Command, Rule, State};
+use mighty::prelude::{Command, Preset, Rule, State, Summary};
 use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "client", feature = "server"))]
+use sha2::{Digest, Sha256};
 use std::fmt::{self, Display};
 #[cfg(feature = "client")]
 use wasm_bindgen::prelude::*;
 #[cfg(feature = "server")]
 use {
     actix::prelude::*,
-    sha2::{Digest, Sha256},
     std::str::FromStr,
     std::time::{SystemTime, UNIX_EPOCH},
 };
@@ -75,7 +76,7 @@ impl CopyableHash {
         ]
     }
 
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     fn from_vec(data: Vec<u8>) -> CopyableHash {
         CopyableHash(
             (data[0] as u64) << 56
@@ -113,7 +114,7 @@ impl CopyableHash {
         )
     }
 
-    #[cfg(feature = "server")]
+    #[cfg(any(feature = "client", feature = "server"))]
     pub fn generate<S: AsRef<str>>(s: S) -> CopyableHash {
         let mut hasher = Sha256::new();
         hasher.update(s.as_ref().as_bytes());
@@ -143,12 +144,15 @@ impl Display for RoomUid {
     }
 }
 
-#[cfg(feature = "server")]
+#[cfg(any(feature = "client", feature = "server"))]
 impl RoomUid {
     pub fn generate<S: AsRef<str>>(s: S) -> RoomUid {
         RoomUid(CopyableHash::generate(s))
     }
+}
 
+#[cfg(feature = "server")]
+impl RoomUid {
     pub fn generate_random() -> RoomUid {
         RoomUid::generate(format!(
             "room-{}-{}",
@@ -161,7 +165,7 @@ impl RoomUid {
 /// Short 6-digit room id during room is alive.
 /// If room is removed, then this id is useless and can be representing other room.
 #[cfg_attr(feature = "client", wasm_bindgen)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(MessageResponse))]
 pub struct RoomId(pub u32);
 
@@ -259,7 +263,7 @@ impl Display for RuleHash {
     }
 }
 
-#[cfg(feature = "server")]
+#[cfg(any(feature = "client", feature = "server"))]
 impl RuleHash {
     pub fn generate(rule: &Rule) -> RuleHash {
         RuleHash(CopyableHash::generate(serde_json::to_string(rule).unwrap()))
@@ -275,8 +279,23 @@ impl RuleHash {
 /// - `is_rank`: if this room is rank
 /// - `head`: head of this room
 /// - `user`: user list who plays game
+/// - `standing`: users in the room who are not seated, watching the game like observers
 /// - `observer`: observer list
 /// - `is_game`: if room is on gaming
+/// - `ghost`: bitmask of seats played by bot while the user is away
+/// - `locked`: bitmask of seats locked by the head
+/// - `swap`: pending swap requests as (requester, target)
+/// - `ready`: bitmask of seats which are ready to start
+/// - `auto_start`: if the game starts automatically after countdown when everyone is ready
+/// - `bot_fill`: if empty seats are played by bot (only in casual rooms)
+/// - `start_at`: time when the game starts in milliseconds since the unix epoch, while counting down
+/// - `has_password`: if password is needed to join
+/// - `invite_only`: if invite token is needed to join
+/// - `vote`: vote going on in the game
+/// - `vote_rule`: thresholds and timeout of votes
+/// - `paused`: if the game is paused
+/// - `spectator`: what observers can see
+/// - `coach`: observers who can see the hand of the user as (observer, user)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Message, MessageResponse))]
 #[cfg_attr(feature = "server", rtype(result = "()"))]
@@ -288,8 +307,191 @@ pub struct RoomInfo {
     pub is_rank: bool,
     pub head: UserNo,
     pub user: Vec<UserNo>,
+    pub standing: Vec<UserNo>,
     pub observer_cnt: usize,
     pub is_game: bool,
+    pub ghost: u8,
+    pub locked: u8,
+    pub swap: Vec<(UserNo, UserNo)>,
+    pub ready: u8,
+    pub auto_start: bool,
+    pub bot_fill: bool,
+    pub start_at: Option<u64>,
+    pub has_password: bool,
+    pub invite_only: bool,
+    pub vote: Option<Vote>,
+    pub vote_rule: VoteRule,
+    pub paused: bool,
+    pub spectator: SpectatorPolicy,
+    pub coach: Vec<(UserNo, UserNo)>,
+}
+
+impl RoomInfo {
+    /// New room with `user_cnt` empty seats and default settings.
+    pub fn new(uid: RoomUid, id: RoomId, name: String, rule: RuleHash, is_rank: bool, user_cnt: usize) -> RoomInfo {
+        RoomInfo {
+            uid,
+            id,
+            name,
+            rule,
+            is_rank,
+            head: UserNo(0),
+            user: vec![UserNo(0); user_cnt],
+            standing: Vec::new(),
+            observer_cnt: 0,
+            is_game: false,
+            ghost: 0,
+            locked: 0,
+            swap: Vec::new(),
+            ready: 0,
+            auto_start: false,
+            bot_fill: false,
+            start_at: None,
+            has_password: false,
+            invite_only: false,
+            vote: None,
+            vote_rule: VoteRule::default(),
+            paused: false,
+            spectator: SpectatorPolicy::default(),
+            coach: Vec::new(),
+        }
+    }
+}
+
+/// What observers see during the game
+///
+/// - `Full`: every hand
+/// - `Hidden`: public information only
+/// - `Delayed`: every hand, but the given number of tricks behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpectatorView {
+    Full,
+    Hidden,
+    Delayed(u8),
+}
+
+/// Spectator policy of the room
+///
+/// - `view`: what observers see during the game
+/// - `reveal`: if observers get every state of the game after it ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpectatorPolicy {
+    pub view: SpectatorView,
+    pub reveal: bool,
+}
+
+impl Default for SpectatorPolicy {
+    fn default() -> Self {
+        SpectatorPolicy {
+            view: SpectatorView::Hidden,
+            reveal: true,
+        }
+    }
+}
+
+/// Kind of vote in the game
+///
+/// - `Abort`: abort the game without rating change
+/// - `Pause`: pause the game and freeze the clock
+/// - `Resume`: resume the paused game
+/// - `ReplaceWithBot`: let bot play the seat of absent user
+/// - `Undo`: take back the last command of the proposer (only in casual rooms, everyone should agree)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VoteKind {
+    Abort,
+    Pause,
+    Resume,
+    ReplaceWithBot(usize),
+    Undo,
+}
+
+/// Progress of the vote
+///
+/// - `kind`: what is voted for
+/// - `proposer`: user who proposed the vote
+/// - `yes`: bitmask of seats voted for
+/// - `no`: bitmask of seats voted against
+/// - `end_at`: time when the vote fails in milliseconds since the unix epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    pub kind: VoteKind,
+    pub proposer: UserNo,
+    pub yes: u8,
+    pub no: u8,
+    pub end_at: u64,
+}
+
+/// Rule of votes in the room
+///
+/// - `abort`: percentage of voters needed to abort
+/// - `pause`: percentage of voters needed to pause or resume
+/// - `replace`: percentage of voters needed to replace the user with bot
+/// - `timeout`: seconds until the vote fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteRule {
+    pub abort: u8,
+    pub pause: u8,
+    pub replace: u8,
+    pub timeout: u32,
+}
+
+impl Default for VoteRule {
+    fn default() -> Self {
+        VoteRule {
+            abort: 75,
+            pause: 50,
+            replace: 50,
+            timeout: 30,
+        }
+    }
+}
+
+impl VoteRule {
+    /// Percentage of voters needed for the vote to pass
+    pub fn threshold(&self, kind: VoteKind) -> u8 {
+        match kind {
+            VoteKind::Abort => self.abort,
+            VoteKind::Pause | VoteKind::Resume => self.pause,
+            VoteKind::ReplaceWithBot(_) => self.replace,
+            VoteKind::Undo => 100,
+        }
+    }
+}
+
+/// Key to join the private room
+///
+/// - `Password`: password set by the head
+/// - `Invite`: invite token made by the head
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RoomKey {
+    Password(String),
+    Invite(String),
+}
+
+/// Clock of the game in milliseconds since the unix epoch
+///
+/// - `now`: time of the server when this is sent
+/// - `game_start`: time when the game started
+/// - `turn_start`: time when the current state of the game started, excluding the paused time
+/// - `paused_at`: time when the game is paused if it is paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clock {
+    pub now: u64,
+    pub game_start: u64,
+    pub turn_start: u64,
+    pub paused_at: Option<u64>,
+}
+
+/// Summary of the game which just ended
+///
+/// - `game`: Hands, point cards, multipliers and the friend of the game
+/// - `user`: Users of each seat, 0 for bot
+/// - `rating`: Rating change of each seat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub game: Summary,
+    pub user: Vec<UserNo>,
+    pub rating: Vec<i32>,
 }
 
 /// Simplified information of room for in the list
@@ -298,9 +500,10 @@ pub struct RoomInfo {
 /// - `name`: name of room
 /// - `rule_name`: name of rule
 /// - `is_rank`: if this room is rank
-/// - `user_cnt`: count of users
+/// - `user_cnt`: count of seated users
 /// - `observer_cnt`: count of observers
 /// - `is_game`: if this room is on gaming
+/// - `is_private`: if password or invite token is needed to join
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "server", derive(Message, MessageResponse))]
 #[cfg_attr(feature = "server", rtype(result = "()"))]
@@ -312,6 +515,7 @@ pub struct SimpleRoomInfo {
     pub user_cnt: usize,
     pub observer_cnt: usize,
     pub is_game: bool,
+    pub is_private: bool,
 }
 
 impl From<RoomInfo> for SimpleRoomInfo {
@@ -321,9 +525,10 @@ impl From<RoomInfo> for SimpleRoomInfo {
             name: info.name,
             rule: info.rule,
             is_rank: info.is_rank,
-            user_cnt: info.user.len(),
+            user_cnt: info.user.iter().filter(|x| x.0 != 0).count(),
             observer_cnt: info.observer_cnt,
             is_game: info.is_game,
+            is_private: info.has_password || info.invite_only,
         }
     }
 }
@@ -388,65 +593,127 @@ pub enum ListToServer {
     GetRoomList { user_num: (u32, u32) },
 }
 
+/// Status of the user waiting in the matchmaking queue
+///
+/// - `waiting`: users waiting for the same preset
+/// - `estimate`: estimated seconds to wait, if enough games were matched recently
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QueueStatus {
+    pub preset: Preset,
+    pub waiting: usize,
+    pub estimate: Option<u64>,
+}
+
 /// Websocket message for main connection to client
 ///
 /// - `UserStatus`: Sends the status of user
 /// - `UserInfo`: Sends the information of user
+/// - `Joined`: Sends the information of room when joined to the room
+/// - `Queued`: Sends the status of the matchmaking queue
+/// - `QueueCancelled`: When the user is removed from the queue, with the reason
+/// - `Matched`: When the ranked room is made for the user, before joining to it
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(Message))]
 #[cfg_attr(feature = "server", rtype(result = "()"))]
 pub enum MainToClient {
     UserStatus(UserNo, UserStatus),
     UserInfo(UserInfo),
+    Joined(RoomInfo),
+    Queued(QueueStatus),
+    QueueCancelled(String),
+    Matched(RoomId),
 }
 
 /// Websocket message for main connection to server
 ///
 /// - `Subscribe`: Subscribe for changes of user state
 /// - `Unsubscribe`: Unsubscribe for changes of user state
-/// - `GetInfo`: Request user info
 /// - `Update`: Check for user movement
+/// - `GetInfo`: Request info of this user
+/// - `Join`: Join to the room
+/// - `JoinWith`: Join to the private room with password or invite token
+/// - `MakeRoom`: Make room with name, rule and whether it is rank game, then join to it
+/// - `Leave`: Leave the joined room
+/// - `Queue`: Wait for a ranked game of the preset, with the range of the rating of the other users
+/// - `CancelQueue`: Stop waiting for a ranked game
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum MainToServer {
     Subscribe(UserNo),
     Unsubscribe(UserNo),
     Update,
+    GetInfo,
+    Join(RoomId),
+    JoinWith(RoomId, RoomKey),
+    MakeRoom(String, Box<Rule>, bool),
+    Leave,
+    Queue(Preset, Option<(u32, u32)>),
+    CancelQueue,
 }
 
 /// Websocket message for observer connection to client
 ///
 /// - `Room`: Information of room
+/// - `Rule`: Rule of room
 /// - `Game`: Information of game
+/// - `Clock`: Clock of the game
 /// - `Chat`: For receiving chats
+/// - `ChatLog`: Recent chats of the room, replacing the ones client has
+/// - `Reveal`: Every state of the game which just ended
+/// - `Summary`: Summary of the game which just ended
+/// - `Maintenance`: Server is going down for maintenance, and the room will be restored after it
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(Message))]
 #[cfg_attr(feature = "server", rtype(result = "()"))]
 pub enum ObserveToClient {
     Room(RoomInfo),
+    Rule(Rule),
     Game(State),
+    Clock(Clock),
     Chat(String, UserNo),
+    ChatLog(Vec<(String, UserNo)>),
+    Reveal(Vec<State>),
+    Summary(GameSummary),
+    Maintenance,
 }
 
 /// Websocket message for observer connection to server
 ///
 /// - `Chat`: When observer chat
+/// - `Resync`: Request for the whole state of the room again
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ObserveToServer {
     Chat(String),
+    Resync,
 }
 
 /// Websocket message for room connection to client
 ///
 /// - `Room`: Information of room
+/// - `Rule`: Rule of room
 /// - `Game`: Information of game
+/// - `Clock`: Clock of the game
 /// - `Chat`: For receiving chats
+/// - `ChatLog`: Recent chats of the room, replacing the ones client has
+/// - `Invite`: Invite token of the room
+/// - `Kicked`: When the user is kicked from the room
+/// - `Reveal`: Every state of the game which just ended
+/// - `Summary`: Summary of the game which just ended
+/// - `Maintenance`: Server is going down for maintenance, and the room will be restored after it
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[cfg_attr(feature = "server", derive(Message))]
 #[cfg_attr(feature = "server", rtype(result = "()"))]
 pub enum RoomUserToClient {
     Room(RoomInfo),
+    Rule(Rule),
     Game(State),
+    Clock(Clock),
     Chat(String, UserNo),
+    ChatLog(Vec<(String, UserNo)>),
+    Invite(String),
+    Kicked,
+    Reveal(Vec<State>),
+    Summary(GameSummary),
+    Maintenance,
 }
 
 /// Websocket message for room connection to server
@@ -455,6 +722,25 @@ pub enum RoomUserToClient {
 /// - `ChangeName`: Change the name of the room
 /// - `ChangeRule`: Change the rule of the room
 /// - `Command`: Command for next move
+/// - `Chat`: Chat to the room
+/// - `Resync`: Request for the whole state of the room again
+/// - `Sit`: Take the empty seat
+/// - `Stand`: Stand up from the seat and watch the game
+/// - `Swap`: Request to swap seat with the user in the seat, or accept the request
+/// - `Lock`: Lock or unlock the seat (head only)
+/// - `Ready`: Set if the user is ready to start
+/// - `SetAutoStart`: Set if the game starts automatically when everyone is ready (head only)
+/// - `SetBotFill`: Set if empty seats are played by bot (head only, casual rooms only)
+/// - `Kick`: Kick the user and ban from rejoining for a while (head only)
+/// - `TransferHead`: Give the head to other user in the room (head only)
+/// - `SetPassword`: Set or remove the password of the room (head only)
+/// - `SetInviteOnly`: Set if invite token is needed to join (head only)
+/// - `Invite`: Request for invite token (head only)
+/// - `Propose`: Propose the vote in the game
+/// - `Vote`: Vote for or against the vote going on
+/// - `SetVoteRule`: Set thresholds and timeout of votes (head only)
+/// - `SetSpectatorPolicy`: Set what observers can see (head only)
+/// - `Coach`: Allow or disallow the observer to see the hand of the user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RoomUserToServer {
     Start,
@@ -462,4 +748,22 @@ pub enum RoomUserToServer {
     ChangeRule(Rule),
     Command(Command),
     Chat(String),
+    Resync,
+    Sit(usize),
+    Stand,
+    Swap(usize),
+    Lock(usize, bool),
+    Ready(bool),
+    SetAutoStart(bool),
+    SetBotFill(bool),
+    Kick(UserNo),
+    TransferHead(UserNo),
+    SetPassword(Option<String>),
+    SetInviteOnly(bool),
+    Invite,
+    Propose(VoteKind),
+    Vote(bool),
+    SetVoteRule(VoteRule),
+    SetSpectatorPolicy(SpectatorPolicy),
+    Coach(UserNo, bool),
 }